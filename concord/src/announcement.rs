@@ -0,0 +1,390 @@
+// Copyright 2022 37 Miners, LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::channel::can_manage;
+use crate::conn_manager::ConnManager;
+use crate::message::publish_message;
+use crate::send;
+use crate::subscription::SubscriptionManager;
+use crate::types::{ChannelIdentifier, ConnectionInfo, Event, EventBody, Message};
+use crate::types::{CrossPostRequest, CrossPostResponse};
+use crate::utils::host_secret;
+use crate::types::{FollowChannelResponse, UnfollowChannelResponse};
+use concordconfig::ConcordConfig;
+use concorddata::concord::{ChannelKey, DSContext};
+use concorddata::types::{Pubkey, ServerId};
+use concorderror::Error;
+use concordutil::librustlet;
+use ed25519_dalek::ExpandedSecretKey;
+use librustlet::nioruntime_log;
+use librustlet::*;
+use nioruntime_log::*;
+use std::sync::{Arc, RwLock};
+
+info!();
+
+fn channel_key(channel_identifier: &ChannelIdentifier) -> ChannelKey {
+	ChannelKey {
+		server_pubkey: channel_identifier.server_pubkey.to_bytes(),
+		server_id: channel_identifier.server_id.to_bytes(),
+		channel_id: channel_identifier.channel_id,
+	}
+}
+
+fn channel_identifier(channel_key: &ChannelKey) -> ChannelIdentifier {
+	ChannelIdentifier {
+		server_pubkey: Pubkey::from_bytes(channel_key.server_pubkey),
+		server_id: ServerId::from_bytes(channel_key.server_id),
+		channel_id: channel_key.channel_id,
+	}
+}
+
+// Following an announcement channel is a two step process. A user that can manage a local
+// channel (target) sends the request to this host, which records that target follows source
+// and forwards the request to the host of source. That host sees the request coming from the
+// host of target and adds target to the followers of source.
+pub fn follow_channel(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
+) -> Result<bool, Error> {
+	let (source, target) = match &event.body {
+		EventBody::FollowChannelRequest(event) => (event.source, event.target),
+		_ => {
+			warn!(
+				"Malformed follow channel event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let requester = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+
+	if requester != pubkey!() && requester == target.server_pubkey.to_bytes() {
+		// the host of target is registering its channel
+		if source.server_pubkey.to_bytes() != pubkey!() {
+			warn!("follow request for a channel on another host: {:?}", source);
+			return Ok(true);
+		}
+		// the source channel must be visible to everyone for another host to follow it
+		let success =
+			ds_context.add_follower(&channel_key(&source), &channel_key(&target), None)?;
+		let event = Event {
+			request_id: event.request_id,
+			body: EventBody::FollowChannelResponse(FollowChannelResponse { success }).into(),
+			..Default::default()
+		};
+		send!(conn_info.handle, event);
+		return Ok(false);
+	}
+
+	if !local_manager(conn_info, ds_context, &target)? {
+		return Ok(true);
+	}
+
+	if source.server_pubkey.to_bytes() == pubkey!() {
+		// the requester must be able to view the source channel
+		let success = ds_context.add_follower(
+			&channel_key(&source),
+			&channel_key(&target),
+			Some(requester),
+		)?;
+		if success {
+			ds_context.add_following(&channel_key(&target), &channel_key(&source))?;
+		}
+		let event = Event {
+			request_id: event.request_id,
+			body: EventBody::FollowChannelResponse(FollowChannelResponse { success }).into(),
+			..Default::default()
+		};
+		send!(conn_info.handle, event);
+	} else {
+		ds_context.add_following(&channel_key(&target), &channel_key(&source))?;
+		forward(conn_info, event, source, conn_manager, config)?;
+	}
+
+	Ok(false)
+}
+
+pub fn unfollow_channel(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
+) -> Result<bool, Error> {
+	let (source, target) = match &event.body {
+		EventBody::UnfollowChannelRequest(event) => (event.source, event.target),
+		_ => {
+			warn!(
+				"Malformed unfollow channel event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let requester = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+
+	if requester != pubkey!() && requester == target.server_pubkey.to_bytes() {
+		ds_context.remove_follower(&channel_key(&source), &channel_key(&target))?;
+		let event = Event {
+			request_id: event.request_id,
+			body: EventBody::UnfollowChannelResponse(UnfollowChannelResponse { success: true })
+				.into(),
+			..Default::default()
+		};
+		send!(conn_info.handle, event);
+		return Ok(false);
+	}
+
+	if !local_manager(conn_info, ds_context, &target)? {
+		return Ok(true);
+	}
+
+	// once this is removed, cross posts from source are rejected even if the host of
+	// source can't be reached.
+	ds_context.remove_following(&channel_key(&target), &channel_key(&source))?;
+
+	if source.server_pubkey.to_bytes() == pubkey!() {
+		ds_context.remove_follower(&channel_key(&source), &channel_key(&target))?;
+		let event = Event {
+			request_id: event.request_id,
+			body: EventBody::UnfollowChannelResponse(UnfollowChannelResponse { success: true })
+				.into(),
+			..Default::default()
+		};
+		send!(conn_info.handle, event);
+	} else {
+		forward(conn_info, event, source, conn_manager, config)?;
+	}
+
+	Ok(false)
+}
+
+// republish an announcement that the host of a followed channel pushed to us.
+pub fn cross_post(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	subscriptions: &SubscriptionManager,
+) -> Result<bool, Error> {
+	let (source, target, message) = match &event.body {
+		EventBody::CrossPostRequest(event) => (event.source, event.target, event.message.clone()),
+		_ => {
+			warn!("Malformed cross post event. No event present: {:?}", event);
+			return Ok(true);
+		}
+	};
+
+	// only the host of the source channel may push its announcements
+	match &conn_info.pubkey {
+		Some(pubkey) => {
+			if pubkey.to_bytes() != source.server_pubkey.to_bytes() {
+				warn!("cross post not sent by the host of {:?}", source);
+				return Ok(true);
+			}
+		}
+		None => return Ok(true),
+	}
+
+	if message.channel_identifier() != source {
+		warn!("cross post for a different channel: {:?}", message);
+		return Ok(true);
+	}
+	let payload = match message.payload() {
+		Ok(payload) => payload,
+		Err(e) => {
+			warn!("invalid cross post signature: {}", e);
+			return Ok(true);
+		}
+	};
+
+	let success = republish(
+		ds_context,
+		subscriptions,
+		&source,
+		&target,
+		&message,
+		payload,
+	)?;
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::CrossPostResponse(CrossPostResponse { success }).into(),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+// push a message that was posted in a local announcement channel to every follower.
+// Followers on this host are published to directly.
+pub fn push_to_followers(
+	ds_context: &DSContext,
+	subscriptions: &SubscriptionManager,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
+	message: &Message,
+	payload: Vec<u8>,
+) -> Result<(), Error> {
+	let source = message.channel_identifier();
+	for follower in ds_context.get_followers(&channel_key(&source))? {
+		let target = channel_identifier(&follower);
+		if follower.server_pubkey == pubkey!() {
+			republish(
+				ds_context,
+				subscriptions,
+				&source,
+				&target,
+				message,
+				payload.clone(),
+			)?;
+			continue;
+		}
+
+		let event = Event {
+			body: EventBody::CrossPostRequest(CrossPostRequest {
+				source,
+				target,
+				message: message.clone(),
+			})
+			.into(),
+			..Default::default()
+		};
+
+		let mut conn_manager = nioruntime_util::lockw!(conn_manager)?;
+		conn_manager.send_event(
+			follower.server_pubkey,
+			event,
+			config.tor_port,
+			Box::pin(move |event| {
+				debug!("cross post to {:?} response: {:?}", target, event);
+				Ok(())
+			}),
+		)?;
+	}
+
+	Ok(())
+}
+
+// post a copy of the message in the local target channel, signed by this host.
+// Returns false if target does not follow source.
+fn republish(
+	ds_context: &DSContext,
+	subscriptions: &SubscriptionManager,
+	source: &ChannelIdentifier,
+	target: &ChannelIdentifier,
+	message: &Message,
+	payload: Vec<u8>,
+) -> Result<bool, Error> {
+	if target.server_pubkey.to_bytes() != pubkey!()
+		|| !ds_context.is_following(&channel_key(target), &channel_key(source))?
+	{
+		warn!("{:?} does not follow {:?}", target, source);
+		return Ok(false);
+	}
+
+	// archived channels are read-only
+	match ds_context.get_channel(
+		target.server_pubkey.to_bytes(),
+		target.server_id.to_bytes(),
+		target.channel_id,
+	)? {
+		Some(channel) => {
+			if channel.archived {
+				info!("not republishing in archived channel {:?}", target);
+				return Ok(false);
+			}
+		}
+		None => return Ok(false),
+	}
+
+	let secret = match host_secret()? {
+		Some(secret) => secret,
+		None => {
+			warn!("no host secret to sign the cross post with");
+			return Ok(false);
+		}
+	};
+	let secret_key = ExpandedSecretKey::from_bytes(&secret[..])?;
+
+	let message = Message::new(
+		*target,
+		payload.clone(),
+		message.message_type(),
+		message.timestamp(),
+		message.nonce(),
+		secret_key,
+	)?;
+	publish_message(ds_context, subscriptions, message, payload)?;
+
+	Ok(true)
+}
+
+// returns true if the target channel is on this host and the user can manage it.
+fn local_manager(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	target: &ChannelIdentifier,
+) -> Result<bool, Error> {
+	if target.server_pubkey.to_bytes() != pubkey!() {
+		warn!("target channel is not on this host: {:?}", target);
+		return Ok(false);
+	}
+	if !can_manage(
+		conn_info,
+		ds_context,
+		target.server_pubkey.to_bytes(),
+		target.server_id.to_bytes(),
+		target.channel_id,
+	)? {
+		info!("not allowed to manage channel {}", target.channel_id);
+		return Ok(false);
+	}
+	Ok(true)
+}
+
+// send a follow or unfollow request on to the host of the source channel and relay its
+// response back to the user.
+fn forward(
+	conn_info: &ConnectionInfo,
+	event: &Event,
+	source: ChannelIdentifier,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
+) -> Result<(), Error> {
+	let mut conn_manager = nioruntime_util::lockw!(conn_manager)?;
+	let handle = conn_info.handle.clone();
+	conn_manager.send_event(
+		source.server_pubkey.to_bytes(),
+		event.clone(),
+		config.tor_port,
+		Box::pin(move |event| {
+			send!(handle, event);
+			Ok(())
+		}),
+	)?;
+	Ok(())
+}
@@ -12,12 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::presence::PresenceManager;
 use crate::types::ConnectionInfo;
-use crate::types::{AuthResponse, Event, EventBody};
+use crate::types::{
+	AuthResponse, ChallengeEvent, Event, EventBody, ListDeviceRevocationsResponse,
+	ListSessionsResponse, RevokeDeviceResponse, RevokeSessionResponse, RotateAuthTokenResponse,
+};
 use crate::{send, try2};
 use concordconfig::ConcordConfig;
 use concorddata::concord::DSContext;
-use concorddata::concord::{AUTH_FLAG_MEMBER, AUTH_FLAG_OWNER, TOKEN_EXPIRATION};
+use concorddata::concord::{session_id, AUTH_FLAG_MEMBER, AUTH_FLAG_OWNER, TOKEN_EXPIRATION};
 use concorddata::types::Pubkey;
 use concorderror::Error as ConcordError;
 use concordutil::librustlet;
@@ -49,15 +53,28 @@ pub fn ws_auth(
 	ds_context: &DSContext,
 	conn_info: Arc<RwLock<HashMap<u128, ConnectionInfo>>>,
 ) -> Result<bool, ConcordError> {
-	let success;
+	let mut success;
 	let id = handle.get_connection_id();
 	let pubkey: Option<Pubkey>;
+	let mut session = None;
 
 	match &event.body {
 		EventBody::AuthEvent(auth_event) => match &auth_event.token.0 {
 			Some(token) => {
 				let token = token.0;
-				success = ds_context.check_ws_auth_token(token)?;
+				// a session token is presented with the pubkey it was issued to, otherwise
+				// this is the token printed on startup.
+				let user_pubkey = match &auth_event.pubkey.0 {
+					Some(user_pubkey) => {
+						success = ds_context.touch_session(user_pubkey.to_bytes(), token)?;
+						session = Some(token);
+						user_pubkey.clone()
+					}
+					None => {
+						success = ds_context.check_ws_auth_token(token)?;
+						Pubkey::from_bytes(pubkey!())
+					}
+				};
 				info!("success={}", success);
 				send!(
 					handle,
@@ -71,15 +88,39 @@ pub fn ws_auth(
 					}
 				);
 				if success {
-					pubkey = Some(Pubkey::from_bytes(pubkey!()));
+					pubkey = Some(user_pubkey);
 				} else {
 					debug!("return true");
 					return Ok(true);
 				}
 			}
 			None => {
-				let message = format!("{}", handle.get_connection_id());
-				let message = message.as_bytes();
+				// the challenge sent to this connection is single use and must not have expired
+				let challenge = {
+					let mut conn_info = nioruntime_util::lockw!(conn_info)?;
+					match conn_info.get_mut(&id) {
+						Some(info) => info.challenge.take(),
+						None => None,
+					}
+				};
+				let challenge = match challenge {
+					Some(challenge) => ds_context.consume_challenge(challenge, pubkey!())?,
+					None => None,
+				};
+				let challenge = match challenge {
+					Some(challenge) => challenge,
+					None => {
+						warn!("no valid challenge for connection {}", id);
+						return Ok(true);
+					}
+				};
+				let message = ChallengeEvent {
+					challenge: challenge.challenge,
+					server_pubkey: Pubkey::from_bytes(challenge.server_pubkey),
+					timestamp: challenge.timestamp,
+				}
+				.message();
+				let message = &message[..];
 				let spec_pubkey = match &auth_event.pubkey.0 {
 					Some(pubkey) => pubkey,
 					None => {
@@ -97,6 +138,21 @@ pub fn ws_auth(
 				};
 
 				success = verify!(message, spec_pubkey.to_bytes(), signature.0).unwrap_or(false);
+
+				// a linked device signs with its own key but is authenticated as the user
+				// that certified it, unless that user has revoked it.
+				let user_pubkey = match &auth_event.device_certificate.0 {
+					Some(device_certificate) => {
+						if device_certificate.device_pubkey != *spec_pubkey
+							|| !ds_context.check_device_certificate(device_certificate)?
+						{
+							warn!("invalid device certificate: {:?}", device_certificate);
+							success = false;
+						}
+						device_certificate.user_pubkey
+					}
+					None => *spec_pubkey,
+				};
 				info!("success w/sig={}", success);
 				info!(
 					"message={:?},spec_pubkey={:?},signature={:?}",
@@ -115,7 +171,7 @@ pub fn ws_auth(
 					}
 				);
 				if success {
-					pubkey = Some((*spec_pubkey).clone());
+					pubkey = Some(user_pubkey);
 				} else {
 					return Ok(true);
 				}
@@ -133,6 +189,7 @@ pub fn ws_auth(
 		match info {
 			Some(mut info) => {
 				info.pubkey = pubkey;
+				info.session = session;
 			}
 			None => {
 				// already closed.
@@ -143,24 +200,195 @@ pub fn ws_auth(
 	Ok(!success)
 }
 
-// initialize this module. Create rustlets, log info, open browser.
-pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
-	let uri = format!("{}:{}", cconfig.host, cconfig.port);
+pub fn list_sessions(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+
+	let sessions = ds_context.list_sessions(user_pubkey)?;
+	let current_session = conn_info
+		.session
+		.map(|token| session_id(token))
+		.unwrap_or(0);
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::ListSessionsResponse(ListSessionsResponse {
+			current_session,
+			sessions,
+		}),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
 
-	let ds_context = DSContext::new(cconfig.root_dir.clone())?;
-	let auth_token: u128 = rand::random(); // generate a 128 bit auth token.
+// revoke one of the user's sessions and close any connection that is still using it.
+pub fn revoke_session(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	presence: &PresenceManager,
+) -> Result<bool, ConcordError> {
+	let session_id = match &event.body {
+		EventBody::RevokeSessionRequest(event) => event.session_id,
+		_ => {
+			warn!("Malformed event in revoke_session. Event = {:?}", event);
+			return Ok(true);
+		}
+	};
+
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+
+	let token = ds_context.revoke_session(user_pubkey, session_id)?;
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::RevokeSessionResponse(RevokeSessionResponse {
+			success: token.is_some(),
+		}),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	match token {
+		Some(token) => {
+			for handle in presence.session_connections(token)? {
+				match handle.get_wh().close() {
+					Ok(_) => {}
+					Err(e) => warn!("error closing revoked session connection: {}", e),
+				}
+			}
+		}
+		None => {}
+	}
 
-	// save the auth token for ws_auth
-	try2!(
-		ds_context.save_ws_auth_token(auth_token),
-		"save auth token error"
-	);
+	Ok(false)
+}
+
+// record a revocation of one of a user's linked devices. The revocation is signed by the user's
+// primary key so it does not need to come from the user.
+pub fn revoke_device(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let revocation = match &event.body {
+		EventBody::RevokeDeviceRequest(event) => &event.revocation,
+		_ => {
+			warn!("Malformed event in revoke_device. Event = {:?}", event);
+			return Ok(true);
+		}
+	};
+
+	let success = match ds_context.revoke_device(revocation) {
+		Ok(_) => true,
+		Err(e) => {
+			warn!("device revocation rejected: {}", e);
+			false
+		}
+	};
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::RevokeDeviceResponse(RevokeDeviceResponse { success }),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn list_device_revocations(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let user_pubkey = match &event.body {
+		EventBody::ListDeviceRevocationsRequest(event) => event.user_pubkey.to_bytes(),
+		_ => {
+			warn!(
+				"Malformed event in list_device_revocations. Event = {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let revocations = ds_context.list_device_revocations(user_pubkey)?;
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::ListDeviceRevocationsResponse(ListDeviceRevocationsResponse {
+			revocations,
+		}),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+// replace the instance owner auth token with a new one and revoke the sessions created with it.
+// Only the instance owner may do this.
+pub fn rotate_auth_token(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	config: &ConcordConfig,
+	presence: &PresenceManager,
+) -> Result<bool, ConcordError> {
+	let is_owner = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes() == pubkey!(),
+		None => false,
+	};
+
+	let (success, token, revoked) = if is_owner {
+		let token: u128 = rand::random();
+		ds_context.save_ws_auth_token(token, config.hash_auth_token)?;
+		let revoked = ds_context.revoke_owner_sessions(pubkey!())?;
+		let uri = format!("{}:{}", config.host, config.port);
+		log_auth_token(&uri, token, config.print_auth_token)?;
+		(true, token, revoked)
+	} else {
+		(false, 0, vec![])
+	};
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::RotateAuthTokenResponse(RotateAuthTokenResponse { success, token }),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	for token in revoked {
+		for handle in presence.session_connections(token)? {
+			match handle.get_wh().close() {
+				Ok(_) => {}
+				Err(e) => warn!("error closing revoked session connection: {}", e),
+			}
+		}
+	}
+
+	Ok(!success)
+}
 
+// write the auth url and token to the main log and, if print_stdout is set, to stdout.
+fn log_auth_token(uri: &str, auth_token: u128, print_stdout: bool) -> Result<(), ConcordError> {
 	let mut config = get_config_multi!(MAIN_LOG)?;
 
-	// print auth token to stdout as well as log
+	// print auth token to stdout (if configured) as well as log
 	let prev_show_stdout = config.show_stdout;
-	config.show_stdout = true;
+	config.show_stdout = print_stdout;
 	log_config_multi!(MAIN_LOG, config.clone())?;
 
 	log_no_ts_multi!(
@@ -188,11 +416,41 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 	config.show_stdout = prev_show_stdout;
 	log_config_multi!(MAIN_LOG, config)?;
 
+	Ok(())
+}
+
+// initialize this module. Create rustlets, log info, open browser.
+pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
+	let uri = format!("{}:{}", cconfig.host, cconfig.port);
+
+	let ds_context = DSContext::new(cconfig.root_dir.clone())?;
+	let auth_token: u128 = rand::random(); // generate a 128 bit auth token.
+
+	// save the auth token for ws_auth. This invalidates the tokens of previous runs.
+	try2!(
+		ds_context.save_ws_auth_token(auth_token, cconfig.hash_auth_token),
+		"save auth token error"
+	);
+
+	log_auth_token(&uri, auth_token, cconfig.print_auth_token)?;
+
 	// auth on this concord instance
 	rustlet!("auth", {
 		let token = query!("token").unwrap_or("".to_string());
 
-		if token.parse().unwrap_or(0) == auth_token.clone() {
+		let valid = ds_context
+			.check_ws_auth_token(token.parse().unwrap_or(0))
+			.map_err(|e| {
+				let error: Error = ErrorKind::ApplicationError(format!(
+					"Error checking auth token: {}",
+					e.to_string()
+				))
+				.into();
+				error
+			})?;
+
+		if valid {
+			let device_label = query!("device").unwrap_or("".to_string());
 			let user_pubkey = pubkey!();
 			let challenge = ds_context.create_auth_challenge(user_pubkey).map_err(|e| {
 				let error: Error = ErrorKind::ApplicationError(format!(
@@ -206,9 +464,10 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 				.validate_challenge(
 					user_pubkey,
 					user_pubkey,
-					challenge,
-					u128::MAX, // never expire
+					challenge.challenge,
+					TOKEN_EXPIRATION,
 					AUTH_FLAG_OWNER | AUTH_FLAG_MEMBER,
+					device_label,
 				)
 				.map_err(|e| {
 					let error: Error =
@@ -219,7 +478,8 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 
 			match token {
 				Some(token) => {
-					set_cookie!("auth", &token, "Expires=Fri, 01 Jan 2100 01:00:00 GMT;");
+					// the session slides on use, so the cookie lasts as long as the browser
+					set_cookie!("auth", &token, "");
 				}
 				None => {}
 			}
@@ -232,11 +492,7 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 	let ds_context = DSContext::new(cconfig.root_dir.clone())?;
 
 	rustlet!("get_challenge", {
-		let user_pubkey = query!("user_pubkey").unwrap_or("".to_string());
-		let user_pubkey = urlencoding::decode(&user_pubkey)?;
-		let user_pubkey = base64::decode(&*user_pubkey)?;
-		let user_pubkey: [u8; 32] = user_pubkey.as_slice().try_into()?;
-		let challenge = ds_context.create_auth_challenge(user_pubkey).map_err(|e| {
+		let challenge = ds_context.create_auth_challenge(pubkey!()).map_err(|e| {
 			let error: Error = ErrorKind::ApplicationError(format!(
 				"create auth challenge error: {}",
 				e.to_string()
@@ -245,7 +501,7 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 			error
 		})?;
 
-		let challenge = base64::encode(challenge);
+		let challenge = base64::encode(challenge.challenge);
 		let challenge = urlencoding::encode(&challenge).to_string();
 
 		let challenge = ChallengeResponse { challenge };
@@ -270,13 +526,15 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 		let challenge = query!("challenge").unwrap_or("".to_string());
 		let challenge = urlencoding::decode(&challenge)?;
 		let challenge = base64::decode(&*challenge)?;
-		let challenge: [u8; 8] = challenge.as_slice().try_into()?;
+		let challenge: [u8; 32] = challenge.as_slice().try_into()?;
 
 		let signature = query!("signature").unwrap_or("".to_string());
 		let signature = urlencoding::decode(&signature)?;
 		let signature = base64::decode(&*signature)?;
 		let signature: [u8; 64] = signature.as_slice().try_into()?;
 
+		let device_label = query!("device").unwrap_or("".to_string());
+
 		let verification = verify!(&challenge, user_pubkey, signature);
 		let verification = verification.unwrap_or(false);
 
@@ -288,6 +546,7 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 					challenge,
 					TOKEN_EXPIRATION,
 					AUTH_FLAG_MEMBER,
+					device_label,
 				)
 				.map_err(|e| {
 					let error: Error = ErrorKind::ApplicationError(format!(
@@ -337,6 +596,19 @@ pub fn init_auth(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
 			}
 		}
 
+		// expired and used up invites
+		match ds_context.purge_invites() {
+			Ok(_) => {}
+			Err(e) => {
+				log_multi!(
+					ERROR,
+					MAIN_LOG,
+					"Invite purge generated error: {}",
+					e.to_string(),
+				);
+			}
+		}
+
 		std::thread::sleep(std::time::Duration::from_millis(1000 * 60 * 5));
 	});
 
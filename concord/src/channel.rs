@@ -13,11 +13,19 @@
 // limitations under the License.
 
 use crate::types::{
-	AddChannelResponse, DeleteChannelResponse, GetChannelsResponse, ModifyChannelResponse,
+	AddCategoryResponse, DeleteCategoryResponse, ModifyCategoryResponse, ReorderChannelsResponse,
 };
-use crate::types::{Channel, ConnectionInfo, Event, EventBody};
-use crate::{member, send};
+use crate::types::{
+	AddChannelResponse, ArchiveChannelResponse, DeleteChannelResponse, GetChannelsResponse,
+	ModifyChannelResponse, SetChannelKindResponse, SetChannelPermissionsResponse,
+};
+use crate::types::{Category, Channel, ConnectionInfo, Event, EventBody};
+use crate::{member, owner, send};
 use concorddata::concord::DSContext;
+use concorddata::concord::{
+	CHANNEL_KIND_ANNOUNCEMENT, CHANNEL_KIND_TEXT, CHANNEL_PERMISSION_MANAGE,
+	CHANNEL_PERMISSION_VIEW,
+};
 use concorddata::types::{Pubkey, ServerId};
 use concorderror::Error as ConcordError;
 use concordutil::librustlet;
@@ -34,6 +42,22 @@ struct ChannelInfo {
 	id: String,
 }
 
+pub fn can_manage(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	server_pubkey: [u8; 32],
+	server_id: [u8; 8],
+	channel_id: u64,
+) -> Result<bool, ConcordError> {
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(false),
+	};
+	let permissions =
+		ds_context.channel_permissions(server_pubkey, server_id, channel_id, user_pubkey)?;
+	Ok(permissions & CHANNEL_PERMISSION_MANAGE != 0)
+}
+
 pub fn add_channel(
 	conn_info: &ConnectionInfo,
 	ds_context: &DSContext,
@@ -53,6 +77,8 @@ pub fn add_channel(
 		}
 	};
 
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
 	let channel_id = ds_context.add_channel(server_id, server_pubkey, name, description)?;
 
 	let event = Event {
@@ -93,6 +119,11 @@ pub fn modify_channel(
 		}
 	};
 
+	if !can_manage(conn_info, ds_context, server_pubkey, server_id, channel_id)? {
+		info!("not allowed to manage channel {}", channel_id);
+		return Ok(true);
+	}
+
 	ds_context.modify_channel(server_id, server_pubkey, channel_id, name, description)?;
 
 	let event = Event {
@@ -127,6 +158,11 @@ pub fn delete_channel(
 		}
 	};
 
+	if !can_manage(conn_info, ds_context, server_pubkey, server_id, channel_id)? {
+		info!("not allowed to manage channel {}", channel_id);
+		return Ok(true);
+	}
+
 	ds_context.delete_channel(server_id, server_pubkey, channel_id)?;
 
 	let event = Event {
@@ -148,23 +184,58 @@ pub fn get_channels(
 	member!(conn_info, ds_context);
 
 	let request_id = event.request_id;
-	let (server_id, server_pubkey) = match &event.body {
-		EventBody::GetChannelsRequest(event) => {
-			(event.server_id.to_bytes(), event.server_pubkey.to_bytes())
-		}
+	let (server_id, server_pubkey, include_archived) = match &event.body {
+		EventBody::GetChannelsRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.include_archived,
+		),
 		_ => {
 			warn!("Malformed get channel event. No event present: {:?}", event);
 			return Ok(true);
 		}
 	};
 
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+	let roles = ds_context
+		.get_server_member(server_pubkey, server_id, user_pubkey)?
+		.map(|m| m.roles);
+
 	let channels = ds_context.get_channels(server_pubkey, server_id)?;
 	let mut channels_event = vec![];
 	for channel in channels {
+		if channel.archived && !include_archived {
+			continue;
+		}
+		// private channels are only listed for users that can view them
+		let permissions = channel.permissions(roles, user_pubkey);
+		if permissions & CHANNEL_PERMISSION_VIEW == 0 {
+			continue;
+		}
 		channels_event.push(Channel {
 			name: channel.name.into(),
 			description: channel.description.into(),
 			channel_id: channel.channel_id,
+			position: channel.position,
+			category_id: channel.category_id,
+			overwrites: channel.overwrites,
+			permissions,
+			kind: channel.kind,
+			post_roles: channel.post_roles,
+			archived: channel.archived,
+		});
+	}
+
+	let categories = ds_context.get_categories(server_pubkey, server_id)?;
+	let mut categories_event = vec![];
+	for category in categories {
+		categories_event.push(Category {
+			name: category.name.into(),
+			category_id: category.category_id,
+			position: category.position,
 		});
 	}
 
@@ -174,6 +245,7 @@ pub fn get_channels(
 			channels: channels_event,
 			server_id: ServerId::from_bytes(server_id),
 			server_pubkey: Pubkey::from_bytes(server_pubkey),
+			categories: categories_event,
 		})
 		.into(),
 		..Default::default()
@@ -183,3 +255,287 @@ pub fn get_channels(
 
 	Ok(false)
 }
+
+pub fn add_category(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, name) = match &event.body {
+		EventBody::AddCategoryRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.name.to_string(),
+		),
+		_ => {
+			warn!(
+				"Malformed add category event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
+	let category_id = ds_context.add_category(server_pubkey, server_id, name)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::AddCategoryResponse(AddCategoryResponse {
+			category_id,
+			success: true,
+		})
+		.into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn modify_category(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, category_id, name) = match &event.body {
+		EventBody::ModifyCategoryRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.category_id,
+			event.name.to_string(),
+		),
+		_ => {
+			warn!(
+				"Malformed modify category event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
+	let success = ds_context.modify_category(server_pubkey, server_id, category_id, name)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::ModifyCategoryResponse(ModifyCategoryResponse { success }).into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn delete_category(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, category_id) = match &event.body {
+		EventBody::DeleteCategoryRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.category_id,
+		),
+		_ => {
+			warn!(
+				"Malformed delete category event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
+	ds_context.delete_category(server_pubkey, server_id, category_id)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::DeleteCategoryResponse(DeleteCategoryResponse { success: true }).into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn reorder_channels(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, categories, channels) = match &event.body {
+		EventBody::ReorderChannelsRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.categories.clone(),
+			event
+				.channels
+				.iter()
+				.map(|c| (c.channel_id, c.category_id))
+				.collect::<Vec<(u64, u64)>>(),
+		),
+		_ => {
+			warn!(
+				"Malformed reorder channels event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
+	let success = ds_context.reorder_channels(server_pubkey, server_id, categories, channels)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::ReorderChannelsResponse(ReorderChannelsResponse { success }).into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn set_channel_permissions(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, channel_id, overwrites) = match &event.body {
+		EventBody::SetChannelPermissionsRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.channel_id,
+			event.overwrites.clone(),
+		),
+		_ => {
+			warn!(
+				"Malformed set channel permissions event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	if !can_manage(conn_info, ds_context, server_pubkey, server_id, channel_id)? {
+		info!("not allowed to manage channel {}", channel_id);
+		return Ok(true);
+	}
+
+	let success =
+		ds_context.set_channel_overwrites(server_pubkey, server_id, channel_id, overwrites)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::SetChannelPermissionsResponse(SetChannelPermissionsResponse { success })
+			.into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn set_channel_kind(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, channel_id, kind, post_roles) = match &event.body {
+		EventBody::SetChannelKindRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.channel_id,
+			event.kind,
+			event.post_roles,
+		),
+		_ => {
+			warn!(
+				"Malformed set channel kind event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	if kind != CHANNEL_KIND_TEXT && kind != CHANNEL_KIND_ANNOUNCEMENT {
+		warn!("unknown channel kind: {}", kind);
+		return Ok(true);
+	}
+
+	if !can_manage(conn_info, ds_context, server_pubkey, server_id, channel_id)? {
+		info!("not allowed to manage channel {}", channel_id);
+		return Ok(true);
+	}
+
+	let success =
+		ds_context.set_channel_kind(server_pubkey, server_id, channel_id, kind, post_roles)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::SetChannelKindResponse(SetChannelKindResponse { success }).into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn archive_channel(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, channel_id, archived) = match &event.body {
+		EventBody::ArchiveChannelRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.channel_id,
+			event.archived,
+		),
+		_ => {
+			warn!(
+				"Malformed archive channel event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	if !can_manage(conn_info, ds_context, server_pubkey, server_id, channel_id)? {
+		info!("not allowed to manage channel {}", channel_id);
+		return Ok(true);
+	}
+
+	let success =
+		ds_context.set_channel_archived(server_pubkey, server_id, channel_id, archived)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::ArchiveChannelResponse(ArchiveChannelResponse { success }).into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
@@ -16,7 +16,7 @@ use crate::librustlet::nioruntime_http::{build_messages, WebSocketMessageType};
 use crate::librustlet::WebSocketMessage;
 use crate::types::{AuthEvent, Event, EventBody};
 use concorddata::ser::serialize_default;
-use concorddata::types::{Pubkey, Signature, U128};
+use concorddata::types::{DeviceCertificate, Pubkey, Signature, U128};
 use concorderror::{Error, ErrorKind};
 use concordutil::nioruntime_log;
 use ed25519_dalek::ExpandedSecretKey;
@@ -25,11 +25,20 @@ use nioruntime_log::*;
 use std::io::prelude::*;
 use std::net::*;
 use std::pin::Pin;
-use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use tor_stream::TorStream;
 
 debug!();
 
+// a ping is sent when nothing has been written for this long.
+const HEARTBEAT_INTERVAL_MILLIS: u64 = 30_000;
+// the connection is considered dead when nothing has been read for this long.
+const HEARTBEAT_TIMEOUT_MILLIS: u64 = 90_000;
+
 //trait Callback: for<'r, 's> Fn(&'r Event, &'s WSListenerClientWriter) -> Result<(), Error> + Send + Sync + Unpin + 'static {}
 //trait ErrHandler: for<'r, 's> Fn(Error, String) -> Result<(), Error> + Send + Sync + Unpin + 'static {}
 
@@ -67,6 +76,8 @@ impl WSListenerClientWriter {
 pub enum AuthParams {
 	Token(String),
 	Secret([u8; 64]),
+	// the secret of a linked device and the certificate the user's primary key issued for it
+	Device([u8; 64], DeviceCertificate),
 }
 
 /// Websocket listener client for concord. This struct can be used to communicate with
@@ -155,6 +166,10 @@ pub struct WSListenerClient<Callback, ErrHandler> {
 	tor_proxy_port: u16,
 	sender: Option<SyncSender<Option<Event>>>,
 	auth_params: AuthParams,
+	// used by close to shut the socket down
+	stream: Option<TcpStream>,
+	// the read and write threads, joined by close
+	threads: Mutex<Vec<JoinHandle<()>>>,
 }
 
 //Fn(Error, String) -> Result<(), Error> + Send + 'static + Clone + Sync + Unpin
@@ -180,6 +195,8 @@ where
 			tor_proxy_port,
 			sender: None,
 			auth_params,
+			stream: None,
+			threads: Mutex::new(vec![]),
 		}
 	}
 
@@ -240,6 +257,10 @@ where
 		let error_clone2 = error.clone();
 		let error_clone3 = error.clone();
 
+		// time of the last read, shared with the write thread to detect dead circuits
+		let last_read = Arc::new(AtomicU64::new(now_millis()));
+		let last_read_clone = last_read.clone();
+
 		let sec_value_base64 = base64::encode(sec_value);
 		let proxy_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), tor_proxy_port);
 		let target: socks::TargetAddr = socks::TargetAddr::Domain(onion.clone(), 80);
@@ -257,6 +278,7 @@ Sec-WebSocket-Key: {}\
 		)?;
 
 		let stream = stream.into_inner();
+		self.stream = Some(stream.try_clone()?);
 		let stream_clone = match stream.try_clone() {
 			Ok(stream) => stream,
 			Err(e) => {
@@ -266,7 +288,7 @@ Sec-WebSocket-Key: {}\
 
 		let onion_clone = onion.clone();
 		// start read thread
-		std::thread::spawn(move || {
+		let read_thread = std::thread::spawn(move || {
 			let stream = match stream.try_clone() {
 				Ok(stream) => stream,
 				Err(e) => {
@@ -293,7 +315,13 @@ Sec-WebSocket-Key: {}\
 					return;
 				}
 			};
-			match Self::do_proxy_read_loop(stream, &callback, auth_params.clone(), sender) {
+			match Self::do_proxy_read_loop(
+				stream,
+				&callback,
+				auth_params.clone(),
+				sender,
+				last_read_clone,
+			) {
 				Ok(_) => {}
 				Err(e) => {
 					// also ensure we close the stream so writer thread ends
@@ -312,7 +340,7 @@ Sec-WebSocket-Key: {}\
 
 		let onion_clone = onion.clone();
 		// start write thread
-		std::thread::spawn(move || {
+		let write_thread = std::thread::spawn(move || {
 			let stream = match stream_clone.try_clone() {
 				Ok(stream) => stream,
 				Err(e) => {
@@ -326,7 +354,13 @@ Sec-WebSocket-Key: {}\
 					return;
 				}
 			};
-			match Self::do_proxy_write_loop(&stream, receiver, &error_clone, onion_clone.clone()) {
+			match Self::do_proxy_write_loop(
+				&stream,
+				receiver,
+				&error_clone,
+				onion_clone.clone(),
+				last_read,
+			) {
 				Ok(_) => {}
 				Err(e) => {
 					// also ensure we close the stream so read thread ends
@@ -343,6 +377,14 @@ Sec-WebSocket-Key: {}\
 			}
 		});
 
+		match self.threads.lock() {
+			Ok(mut threads) => {
+				threads.push(read_thread);
+				threads.push(write_thread);
+			}
+			Err(e) => return Err(ErrorKind::LockError(e.to_string()).into()),
+		}
+
 		Ok(())
 	}
 
@@ -360,8 +402,47 @@ Sec-WebSocket-Key: {}\
 		}
 	}
 
-	/// Close the WSListenerClient freeing all it's resources.
+	/// Close the WSListenerClient freeing all it's resources. This returns once the read and
+	/// write threads have exited, unless it is called from one of them, as is the case in the
+	/// callback closure.
 	pub fn close(&self) -> Result<(), Error> {
+		match self.sender.as_ref() {
+			Some(sender) => {
+				// None is used to indicate shutdown. If the channel is full or the write
+				// thread is already gone, shutting the socket down below ends it.
+				let _ = sender.try_send(None);
+			}
+			None => {
+				// never started
+				return Ok(());
+			}
+		}
+
+		match self.stream.as_ref() {
+			Some(stream) => {
+				// fully shutdown both send/receive side of the socket.
+				let _ = stream.shutdown(std::net::Shutdown::Both);
+			}
+			None => {}
+		}
+
+		let threads: Vec<JoinHandle<()>> = match self.threads.lock() {
+			Ok(mut threads) => threads.drain(..).collect(),
+			Err(e) => return Err(ErrorKind::LockError(e.to_string()).into()),
+		};
+
+		let current = std::thread::current().id();
+		for thread in threads {
+			if thread.thread().id() == current {
+				continue;
+			}
+			match thread.join() {
+				Ok(_) => {}
+				Err(_) => error!("client thread for {} panicked", self.onion),
+			}
+		}
+
+		info!("client for {} closed", self.onion);
 		Ok(())
 	}
 
@@ -370,17 +451,41 @@ Sec-WebSocket-Key: {}\
 		receiver: Receiver<Option<Event>>,
 		error: &Pin<Box<ErrHandler>>,
 		onion: String,
+		last_read: Arc<AtomicU64>,
 	) -> Result<(), Error> {
 		let mut auth = false;
 		let mut pending = vec![];
 
 		loop {
-			let event = receiver.recv()?;
+			let event = match receiver
+				.recv_timeout(Duration::from_millis(HEARTBEAT_INTERVAL_MILLIS))
+			{
+				Ok(event) => event,
+				Err(RecvTimeoutError::Timeout) => {
+					// the server answers pings, so silence means the circuit is gone
+					let idle = now_millis().saturating_sub(last_read.load(Ordering::SeqCst));
+					if idle > HEARTBEAT_TIMEOUT_MILLIS {
+						return Err(ErrorKind::Disconnect(format!(
+							"nothing received for {} ms",
+							idle
+						))
+						.into());
+					}
+					Self::process_ping_write(stream)?;
+					continue;
+				}
+				Err(RecvTimeoutError::Disconnected) => {
+					return Err(
+						ErrorKind::Disconnect("event channel disconnected".to_string()).into(),
+					);
+				}
+			};
 
 			let event = match event {
 				Some(event) => event,
 				None => {
-					// shutdown thread
+					// shutdown thread, closing the socket so that the read thread ends too
+					let _ = stream.shutdown(std::net::Shutdown::Both);
 					break;
 				}
 			};
@@ -435,11 +540,24 @@ Sec-WebSocket-Key: {}\
 		Ok(())
 	}
 
+	fn process_ping_write(mut stream: &TcpStream) -> Result<(), Error> {
+		let wsm: WebSocketMessage = WebSocketMessage {
+			mtype: WebSocketMessageType::Ping,
+			payload: vec![],
+			mask: false,
+			header_info: None,
+		};
+		let bin_data: Vec<u8> = wsm.into();
+		stream.write(&bin_data)?;
+		Ok(())
+	}
+
 	fn do_proxy_read_loop(
 		mut stream: TcpStream,
 		callback: &Pin<Box<Callback>>,
 		auth_params: AuthParams,
 		sender: SyncSender<Option<Event>>,
+		last_read: Arc<AtomicU64>,
 	) -> Result<(), Error> {
 		let mut buf = Self::skip_headers(&mut stream)?;
 		let mut rbuf = [0u8; 4096];
@@ -452,6 +570,7 @@ Sec-WebSocket-Key: {}\
 			if len == 0 {
 				break;
 			}
+			last_read.store(now_millis(), Ordering::SeqCst);
 			buf.append(&mut rbuf[0..len].to_vec());
 		}
 
@@ -489,15 +608,14 @@ Sec-WebSocket-Key: {}\
 							};
 
 							let (pubkey, signature) = match auth_params {
-								AuthParams::Secret(secret_bytes) => {
+								AuthParams::Secret(secret_bytes)
+								| AuthParams::Device(secret_bytes, _) => {
 									let secret_key =
 										ExpandedSecretKey::from_bytes(&secret_bytes[..])?;
 									let pubkey: PublicKey = (&secret_key).into();
 
-									let challenge_value = challenge.challenge;
-									let message = format!("{}", challenge_value);
-									let message = message.as_bytes();
-									let signature = secret_key.sign(message, &pubkey);
+									let message = challenge.message();
+									let signature = secret_key.sign(&message, &pubkey);
 
 									let pubkey = Pubkey::from_bytes(*pubkey.as_bytes());
 
@@ -508,11 +626,19 @@ Sec-WebSocket-Key: {}\
 								_ => (None.into(), None.into()),
 							};
 
+							let device_certificate = match auth_params {
+								AuthParams::Device(_, device_certificate) => {
+									Some(device_certificate.clone()).into()
+								}
+								_ => None.into(),
+							};
+
 							let auth_event = Event {
 								body: EventBody::AuthEvent(AuthEvent {
 									pubkey,
 									signature,
 									token,
+									device_certificate,
 								}),
 								..Default::default()
 							};
@@ -561,3 +687,10 @@ Sec-WebSocket-Key: {}\
 		Ok((&buf[end..len]).to_vec())
 	}
 }
+
+fn now_millis() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.unwrap_or(Duration::from_millis(0))
+		.as_millis() as u64
+}
@@ -433,7 +433,9 @@ fn init_webroot(config: &ConcordConfig) {
 // We initialize concord here.
 pub fn concord_init(config: ConcordConfig) -> Result<(), ConcordError> {
 	init_webroot(&config); // setup webroot
+	crate::utils::set_host_secret(config.secret)?; // secret unlocked in memory
 	crate::auth::init_auth(&config)?; // auth module
+	crate::server::init_server(&config)?; // server module
 	crate::ws::init_ws(config)?; // websocket module
 							 //crate::server::init_server(&config, context.clone())?; // server module
 							 //crate::message::init_message(&config, context.clone())?; // message module
@@ -16,37 +16,138 @@ use crate::client::AuthParams;
 use crate::client::WSListenerClient;
 use crate::librustlet;
 use crate::types::Event;
+use crate::utils::host_secret;
 use concorddata::types::Pubkey;
 use concorderror::Error;
 use concordutil::nioruntime_log;
 use librustlet::*;
 use nioruntime_log::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 debug!();
 
+// number of consecutive failed connections to a server before its requests are failed.
+const MAX_RECONNECT_ATTEMPTS: u32 = 6;
+// delay before the first reconnect. It doubles with each failed attempt.
+const RECONNECT_BASE_DELAY_MILLIS: u64 = 1_000;
+// how long send_event waits for a response before failing the request.
+pub const DEFAULT_REQUEST_TIMEOUT_MILLIS: u64 = 60_000;
+// how often expired requests and idle connections are looked for.
+const EXPIRE_INTERVAL_MILLIS: u64 = 1_000;
+// connections that have not been used for this long are closed.
+const IDLE_TIMEOUT_MILLIS: u128 = 1000 * 60 * 5;
+// the most outbound connections open at once. The least recently used idle connection is
+// closed to make room for a new one.
+const MAX_OUTBOUND_CONNECTIONS: usize = 32;
+
+// called with the response to a request.
+type ResponseCallback =
+	Pin<Box<dyn Fn(&Event) -> Result<(), Error> + Send + 'static + Sync + Unpin>>;
+// called if a request can't be delivered or is not answered in time.
+type FailureCallback =
+	Pin<Box<dyn Fn(&Error) -> Result<(), Error> + Send + 'static + Sync + Unpin>>;
+
+// shuts the client of a connection down, see WSListenerClient::close.
+type Closer = Box<dyn Fn() -> Result<(), Error> + Send + Sync>;
+
+// an open connection to a remote server.
+struct Connection {
+	id: u64,
+	sender: SyncSender<Option<Event>>,
+	// time in millis the connection was last used to send a request
+	last_used: u128,
+	handle: ClientHandle,
+}
+
+// lets a connection be shut down from outside of its listener thread.
+#[derive(Clone)]
+struct ClientHandle {
+	// set by the listener thread once the client is started
+	closer: Arc<RwLock<Option<Closer>>>,
+	// set when the connection is shut down, possibly before the client is started
+	closing: Arc<AtomicBool>,
+}
+
+impl ClientHandle {
+	fn new() -> Self {
+		Self {
+			closer: Arc::new(RwLock::new(None)),
+			closing: Arc::new(AtomicBool::new(false)),
+		}
+	}
+
+	// called by the listener thread once the client is started. If the connection was shut
+	// down in the meantime, the client is closed right away.
+	fn set_closer(&self, closer: Closer) -> Result<(), Error> {
+		{
+			let mut current = nioruntime_util::lockw!(self.closer)?;
+			*current = Some(closer);
+		}
+		if self.closing.load(Ordering::SeqCst) {
+			self.shutdown()?;
+		}
+		Ok(())
+	}
+
+	// close the client, or have it closed as soon as it is started.
+	fn shutdown(&self) -> Result<(), Error> {
+		self.closing.store(true, Ordering::SeqCst);
+		let closer = nioruntime_util::lockr!(self.closer)?;
+		match closer.as_ref() {
+			Some(closer) => closer(),
+			None => Ok(()),
+		}
+	}
+}
+
+// a request sent to a remote server that has not been answered yet. The event is sent with
+// a request_id chosen by the ConnManager, so that requests from different callers can't
+// collide. The response is passed to the callback with the request_id of the caller.
+struct PendingRequest {
+	server_pubkey: [u8; 32],
+	// request_id of the event as given by the caller
+	request_id: u32,
+	event: Event,
+	callback: ResponseCallback,
+	failure: FailureCallback,
+	// id of the connection the request was written to, None if it still has to be sent
+	sent_on: Option<u64>,
+	// time in millis after which the request fails if it is not answered
+	deadline: u128,
+}
+
 #[derive(Clone)]
 pub struct ConnManager {
-	map: Arc<RwLock<HashMap<[u8; 32], SyncSender<Option<Event>>>>>,
-	callbacks: Arc<
-		RwLock<
-			HashMap<
-				u32,
-				Pin<Box<dyn Fn(&Event) -> Result<(), Error> + Send + 'static + Sync + Unpin>>,
-			>,
-		>,
-	>,
+	// the open connection to each server
+	map: Arc<RwLock<HashMap<[u8; 32], Connection>>>,
+	pending: Arc<RwLock<HashMap<u32, PendingRequest>>>,
 }
 
 impl ConnManager {
 	pub fn new() -> Self {
-		ConnManager {
-			map: Arc::new(RwLock::new(HashMap::new())),
-			callbacks: Arc::new(RwLock::new(HashMap::new())),
-		}
+		let pending = Arc::new(RwLock::new(HashMap::new()));
+		let map = Arc::new(RwLock::new(HashMap::new()));
+
+		let pending_clone = pending.clone();
+		let map_clone = map.clone();
+		std::thread::spawn(move || loop {
+			std::thread::sleep(Duration::from_millis(EXPIRE_INTERVAL_MILLIS));
+			match Self::expire(&pending_clone) {
+				Ok(_) => {}
+				Err(e) => error!("error expiring requests: {}", e),
+			}
+			match Self::close_idle(&map_clone, &pending_clone) {
+				Ok(_) => {}
+				Err(e) => error!("error closing idle connections: {}", e),
+			}
+		});
+
+		ConnManager { map, pending }
 	}
 
 	pub fn send_event(
@@ -54,53 +155,189 @@ impl ConnManager {
 		server_pubkey: [u8; 32],
 		event: Event,
 		tor_port: u16,
-		callback: Pin<Box<dyn Fn(&Event) -> Result<(), Error> + Send + 'static + Sync + Unpin>>,
+		callback: ResponseCallback,
 	) -> Result<(), Error> {
-		{
-			let mut callbacks = nioruntime_util::lockw!(self.callbacks)?;
-			callbacks.insert(event.request_id, callback);
+		self.send_event_with_failure(
+			server_pubkey,
+			event,
+			tor_port,
+			DEFAULT_REQUEST_TIMEOUT_MILLIS,
+			callback,
+			Box::pin(move |e| {
+				warn!("request to a remote server could not be delivered: {}", e);
+				Ok(())
+			}),
+		)
+	}
+
+	// send an event to a remote server. The callback is called with the response. If the
+	// server can't be reached after reconnecting or does not answer within timeout_millis,
+	// failure is called instead.
+	pub fn send_event_with_failure(
+		&mut self,
+		server_pubkey: [u8; 32],
+		event: Event,
+		tor_port: u16,
+		timeout_millis: u64,
+		callback: ResponseCallback,
+		failure: FailureCallback,
+	) -> Result<(), Error> {
+		let deadline = now_millis()? + u128::from(timeout_millis);
+		Self::queue(
+			server_pubkey,
+			event,
+			deadline,
+			callback,
+			failure,
+			&self.pending,
+		)?;
+
+		let (connection_id, sender) = Self::get_sender(
+			server_pubkey,
+			self.map.clone(),
+			tor_port,
+			self.pending.clone(),
+			0,
+		)?;
+		Self::flush(server_pubkey, connection_id, &sender, &self.pending)
+	}
+
+	// add a request to the pending requests under a new request_id, which is returned.
+	fn queue(
+		server_pubkey: [u8; 32],
+		mut event: Event,
+		deadline: u128,
+		callback: ResponseCallback,
+		failure: FailureCallback,
+		pending: &Arc<RwLock<HashMap<u32, PendingRequest>>>,
+	) -> Result<u32, Error> {
+		let mut pending = nioruntime_util::lockw!(pending)?;
+		let mut request_id: u32 = rand::random();
+		while pending.contains_key(&request_id) {
+			request_id = rand::random();
 		}
+		let caller_request_id = event.request_id;
+		event.request_id = request_id;
+		pending.insert(
+			request_id,
+			PendingRequest {
+				server_pubkey,
+				request_id: caller_request_id,
+				event,
+				callback,
+				failure,
+				sent_on: None,
+				deadline,
+			},
+		);
+		Ok(request_id)
+	}
 
+	// returns the open connection to the server, starting one if there is none.
+	fn get_sender(
+		server_pubkey: [u8; 32],
+		map: Arc<RwLock<HashMap<[u8; 32], Connection>>>,
+		tor_port: u16,
+		pending: Arc<RwLock<HashMap<u32, PendingRequest>>>,
+		attempt: u32,
+	) -> Result<(u64, SyncSender<Option<Event>>), Error> {
 		{
-			let map = nioruntime_util::lockw!(self.map)?;
-
-			match map.get(&server_pubkey) {
-				Some(sender) => {
-					sender.send(Some(event))?;
-					return Ok(());
+			let mut map = nioruntime_util::lockw!(map)?;
+			match map.get_mut(&server_pubkey) {
+				Some(connection) => {
+					connection.last_used = now_millis()?;
+					return Ok((connection.id, connection.sender.clone()));
 				}
 				None => {}
 			}
 		}
 
-		let sender = Self::start_listener(
-			server_pubkey,
-			self.map.clone(),
-			tor_port,
-			self.callbacks.clone(),
-		)?;
-		sender.send(Some(event))?;
+		Self::start_listener(server_pubkey, map, tor_port, pending, attempt)
+	}
+
+	// write the requests to the server that have not been sent on a live connection yet.
+	// The client holds them back until it has authenticated.
+	fn flush(
+		server_pubkey: [u8; 32],
+		connection_id: u64,
+		sender: &SyncSender<Option<Event>>,
+		pending: &Arc<RwLock<HashMap<u32, PendingRequest>>>,
+	) -> Result<(), Error> {
+		let events: Vec<Event> = {
+			let mut pending = nioruntime_util::lockw!(pending)?;
+			pending
+				.values_mut()
+				.filter(|r| r.server_pubkey == server_pubkey && r.sent_on.is_none())
+				.map(|r| {
+					r.sent_on = Some(connection_id);
+					r.event.clone()
+				})
+				.collect()
+		};
+
+		for (i, event) in events.iter().enumerate() {
+			match sender.send(Some(event.clone())) {
+				Ok(_) => {}
+				Err(e) => {
+					// the connection closed in the meantime. Keep the requests that were not
+					// handed to it queued for the next connection.
+					warn!("connection to {:?} closed: {}", server_pubkey, e);
+					let mut pending = nioruntime_util::lockw!(pending)?;
+					for event in &events[i..] {
+						match pending.get_mut(&event.request_id) {
+							Some(request) if request.sent_on == Some(connection_id) => {
+								request.sent_on = None;
+							}
+							_ => {}
+						}
+					}
+					break;
+				}
+			}
+		}
 		Ok(())
 	}
 
 	fn start_listener(
 		server_pubkey: [u8; 32],
-		map: Arc<RwLock<HashMap<[u8; 32], SyncSender<Option<Event>>>>>,
+		map: Arc<RwLock<HashMap<[u8; 32], Connection>>>,
 		tor_port: u16,
-		callbacks: Arc<
-			RwLock<
-				HashMap<
-					u32,
-					Pin<Box<dyn Fn(&Event) -> Result<(), Error> + Send + 'static + Sync + Unpin>>,
-				>,
-			>,
-		>,
-	) -> Result<SyncSender<Option<Event>>, Error> {
+		pending: Arc<RwLock<HashMap<u32, PendingRequest>>>,
+		attempt: u32,
+	) -> Result<(u64, SyncSender<Option<Event>>), Error> {
 		let (sender, receiver) = sync_channel(2);
+		let connection_id: u64 = rand::random();
+		let busy = Self::busy_connections(&pending)?;
+		let handle = ClientHandle::new();
 
 		{
 			let mut map = nioruntime_util::lockw!(map)?;
-			map.insert(server_pubkey, sender.clone());
+			if map.len() >= MAX_OUTBOUND_CONNECTIONS {
+				let lru = map
+					.iter()
+					.filter(|(_, c)| !busy.contains(&c.id))
+					.min_by_key(|(_, c)| c.last_used)
+					.map(|(server_pubkey, _)| *server_pubkey);
+				match lru {
+					Some(lru) => match map.remove(&lru) {
+						Some(connection) => Self::close(connection),
+						None => {}
+					},
+					None => warn!(
+						"all {} outbound connections are busy, opening one more",
+						map.len()
+					),
+				}
+			}
+			map.insert(
+				server_pubkey,
+				Connection {
+					id: connection_id,
+					sender: sender.clone(),
+					last_used: now_millis()?,
+					handle: handle.clone(),
+				},
+			);
 		}
 
 		let sender_clone = sender.clone();
@@ -110,36 +347,258 @@ impl ConnManager {
 				sender_clone,
 				receiver,
 				server_pubkey,
+				connection_id,
 				tor_port,
-				callbacks,
-				map,
+				pending.clone(),
+				map.clone(),
+				attempt,
+				handle,
 			) {
 				Ok(_) => {}
 				Err(e) => {
 					error!("Recv thread exited with error: {}", e);
+					match Self::disconnected(
+						server_pubkey,
+						connection_id,
+						tor_port,
+						pending,
+						map,
+						attempt + 1,
+						e,
+					) {
+						Ok(_) => {}
+						Err(e) => error!("error handling failed connection: {}", e),
+					}
 				}
 			}
 		});
 
-		Ok(sender)
+		Ok((connection_id, sender))
+	}
+
+	// called once when a connection fails. Requests that were written to it may already have
+	// been processed by the server, so they are failed instead of being sent again. If requests
+	// are still queued, the connection is retried with exponential backoff. After
+	// MAX_RECONNECT_ATTEMPTS failed attempts, the failure callbacks of the queued requests are
+	// called.
+	fn disconnected(
+		server_pubkey: [u8; 32],
+		connection_id: u64,
+		tor_port: u16,
+		pending: Arc<RwLock<HashMap<u32, PendingRequest>>>,
+		map: Arc<RwLock<HashMap<[u8; 32], Connection>>>,
+		attempt: u32,
+		error: Error,
+	) -> Result<(), Error> {
+		{
+			let mut map = nioruntime_util::lockw!(map)?;
+			let current = match map.get(&server_pubkey) {
+				Some(connection) => connection.id == connection_id,
+				None => false,
+			};
+			if current {
+				map.remove(&server_pubkey);
+			}
+		}
+
+		let (written, queued) = {
+			let mut pending = nioruntime_util::lockw!(pending)?;
+			let request_ids: Vec<u32> = pending
+				.iter()
+				.filter(|(_, r)| r.sent_on == Some(connection_id))
+				.map(|(request_id, _)| *request_id)
+				.collect();
+			let written: Vec<PendingRequest> = request_ids
+				.iter()
+				.filter_map(|request_id| pending.remove(request_id))
+				.collect();
+			let queued = pending
+				.values()
+				.filter(|r| r.server_pubkey == server_pubkey && r.sent_on.is_none())
+				.count();
+			(written, queued)
+		};
+
+		for request in written {
+			match (request.failure)(&error) {
+				Ok(_) => {}
+				Err(e) => error!("error in failure callback: {}", e),
+			}
+		}
+
+		// nothing to resend, the next request opens a new connection
+		if queued == 0 {
+			return Ok(());
+		}
+
+		if attempt > MAX_RECONNECT_ATTEMPTS {
+			warn!(
+				"giving up on {:?} after {} attempts",
+				server_pubkey, MAX_RECONNECT_ATTEMPTS
+			);
+			return Self::fail_queued(server_pubkey, &pending, &error);
+		}
+
+		let delay = reconnect_delay(attempt);
+		info!(
+			"reconnecting to {:?} in {} ms (attempt {})",
+			server_pubkey, delay, attempt
+		);
+
+		std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(delay));
+			let res = match Self::get_sender(
+				server_pubkey,
+				map.clone(),
+				tor_port,
+				pending.clone(),
+				attempt,
+			) {
+				Ok((connection_id, sender)) => {
+					Self::flush(server_pubkey, connection_id, &sender, &pending)
+				}
+				Err(e) => Err(e),
+			};
+
+			match res {
+				Ok(_) => {}
+				Err(e) => error!("error reconnecting to {:?}: {}", server_pubkey, e),
+			}
+		});
+
+		Ok(())
+	}
+
+	// close the connections that have not been used for IDLE_TIMEOUT_MILLIS and have no
+	// requests waiting for a response.
+	fn close_idle(
+		map: &Arc<RwLock<HashMap<[u8; 32], Connection>>>,
+		pending: &Arc<RwLock<HashMap<u32, PendingRequest>>>,
+	) -> Result<(), Error> {
+		let time_now = now_millis()?;
+		let busy = Self::busy_connections(pending)?;
+
+		let mut map = nioruntime_util::lockw!(map)?;
+		let idle: Vec<[u8; 32]> = map
+			.iter()
+			.filter(|(_, c)| {
+				!busy.contains(&c.id) && time_now.saturating_sub(c.last_used) > IDLE_TIMEOUT_MILLIS
+			})
+			.map(|(server_pubkey, _)| *server_pubkey)
+			.collect();
+
+		for server_pubkey in idle {
+			match map.remove(&server_pubkey) {
+				Some(connection) => {
+					debug!("closing idle connection to {:?}", server_pubkey);
+					Self::close(connection);
+				}
+				None => {}
+			}
+		}
+		Ok(())
+	}
+
+	// ids of the connections that requests were written to and are waiting on.
+	fn busy_connections(
+		pending: &Arc<RwLock<HashMap<u32, PendingRequest>>>,
+	) -> Result<HashSet<u64>, Error> {
+		let pending = nioruntime_util::lockr!(pending)?;
+		Ok(pending.values().filter_map(|r| r.sent_on).collect())
+	}
+
+	// ask the client to shut the connection down. It is already out of the map, so the
+	// resulting disconnect does not trigger a reconnect. If the queue of the client is full,
+	// its socket is shut down instead.
+	fn close(connection: Connection) {
+		match connection.sender.try_send(None) {
+			Ok(_) => {}
+			Err(e) => {
+				debug!(
+					"could not queue close of connection {}: {}, shutting it down",
+					connection.id, e
+				);
+				// closing joins the client threads, which take the map lock as they exit,
+				// so don't wait for it here.
+				std::thread::spawn(move || match connection.handle.shutdown() {
+					Ok(_) => {}
+					Err(e) => warn!("could not close connection {}: {}", connection.id, e),
+				});
+			}
+		}
+	}
+
+	// fail the requests whose deadline has passed.
+	fn expire(pending: &Arc<RwLock<HashMap<u32, PendingRequest>>>) -> Result<(), Error> {
+		let time_now = now_millis()?;
+
+		let expired: Vec<PendingRequest> = {
+			let mut pending = nioruntime_util::lockw!(pending)?;
+			let request_ids: Vec<u32> = pending
+				.iter()
+				.filter(|(_, r)| r.deadline <= time_now)
+				.map(|(request_id, _)| *request_id)
+				.collect();
+			request_ids
+				.iter()
+				.filter_map(|request_id| pending.remove(request_id))
+				.collect()
+		};
+
+		for request in expired {
+			let error: Error = concorderror::ErrorKind::TimeoutError(format!(
+				"no response to request {} from {:?}",
+				request.request_id, request.server_pubkey
+			))
+			.into();
+			match (request.failure)(&error) {
+				Ok(_) => {}
+				Err(e) => error!("error in failure callback: {}", e),
+			}
+		}
+		Ok(())
+	}
+
+	// call the failure callbacks of the requests to the server that could not be sent.
+	fn fail_queued(
+		server_pubkey: [u8; 32],
+		pending: &Arc<RwLock<HashMap<u32, PendingRequest>>>,
+		error: &Error,
+	) -> Result<(), Error> {
+		let failed: Vec<PendingRequest> = {
+			let mut pending = nioruntime_util::lockw!(pending)?;
+			let request_ids: Vec<u32> = pending
+				.iter()
+				.filter(|(_, r)| r.server_pubkey == server_pubkey && r.sent_on.is_none())
+				.map(|(request_id, _)| *request_id)
+				.collect();
+			request_ids
+				.iter()
+				.filter_map(|request_id| pending.remove(request_id))
+				.collect()
+		};
+
+		for request in failed {
+			match (request.failure)(error) {
+				Ok(_) => {}
+				Err(e) => error!("error in failure callback: {}", e),
+			}
+		}
+		Ok(())
 	}
 
 	fn do_recv(
 		sender: SyncSender<Option<Event>>,
 		receiver: Receiver<Option<Event>>,
 		server_pubkey: [u8; 32],
+		connection_id: u64,
 		tor_port: u16,
-		callbacks: Arc<
-			RwLock<
-				HashMap<
-					u32,
-					Pin<Box<dyn Fn(&Event) -> Result<(), Error> + Send + 'static + Sync + Unpin>>,
-				>,
-			>,
-		>,
-		map: Arc<RwLock<HashMap<[u8; 32], SyncSender<Option<Event>>>>>,
+		pending: Arc<RwLock<HashMap<u32, PendingRequest>>>,
+		map: Arc<RwLock<HashMap<[u8; 32], Connection>>>,
+		attempt: u32,
+		handle: ClientHandle,
 	) -> Result<(), Error> {
-		let secret = match secret!() {
+		let secret = match host_secret()? {
 			Some(secret) => secret,
 			None => {
 				return Err(ErrorKind::ApplicationError(
@@ -153,15 +612,28 @@ impl ConnManager {
 		info!("connecting listener to {}", onion);
 		let mut client = WSListenerClient::new(onion, tor_port, AuthParams::Secret(secret));
 
+		// set once a request is answered, so that a later disconnect starts a fresh
+		// series of reconnect attempts.
+		let answered = Arc::new(AtomicBool::new(false));
+		// the read and write threads both report a disconnect, only handle the first.
+		let closed = Arc::new(AtomicBool::new(false));
+
+		let pending_clone = pending.clone();
+		let answered_clone = answered.clone();
 		client.set_callback(move |event, _writer| {
 			info!("client callback on event: {:?}", event);
-			let callback = {
-				let mut callbacks = nioruntime_util::lockw!(callbacks)?;
-				callbacks.remove(&event.request_id)
+			let request = {
+				let mut pending = nioruntime_util::lockw!(pending_clone)?;
+				pending.remove(&event.request_id)
 			};
 
-			match callback {
-				Some(callback) => (callback)(event)?,
+			match request {
+				Some(request) => {
+					answered_clone.store(true, Ordering::SeqCst);
+					let mut event = event.clone();
+					event.request_id = request.request_id;
+					(request.callback)(&event)?
+				}
 				None => {
 					error!("Callback not found for event: {:?}", event);
 				}
@@ -170,21 +642,242 @@ impl ConnManager {
 		})?;
 		client.set_error(move |error, onion| {
 			error!("client [{}] received error: {}", onion, error);
-			let server_pubkey = Pubkey::from_onion(&onion)?.to_bytes();
-
-			{
-				let mut map = nioruntime_util::lockw!(map)?;
-				map.remove(&server_pubkey);
+			if closed.swap(true, Ordering::SeqCst) {
+				return Ok(());
 			}
-			// TODO: handle reconnect and resend logic.
-			// currently we just drop messages on disconnect.
 
-			Ok(())
+			let attempt = match answered.load(Ordering::SeqCst) {
+				true => 1,
+				false => attempt + 1,
+			};
+			Self::disconnected(
+				server_pubkey,
+				connection_id,
+				tor_port,
+				pending.clone(),
+				map.clone(),
+				attempt,
+				error,
+			)
 		})?;
 
 		client.start(Some((sender, receiver)))?;
 
+		let client = Arc::new(Mutex::new(client));
+		handle.set_closer(Box::new(move || match client.lock() {
+			Ok(client) => client.close(),
+			Err(e) => Err(concorderror::ErrorKind::LockError(e.to_string()).into()),
+		}))?;
+
 		warn!("client start thread exiting");
 		Ok(())
 	}
 }
+
+// milliseconds to wait before a reconnect attempt, doubling with each failed attempt.
+fn reconnect_delay(attempt: u32) -> u64 {
+	RECONNECT_BASE_DELAY_MILLIS << attempt.saturating_sub(1)
+}
+
+fn now_millis() -> Result<u128, Error> {
+	Ok(std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)?
+		.as_millis())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::sync::atomic::AtomicUsize;
+
+	const SERVER_PUBKEY: [u8; 32] = [1u8; 32];
+
+	// queue a request whose failures are counted in failed.
+	fn queue_test_request(
+		pending: &Arc<RwLock<HashMap<u32, PendingRequest>>>,
+		caller_request_id: u32,
+		deadline: u128,
+		failed: &Arc<AtomicUsize>,
+	) -> Result<u32, Error> {
+		let failed = failed.clone();
+		let event = Event {
+			request_id: caller_request_id,
+			..Default::default()
+		};
+		ConnManager::queue(
+			SERVER_PUBKEY,
+			event,
+			deadline,
+			Box::pin(move |_| Ok(())),
+			Box::pin(move |_| {
+				failed.fetch_add(1, Ordering::SeqCst);
+				Ok(())
+			}),
+			pending,
+		)
+	}
+
+	fn test_error() -> Error {
+		concorderror::ErrorKind::TimeoutError("test".to_string()).into()
+	}
+
+	#[test]
+	fn test_reconnect_delay() {
+		assert_eq!(reconnect_delay(0), RECONNECT_BASE_DELAY_MILLIS);
+		assert_eq!(reconnect_delay(1), RECONNECT_BASE_DELAY_MILLIS);
+		assert_eq!(reconnect_delay(2), RECONNECT_BASE_DELAY_MILLIS * 2);
+		assert_eq!(
+			reconnect_delay(MAX_RECONNECT_ATTEMPTS),
+			RECONNECT_BASE_DELAY_MILLIS * 32
+		);
+	}
+
+	#[test]
+	fn test_queue_request_ids() -> Result<(), Error> {
+		let pending = Arc::new(RwLock::new(HashMap::new()));
+		let failed = Arc::new(AtomicUsize::new(0));
+
+		// two callers may use the same request_id
+		let first = queue_test_request(&pending, 7, u128::MAX, &failed)?;
+		let second = queue_test_request(&pending, 7, u128::MAX, &failed)?;
+		assert_ne!(first, second);
+
+		let pending = nioruntime_util::lockr!(pending)?;
+		assert_eq!(pending.len(), 2);
+		for request_id in &[first, second] {
+			let request = pending.get(request_id).unwrap();
+			assert_eq!(request.event.request_id, *request_id);
+			assert_eq!(request.request_id, 7);
+			assert_eq!(request.sent_on, None);
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn test_expire() -> Result<(), Error> {
+		let pending = Arc::new(RwLock::new(HashMap::new()));
+		let failed = Arc::new(AtomicUsize::new(0));
+		queue_test_request(&pending, 1, 0, &failed)?;
+		let waiting = queue_test_request(&pending, 2, u128::MAX, &failed)?;
+
+		ConnManager::expire(&pending)?;
+		assert_eq!(failed.load(Ordering::SeqCst), 1);
+		let pending = nioruntime_util::lockr!(pending)?;
+		assert_eq!(pending.len(), 1);
+		assert!(pending.contains_key(&waiting));
+		Ok(())
+	}
+
+	#[test]
+	fn test_flush() -> Result<(), Error> {
+		let pending = Arc::new(RwLock::new(HashMap::new()));
+		let failed = Arc::new(AtomicUsize::new(0));
+		let request_id = queue_test_request(&pending, 1, u128::MAX, &failed)?;
+
+		// requests stay queued if the connection is gone
+		let (sender, receiver) = sync_channel(2);
+		drop(receiver);
+		ConnManager::flush(SERVER_PUBKEY, 10, &sender, &pending)?;
+		{
+			let pending = nioruntime_util::lockr!(pending)?;
+			assert_eq!(pending.get(&request_id).unwrap().sent_on, None);
+		}
+
+		// and are written once to a live one, with the request_id of the manager
+		let (sender, receiver) = sync_channel(2);
+		ConnManager::flush(SERVER_PUBKEY, 11, &sender, &pending)?;
+		ConnManager::flush(SERVER_PUBKEY, 11, &sender, &pending)?;
+		let event = receiver.try_recv().unwrap().unwrap();
+		assert_eq!(event.request_id, request_id);
+		assert!(receiver.try_recv().is_err());
+		let pending = nioruntime_util::lockr!(pending)?;
+		assert_eq!(pending.get(&request_id).unwrap().sent_on, Some(11));
+		Ok(())
+	}
+
+	#[test]
+	fn test_disconnected() -> Result<(), Error> {
+		let pending = Arc::new(RwLock::new(HashMap::new()));
+		let map = Arc::new(RwLock::new(HashMap::new()));
+		let failed = Arc::new(AtomicUsize::new(0));
+		let written = queue_test_request(&pending, 1, u128::MAX, &failed)?;
+		queue_test_request(&pending, 2, u128::MAX, &failed)?;
+		{
+			let mut pending = nioruntime_util::lockw!(pending)?;
+			pending.get_mut(&written).unwrap().sent_on = Some(10);
+		}
+
+		// a written request may have been processed, so it is failed rather than resent
+		ConnManager::disconnected(
+			SERVER_PUBKEY,
+			10,
+			0,
+			pending.clone(),
+			map.clone(),
+			MAX_RECONNECT_ATTEMPTS + 1,
+			test_error(),
+		)?;
+		assert_eq!(failed.load(Ordering::SeqCst), 2);
+		assert!(nioruntime_util::lockr!(pending)?.is_empty());
+
+		// nothing is failed or resent if there are no requests left
+		ConnManager::disconnected(SERVER_PUBKEY, 10, 0, pending.clone(), map, 1, test_error())?;
+		assert_eq!(failed.load(Ordering::SeqCst), 2);
+		Ok(())
+	}
+
+	#[test]
+	fn test_close_full_queue() -> Result<(), Error> {
+		let closed = Arc::new(AtomicUsize::new(0));
+		let closed_clone = closed.clone();
+		let handle = ClientHandle::new();
+		handle.set_closer(Box::new(move || {
+			closed_clone.fetch_add(1, Ordering::SeqCst);
+			Ok(())
+		}))?;
+
+		// the close is queued while there is room
+		let (sender, receiver) = sync_channel(2);
+		ConnManager::close(Connection {
+			id: 1,
+			sender: sender.clone(),
+			last_used: 0,
+			handle: handle.clone(),
+		});
+		assert!(receiver.try_recv().unwrap().is_none());
+
+		// otherwise the client is shut down
+		sender.try_send(Some(Event::default())).unwrap();
+		sender.try_send(Some(Event::default())).unwrap();
+		ConnManager::close(Connection {
+			id: 1,
+			sender,
+			last_used: 0,
+			handle,
+		});
+		for _ in 0..100 {
+			if closed.load(Ordering::SeqCst) > 0 {
+				break;
+			}
+			std::thread::sleep(Duration::from_millis(10));
+		}
+		assert_eq!(closed.load(Ordering::SeqCst), 1);
+		Ok(())
+	}
+
+	#[test]
+	fn test_shutdown_before_start() -> Result<(), Error> {
+		let closed = Arc::new(AtomicUsize::new(0));
+		let closed_clone = closed.clone();
+		let handle = ClientHandle::new();
+
+		// a client shut down before it was started is closed once it starts
+		handle.shutdown()?;
+		handle.set_closer(Box::new(move || {
+			closed_clone.fetch_add(1, Ordering::SeqCst);
+			Ok(())
+		}))?;
+		assert_eq!(closed.load(Ordering::SeqCst), 1);
+		Ok(())
+	}
+}
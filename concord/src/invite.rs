@@ -12,16 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::conn_manager::ConnManager;
-use crate::send;
+use crate::channel::can_manage;
+use crate::conn_manager::{ConnManager, DEFAULT_REQUEST_TIMEOUT_MILLIS};
+use crate::presence::PresenceManager;
+use crate::profile::get_avatar;
+use crate::server::get_icon;
 use crate::types::{
-	ConnectionInfo, CreateInviteResponse, DeleteInviteResponse, Event, EventBody,
-	InviteResponseInfo, ListInvitesResponse, ViewInviteResponse,
+	ApproveJoinRequestResponse, ConnectionInfo, CreateInviteResponse, DeleteInviteResponse, Event,
+	EventBody, GetInviteUsesResponse, InviteResponseInfo, JoinRequestOutcomeNotification,
+	JoinRequestOutcomeRequest, JoinRequestOutcomeResponse, ListInvitesResponse,
+	ListJoinRequestsResponse, ModifyInviteResponse, RejectJoinRequestResponse, ViewInviteResponse,
 };
+use crate::{moderator, owner, send};
 use concordconfig::ConcordConfig;
+use concorddata::concord::AcceptInviteResult;
 use concorddata::concord::Channel;
 use concorddata::concord::DSContext;
 use concorddata::concord::ServerInfoReply;
+use concorddata::concord::AUTH_FLAG_MEMBER;
+use concorddata::pow;
+use concorddata::pow::MAX_POW_DIFFICULTY;
 use concorddata::types::Image;
 use concorddata::types::Pubkey;
 use concorddata::types::SerString;
@@ -44,12 +54,14 @@ pub fn create_invite(
 	event: &Event,
 ) -> Result<bool, ConcordError> {
 	let request_id = event.request_id;
-	let (server_id, server_pubkey, count, expiration) = match &event.body {
+	let (server_id, server_pubkey, count, expiration, roles, landing_channel) = match &event.body {
 		EventBody::CreateInviteRequest(event) => (
 			event.server_id.to_bytes(),
 			event.server_pubkey.to_bytes(),
 			event.count,
 			event.expiration,
+			event.roles,
+			event.landing_channel,
 		),
 		_ => {
 			warn!(
@@ -69,8 +81,31 @@ pub fn create_invite(
 	};
 
 	if server_pubkey == pubkey!() {
-		let invite_id =
-			ds_context.create_invite(user_pubkey.to_bytes(), server_id, expiration, count)?;
+		// only owners may hand out roles and only users that manage the landing channel
+		// may give access to it
+		if roles & !AUTH_FLAG_MEMBER != 0 {
+			owner!(conn_info, ds_context, server_pubkey, server_id);
+		}
+		if landing_channel != 0
+			&& !can_manage(
+				conn_info,
+				ds_context,
+				server_pubkey,
+				server_id,
+				landing_channel,
+			)? {
+			info!("not allowed to manage channel {}", landing_channel);
+			return Ok(true);
+		}
+
+		let invite_id = ds_context.create_invite(
+			user_pubkey.to_bytes(),
+			server_id,
+			expiration,
+			count,
+			roles,
+			landing_channel,
+		)?;
 
 		let event = Event {
 			request_id,
@@ -132,26 +167,109 @@ pub fn list_invites(
 }
 
 pub fn modify_invite(
-	_conn_info: &ConnectionInfo,
-	_ds_context: &DSContext,
-	_event: &Event,
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
 ) -> Result<bool, ConcordError> {
-	/*
-			let (request_id, invite_id, max, expiration) =
-					match event.modify_invite_request.0.as_ref() {
-							Some(event) => (
-									event.request_id,
-									event.invite_id,
-									event.max,
-									event.expiration,
-							),
-							None => {
-									warn!("Malformed modify invite event. No event present: {:?}", event);
-									return Ok(true);
-							}
-					};
-	*/
-	warn!("modify not implemented");
+	let request_id = event.request_id;
+	let (invite_id, max, expiration) = match &event.body {
+		EventBody::ModifyInviteRequest(event) => (event.invite_id, event.max, event.expiration),
+		_ => {
+			warn!(
+				"Malformed modify invite event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let user_pubkey = match &conn_info.pubkey {
+		Some(user_pubkey) => user_pubkey.to_bytes(),
+		None => {
+			warn!("expected a user pubkey at this point. Event = {:?}", event);
+			return Ok(true);
+		}
+	};
+
+	let invite = match ds_context.get_invite(invite_id)? {
+		Some(invite) => invite,
+		None => {
+			info!("modify invite: {} not found", invite_id);
+			let event = Event {
+				request_id,
+				body: EventBody::ModifyInviteResponse(ModifyInviteResponse { success: false })
+					.into(),
+				..Default::default()
+			};
+			send!(conn_info.handle, event);
+			return Ok(false);
+		}
+	};
+
+	// only the inviter or an owner of the server may change an invite
+	if invite.inviter != user_pubkey {
+		owner!(conn_info, ds_context, pubkey!(), invite.server_id);
+	}
+
+	let success = ds_context.modify_invite(invite_id, max, expiration)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::ModifyInviteResponse(ModifyInviteResponse { success }).into(),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn get_invite_uses(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_id, server_pubkey, invite_id) = match &event.body {
+		EventBody::GetInviteUsesRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.invite_id,
+		),
+		_ => {
+			warn!(
+				"Malformed get invite uses event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let user_pubkey = match &conn_info.pubkey {
+		Some(user_pubkey) => user_pubkey.to_bytes(),
+		None => {
+			warn!("expected a user pubkey at this point. Event = {:?}", event);
+			return Ok(true);
+		}
+	};
+
+	// the inviter may see the uses of a live invite, owners may see all uses
+	let is_inviter = match ds_context.get_invite(invite_id)? {
+		Some(invite) => invite.inviter == user_pubkey && invite.server_id == server_id,
+		None => false,
+	};
+	if !is_inviter {
+		owner!(conn_info, ds_context, server_pubkey, server_id);
+	}
+
+	let uses = ds_context.get_invite_uses(server_pubkey, server_id, invite_id)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::GetInviteUsesResponse(GetInviteUsesResponse { invite_id, uses }).into(),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
 	Ok(false)
 }
 
@@ -224,6 +342,7 @@ pub fn view_invite(
 	event: &Event,
 	conn_manager: Arc<RwLock<ConnManager>>,
 	config: &ConcordConfig,
+	presence: &PresenceManager,
 ) -> Result<bool, ConcordError> {
 	let (pubkey, request_id, id, invite_url) = match parse_invite(event)? {
 		Some((pubkey, request_id, id, invite_url)) => (pubkey, request_id, id, invite_url),
@@ -238,16 +357,52 @@ pub fn view_invite(
 		info!("jri={:?}", jri);
 		match jri {
 			Some(jri) => {
+				let server_pubkey = Pubkey::from_bytes(jri.server_pubkey);
+				let server_id = ServerId::from_bytes(jri.server_id);
+				let inviter_name = match ds_context
+					.get_profiles(
+						vec![Pubkey::from_bytes(jri.inviter_pubkey)],
+						server_pubkey.clone(),
+						server_id.clone(),
+					)?
+					.pop()
+				{
+					Some(Some(profile)) => profile.profile_data.user_name,
+					_ => "".into(),
+				};
+
+				// missing images are not an error, the client shows a placeholder
+				let inviter_icon = get_avatar(
+					config.root_dir.clone(),
+					jri.server_id,
+					jri.server_pubkey,
+					jri.inviter_pubkey,
+				)
+				.unwrap_or(vec![]);
+				let server_icon =
+					get_icon(jri.server_id, jri.server_pubkey, config.root_dir.clone())
+						.unwrap_or(vec![]);
+
+				let current_members = ds_context.member_count(jri.server_pubkey, jri.server_id)?;
+				let online_members =
+					presence.online_member_count(&server_pubkey, &server_id, ds_context)?;
+
 				let event = Event {
 					request_id,
 					body: EventBody::ViewInviteResponse(ViewInviteResponse {
 						response_info: Some(InviteResponseInfo {
-							inviter_name: "".into(),
-							inviter_icon: Image { data: vec![] },
-							server_icon: Image { data: vec![] },
+							inviter_name,
+							inviter_icon: Image { data: inviter_icon },
+							server_icon: Image { data: server_icon },
 							server_name: jri.name.into(),
-							current_members: 0,
-							online_members: 0,
+							current_members,
+							online_members,
+							server_description: jri.description.into(),
+							server_topic: jri.topic.into(),
+							server_rules: jri.rules.into(),
+							server_creation_time: jri.creation_time,
+							verification_level: jri.verification_level,
+							pow_difficulty: jri.pow_difficulty,
 						}),
 					}),
 					..Default::default()
@@ -276,14 +431,29 @@ pub fn view_invite(
 
 		let mut conn_manager = nioruntime_util::lockw!(conn_manager)?;
 		let handle = conn_info.handle.clone();
-		conn_manager.send_event(
+		let failure_handle = conn_info.handle.clone();
+		conn_manager.send_event_with_failure(
 			pubkey,
 			event,
 			config.tor_port,
+			DEFAULT_REQUEST_TIMEOUT_MILLIS,
 			Box::pin(move |event| {
 				send!(handle, event);
 				Ok(())
 			}),
+			Box::pin(move |e| {
+				warn!("view invite request failed: {}", e);
+				// answer as if the invite was not found
+				let event = Event {
+					request_id,
+					body: EventBody::ViewInviteResponse(ViewInviteResponse {
+						response_info: None,
+					}),
+					..Default::default()
+				};
+				send!(failure_handle, event);
+				Ok(())
+			}),
 		)?;
 	}
 
@@ -296,38 +466,299 @@ pub fn accept_invite(
 	event: &Event,
 ) -> Result<bool, ConcordError> {
 	let request_id = event.request_id;
-	let (invite_id, user_pubkey, server_pubkey, user_name, user_bio, avatar) = match &event.body {
-		EventBody::AcceptInviteRequest(event) => (
-			event.invite_id,
-			event.user_pubkey,
-			event.server_pubkey,
-			event.user_name.data.clone(),
-			event.user_bio.data.clone(),
-			event.avatar.data.clone(),
-		),
-		_ => {
-			warn!("Malformed event in accept_invite. Event = {:?}", event);
-			return Ok(true);
-		}
-	};
+	let (invite_id, user_pubkey, server_pubkey, user_name, user_bio, avatar, pow_nonce) =
+		match &event.body {
+			EventBody::AcceptInviteRequest(event) => (
+				event.invite_id,
+				event.user_pubkey,
+				event.server_pubkey,
+				event.user_name.data.clone(),
+				event.user_bio.data.clone(),
+				event.avatar.data.clone(),
+				event.pow_nonce,
+			),
+			_ => {
+				warn!("Malformed event in accept_invite. Event = {:?}", event);
+				return Ok(true);
+			}
+		};
 
-	ds_context.accept_invite(
+	let (success, landing_channel, pending, pow_difficulty) = match ds_context.accept_invite(
 		invite_id,
 		user_pubkey,
 		server_pubkey,
 		user_name,
 		user_bio,
 		avatar,
+		pow_nonce,
+	)? {
+		AcceptInviteResult::Joined(_, landing_channel) => (true, landing_channel, false, 0),
+		AcceptInviteResult::Pending => (false, 0, true, 0),
+		AcceptInviteResult::ProofOfWorkRequired(pow_difficulty) => {
+			(false, 0, false, pow_difficulty)
+		}
+		AcceptInviteResult::Rejected => (false, 0, false, 0),
+	};
+
+	let event = Event {
+		request_id,
+		body: EventBody::AcceptInviteResponse(crate::types::AcceptInviteResponse {
+			success,
+			landing_channel,
+			pending,
+			pow_difficulty,
+		}),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn list_join_requests(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_pubkey, server_id) = match &event.body {
+		EventBody::ListJoinRequestsRequest(event) => {
+			(event.server_pubkey.clone(), event.server_id.clone())
+		}
+		_ => {
+			warn!("Malformed event in list_join_requests. Event = {:?}", event);
+			return Ok(true);
+		}
+	};
+
+	moderator!(
+		conn_info,
+		ds_context,
+		server_pubkey.to_bytes(),
+		server_id.to_bytes()
+	);
+
+	let join_requests =
+		ds_context.list_join_requests(server_pubkey.to_bytes(), server_id.to_bytes())?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::ListJoinRequestsResponse(ListJoinRequestsResponse {
+			server_pubkey,
+			server_id,
+			join_requests,
+		}),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn approve_join_request(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_pubkey, server_id, user_pubkey) = match &event.body {
+		EventBody::ApproveJoinRequestRequest(event) => (
+			event.server_pubkey.clone(),
+			event.server_id.clone(),
+			event.user_pubkey.clone(),
+		),
+		_ => {
+			warn!(
+				"Malformed event in approve_join_request. Event = {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	moderator!(
+		conn_info,
+		ds_context,
+		server_pubkey.to_bytes(),
+		server_id.to_bytes()
+	);
+
+	let landing_channel = ds_context.approve_join_request(
+		server_pubkey.to_bytes(),
+		server_id.to_bytes(),
+		user_pubkey.to_bytes(),
 	)?;
 
 	let event = Event {
 		request_id,
-		body: EventBody::AcceptInviteResponse(crate::types::AcceptInviteResponse { success: true }),
+		body: EventBody::ApproveJoinRequestResponse(ApproveJoinRequestResponse {
+			success: landing_channel.is_some(),
+		}),
 		..Default::default()
 	};
+	send!(conn_info.handle, event);
+
+	match landing_channel {
+		Some(landing_channel) => notify_applicant(
+			server_pubkey,
+			server_id,
+			user_pubkey,
+			true,
+			landing_channel,
+			conn_manager,
+			config,
+		)?,
+		None => {}
+	}
 
+	Ok(false)
+}
+
+pub fn reject_join_request(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_pubkey, server_id, user_pubkey) = match &event.body {
+		EventBody::RejectJoinRequestRequest(event) => (
+			event.server_pubkey.clone(),
+			event.server_id.clone(),
+			event.user_pubkey.clone(),
+		),
+		_ => {
+			warn!(
+				"Malformed event in reject_join_request. Event = {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	moderator!(
+		conn_info,
+		ds_context,
+		server_pubkey.to_bytes(),
+		server_id.to_bytes()
+	);
+
+	let success = ds_context.reject_join_request(
+		server_pubkey.to_bytes(),
+		server_id.to_bytes(),
+		user_pubkey.to_bytes(),
+	)?;
+
+	let event = Event {
+		request_id,
+		body: EventBody::RejectJoinRequestResponse(RejectJoinRequestResponse { success }),
+		..Default::default()
+	};
 	send!(conn_info.handle, event);
 
+	if success {
+		notify_applicant(
+			server_pubkey,
+			server_id,
+			user_pubkey,
+			false,
+			0,
+			conn_manager,
+			config,
+		)?;
+	}
+
+	Ok(false)
+}
+
+// tell the applicant's host how their join request was decided.
+fn notify_applicant(
+	server_pubkey: Pubkey,
+	server_id: ServerId,
+	user_pubkey: Pubkey,
+	approved: bool,
+	landing_channel: u64,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
+) -> Result<(), ConcordError> {
+	let event = Event {
+		body: EventBody::JoinRequestOutcomeRequest(JoinRequestOutcomeRequest {
+			server_pubkey,
+			server_id,
+			approved,
+			landing_channel,
+		})
+		.into(),
+		..Default::default()
+	};
+
+	let mut conn_manager = nioruntime_util::lockw!(conn_manager)?;
+	conn_manager.send_event(
+		user_pubkey.to_bytes(),
+		event,
+		config.tor_port,
+		Box::pin(move |event| {
+			debug!("join request outcome response: {:?}", event);
+			Ok(())
+		}),
+	)?;
+	Ok(())
+}
+
+// called on the applicant's host when the host of the server decides a join request. The
+// outcome is passed on to the applicant's connections.
+pub fn join_request_outcome(
+	conn_info: &ConnectionInfo,
+	event: &Event,
+	presence: &PresenceManager,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let outcome = match &event.body {
+		EventBody::JoinRequestOutcomeRequest(event) => event.clone(),
+		_ => {
+			warn!(
+				"Malformed event in join_request_outcome. Event = {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	// only the host of the server may decide its join requests
+	match &conn_info.pubkey {
+		Some(pubkey) => {
+			if pubkey.to_bytes() != outcome.server_pubkey.to_bytes() {
+				warn!("join request outcome from a non-host: {:?}", event);
+				return Ok(true);
+			}
+		}
+		None => return Ok(true),
+	}
+
+	let event = Event {
+		request_id,
+		body: EventBody::JoinRequestOutcomeResponse(JoinRequestOutcomeResponse {}),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
+	let event = Event {
+		body: EventBody::JoinRequestOutcomeNotification(JoinRequestOutcomeNotification {
+			server_pubkey: outcome.server_pubkey,
+			server_id: outcome.server_id,
+			approved: outcome.approved,
+			landing_channel: outcome.landing_channel,
+		}),
+		..Default::default()
+	};
+	for handle in presence.user_connections(&Pubkey::from_bytes(pubkey!()))? {
+		send!(handle, event);
+	}
+
 	Ok(false)
 }
 
@@ -344,6 +775,10 @@ pub fn join_server(
 			return Ok(true);
 		}
 	};
+	let pow_difficulty = match &event.body {
+		EventBody::JoinServerRequest(event) => event.pow_difficulty,
+		_ => 0,
+	};
 
 	if server_pubkey == pubkey!() {
 		// Something's wrong. We can't join our own server
@@ -367,35 +802,95 @@ pub fn join_server(
 			false => ("".to_string().into(), "".to_string().into()),
 		};
 
-		let event = Event {
-			request_id,
-			body: EventBody::AcceptInviteRequest(crate::types::AcceptInviteRequest {
-				invite_id,
-				user_pubkey: pubkey!(),
-				server_pubkey,
-				user_name,
-				user_bio,
-				avatar: Image { data: vec![] },
-			}),
-			..Default::default()
+		let request = crate::types::AcceptInviteRequest {
+			invite_id,
+			user_pubkey: pubkey!(),
+			server_pubkey,
+			user_name,
+			user_bio,
+			avatar: Image { data: vec![] },
+			pow_nonce: 0,
 		};
 
-		let mut conn_manager = nioruntime_util::lockw!(conn_manager)?;
+		// solving can take a while, so it is done on its own thread rather than blocking
+		// the websocket handler.
 		let handle = conn_info.handle.clone();
-		conn_manager.send_event(
-			server_pubkey,
-			event,
-			config.tor_port,
-			Box::pin(move |event| {
-				send!(handle, event);
-				Ok(())
-			}),
-		)?;
+		let tor_port = config.tor_port;
+		std::thread::spawn(move || {
+			match send_accept_invite(
+				request,
+				pow_difficulty,
+				request_id,
+				handle,
+				conn_manager,
+				tor_port,
+			) {
+				Ok(_) => {}
+				Err(e) => error!("error sending join request: {}", e),
+			}
+		});
 	}
 
 	Ok(false)
 }
 
+// solve the proof of work of the server and send it the request. The response, or a failure
+// response if the server can't be reached, is sent to handle.
+fn send_accept_invite(
+	mut request: crate::types::AcceptInviteRequest,
+	pow_difficulty: u8,
+	request_id: u32,
+	handle: ConnData,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	tor_port: u16,
+) -> Result<(), ConcordError> {
+	let server_pubkey = request.server_pubkey;
+
+	// never more than a server may ask for, so a bad request can't stall us
+	request.pow_nonce = pow::solve(
+		server_pubkey,
+		request.invite_id,
+		request.user_pubkey,
+		pow_difficulty.min(MAX_POW_DIFFICULTY),
+	);
+
+	let event = Event {
+		request_id,
+		body: EventBody::AcceptInviteRequest(request),
+		..Default::default()
+	};
+
+	let mut conn_manager = nioruntime_util::lockw!(conn_manager)?;
+	let failure_handle = handle.clone();
+	conn_manager.send_event_with_failure(
+		server_pubkey,
+		event,
+		tor_port,
+		DEFAULT_REQUEST_TIMEOUT_MILLIS,
+		Box::pin(move |event| {
+			send!(handle, event);
+			Ok(())
+		}),
+		Box::pin(move |e| {
+			warn!("join request failed: {}", e);
+			let event = Event {
+				request_id,
+				body: EventBody::AcceptInviteResponse(crate::types::AcceptInviteResponse {
+					success: false,
+					landing_channel: 0,
+					pending: false,
+					pow_difficulty: 0,
+				}),
+				..Default::default()
+			};
+			send!(failure_handle, event);
+			Ok(())
+		}),
+	)?;
+
+	Ok(())
+}
+
 #[derive(Serialize)]
 struct InviteResponse {
 	invite_url: String,
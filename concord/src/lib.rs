@@ -18,6 +18,7 @@ extern crate serde_derive;
 pub mod client;
 pub mod types;
 
+mod announcement;
 mod auth;
 mod channel;
 mod concord;
@@ -25,8 +26,10 @@ mod conn_manager;
 mod invite;
 mod members;
 mod message;
+mod presence;
 mod profile;
 mod server;
+mod subscription;
 mod ws;
 
 #[macro_use]
@@ -12,10 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::send;
+use crate::presence::PresenceManager;
 use crate::types::ConnectionInfo;
-use crate::types::GetMembersResponse;
 use crate::types::{Event, EventBody};
+use crate::types::{GetMembersResponse, SearchMembersResponse, SetMemberRolesResponse};
+use crate::{owner, send};
 use concorddata::concord::DSContext;
 use concorderror::Error as ConcordError;
 use concordutil::librustlet;
@@ -29,12 +30,14 @@ pub fn get_members(
 	conn_info: &ConnectionInfo,
 	ds_context: &DSContext,
 	event: &Event,
+	presence: &PresenceManager,
 ) -> Result<bool, ConcordError> {
-	let (server_id, server_pubkey, batch_num) = match &event.body {
+	let (server_id, server_pubkey, cursor, limit) = match &event.body {
 		EventBody::GetMembersRequest(event) => (
 			event.server_id.clone(),
 			event.server_pubkey.clone(),
-			event.batch_num,
+			event.cursor.0.clone(),
+			event.limit,
 		),
 		_ => {
 			warn!(
@@ -44,40 +47,107 @@ pub fn get_members(
 			return Ok(true);
 		}
 	};
-	// TODO: online/offline and role ordering
 
-	let mut members = ds_context
-		.get_members(server_pubkey.to_bytes(), server_id.to_bytes(), 0, true)
+	// members of the server get presence updates for it from now on
+	match &conn_info.pubkey {
+		Some(pubkey) => {
+			if ds_context
+				.get_server_member(
+					server_pubkey.to_bytes(),
+					server_id.to_bytes(),
+					pubkey.to_bytes(),
+				)?
+				.is_some()
+			{
+				presence.subscribe(&server_pubkey, &server_id, &conn_info.handle)?;
+			}
+		}
+		None => {}
+	}
+
+	let (members, next_cursor) = ds_context
+		.get_members(
+			server_pubkey.to_bytes(),
+			server_id.to_bytes(),
+			cursor,
+			limit,
+		)
 		.map_err(|e| {
-			let error: Error = ErrorKind::ApplicationError(format!(
-				"error accepting invite - members: {}",
-				e.to_string()
-			))
-			.into();
+			let error: Error =
+				ErrorKind::ApplicationError(format!("error getting members: {}", e.to_string()))
+					.into();
 			error
 		})?;
 
-	let mut other_members = ds_context
-		.get_members(server_pubkey.to_bytes(), server_id.to_bytes(), 0, false)
+	let mut types_members: Vec<crate::types::Member> = vec![];
+	for member in members {
+		let mut member: crate::types::Member = member.into();
+		member.online_status = presence.status(&member.user_pubkey)?;
+		types_members.push(member);
+	}
+
+	let event = Event {
+		body: EventBody::GetMembersResponse(GetMembersResponse {
+			server_id,
+			server_pubkey,
+			members: types_members,
+			next_cursor: next_cursor.into(),
+		})
+		.into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn search_members(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	presence: &PresenceManager,
+) -> Result<bool, ConcordError> {
+	let (server_id, server_pubkey, query, limit) = match &event.body {
+		EventBody::SearchMembersRequest(event) => (
+			event.server_id.clone(),
+			event.server_pubkey.clone(),
+			event.query.to_string(),
+			event.limit,
+		),
+		_ => {
+			warn!(
+				"Malformed search_members_request event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let members = ds_context
+		.search_members(
+			server_pubkey.to_bytes(),
+			server_id.to_bytes(),
+			&query,
+			limit,
+		)
 		.map_err(|e| {
-			let error: Error = ErrorKind::ApplicationError(format!(
-				"error accepting invite - members: {}",
-				e.to_string()
-			))
-			.into();
+			let error: Error =
+				ErrorKind::ApplicationError(format!("error searching members: {}", e.to_string()))
+					.into();
 			error
 		})?;
-	members.append(&mut other_members);
 
 	let mut types_members: Vec<crate::types::Member> = vec![];
 	for member in members {
-		let member: crate::types::Member = member.into();
+		let mut member: crate::types::Member = member.into();
+		member.online_status = presence.status(&member.user_pubkey)?;
 		types_members.push(member);
 	}
 
 	let event = Event {
-		body: EventBody::GetMembersResponse(GetMembersResponse {
-			batch_num,
+		request_id: event.request_id,
+		body: EventBody::SearchMembersResponse(SearchMembersResponse {
 			server_id,
 			server_pubkey,
 			members: types_members,
@@ -91,6 +161,44 @@ pub fn get_members(
 	Ok(false)
 }
 
+// only owners may change roles. Granting AUTH_FLAG_OWNER to another member makes them a
+// co-owner, and ownership can be transferred as long as the server keeps at least one owner.
+pub fn set_member_roles(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let (server_id, server_pubkey, user_pubkey, roles) = match &event.body {
+		EventBody::SetMemberRolesRequest(event) => (
+			event.server_id.to_bytes(),
+			event.server_pubkey.to_bytes(),
+			event.user_pubkey.to_bytes(),
+			event.roles,
+		),
+		_ => {
+			warn!(
+				"Malformed set_member_roles_request event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
+	let success = ds_context.set_member_roles(server_pubkey, server_id, user_pubkey, roles)?;
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::SetMemberRolesResponse(SetMemberRolesResponse { success }).into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
 #[derive(Serialize)]
 struct MemberJson {
 	server_id: String,
@@ -12,39 +12,414 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::announcement::push_to_followers;
 use crate::conn_manager::ConnManager;
-use crate::types::{ConnectionInfo, Event};
+use crate::send;
+use crate::subscription::SubscriptionManager;
+use crate::types::SubscriptionActionType;
+use crate::types::{ChannelIdentifier, ConnectionInfo, Event, EventBody, Message};
+use crate::types::{ExportChannelResponse, GetMessagesResponse, SearchMessagesResponse};
+use crate::types::{MessageNotification, UnreadNotification};
 use concordconfig::ConcordConfig;
 use concorddata::concord::DSContext;
+use concorddata::concord::{
+	Message as DataMessage, MessageType as DataMessageType, CHANNEL_KIND_ANNOUNCEMENT,
+	CHANNEL_PERMISSION_SEND, CHANNEL_PERMISSION_VIEW,
+};
 use concorderror::Error;
+use concordutil::librustlet;
+use librustlet::nioruntime_log;
+use librustlet::*;
+use nioruntime_log::*;
+use std::convert::TryInto;
 use std::sync::{Arc, RwLock};
 
+info!();
+
 pub fn get_messages(
-	_conn_info: &ConnectionInfo,
-	_ds_context: &DSContext,
-	_event: &Event,
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
 	_conn_manager: Arc<RwLock<ConnManager>>,
 	_config: &ConcordConfig,
 ) -> Result<bool, Error> {
+	let (channel_identifier, batch_num) = match &event.body {
+		EventBody::GetMessagesRequest(event) => (event.channel_identifier, event.batch_num),
+		_ => {
+			warn!(
+				"Malformed get messages event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+	let server_pubkey = channel_identifier.server_pubkey.to_bytes();
+	let server_id = channel_identifier.server_id.to_bytes();
+	let channel_id = channel_identifier.channel_id;
+
+	let permissions =
+		ds_context.channel_permissions(server_pubkey, server_id, channel_id, user_pubkey)?;
+	let (batch_num, messages): (u64, Vec<Message>) =
+		match permissions & CHANNEL_PERMISSION_VIEW != 0 {
+			true => {
+				let (batches, messages) =
+					ds_context.get_messages(server_pubkey, server_id, channel_id, batch_num)?;
+				(
+					std::cmp::min(batches, batch_num),
+					messages.into_iter().map(|m| m.into()).collect(),
+				)
+			}
+			false => {
+				info!(
+					"user does not have view permission in {:?}",
+					channel_identifier
+				);
+				(0, vec![])
+			}
+		};
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::GetMessagesResponse(GetMessagesResponse {
+			channel_identifier,
+			messages,
+			batch_num,
+		})
+		.into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn search_messages(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, Error> {
+	let (channel_identifier, query, cursor) = match &event.body {
+		EventBody::SearchMessagesRequest(event) => (
+			event.channel_identifier,
+			event.query.to_string(),
+			event.cursor.0.clone(),
+		),
+		_ => {
+			warn!(
+				"Malformed search messages event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let (messages, next_cursor) = match can_view(conn_info, ds_context, &channel_identifier)? {
+		true => ds_context.search_messages(
+			channel_identifier.server_pubkey.to_bytes(),
+			channel_identifier.server_id.to_bytes(),
+			channel_identifier.channel_id,
+			&query,
+			cursor,
+		)?,
+		false => (vec![], None),
+	};
+	let messages: Vec<Message> = messages.into_iter().map(|m| m.into()).collect();
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::SearchMessagesResponse(SearchMessagesResponse {
+			channel_identifier,
+			messages,
+			next_cursor: next_cursor.into(),
+		})
+		.into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
+	Ok(false)
+}
+
+pub fn export_channel(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, Error> {
+	let (channel_identifier, cursor) = match &event.body {
+		EventBody::ExportChannelRequest(event) => {
+			(event.channel_identifier, event.cursor.0.clone())
+		}
+		_ => {
+			warn!(
+				"Malformed export channel event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let (messages, next_cursor) = match can_view(conn_info, ds_context, &channel_identifier)? {
+		true => ds_context.export_messages(
+			channel_identifier.server_pubkey.to_bytes(),
+			channel_identifier.server_id.to_bytes(),
+			channel_identifier.channel_id,
+			cursor,
+		)?,
+		false => (vec![], None),
+	};
+	let messages: Vec<Message> = messages.into_iter().map(|m| m.into()).collect();
+
+	let event = Event {
+		request_id: event.request_id,
+		body: EventBody::ExportChannelResponse(ExportChannelResponse {
+			channel_identifier,
+			messages,
+			next_cursor: next_cursor.into(),
+		})
+		.into(),
+		..Default::default()
+	};
+
+	send!(conn_info.handle, event);
+
 	Ok(false)
 }
 
+fn can_view(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	channel_identifier: &ChannelIdentifier,
+) -> Result<bool, Error> {
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(false),
+	};
+	let permissions = ds_context.channel_permissions(
+		channel_identifier.server_pubkey.to_bytes(),
+		channel_identifier.server_id.to_bytes(),
+		channel_identifier.channel_id,
+		user_pubkey,
+	)?;
+	if permissions & CHANNEL_PERMISSION_VIEW == 0 {
+		info!(
+			"user does not have view permission in {:?}",
+			channel_identifier
+		);
+		return Ok(false);
+	}
+	Ok(true)
+}
+
 pub fn send_message(
-	_conn_info: &ConnectionInfo,
-	_ds_context: &DSContext,
-	_event: &Event,
-	_conn_manager: Arc<RwLock<ConnManager>>,
-	_config: &ConcordConfig,
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	subscriptions: &SubscriptionManager,
+	conn_manager: Arc<RwLock<ConnManager>>,
+	config: &ConcordConfig,
 ) -> Result<bool, Error> {
+	let message = match &event.body {
+		EventBody::SendMessage(event) => event.message.clone(),
+		_ => {
+			warn!(
+				"Malformed send message event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	// users may only post messages that they signed themselves
+	match &conn_info.pubkey {
+		Some(pubkey) => {
+			if *pubkey != message.user_pubkey() {
+				warn!("message not signed by the sender: {:?}", message);
+				return Ok(true);
+			}
+		}
+		None => return Ok(true),
+	}
+	let payload = match message.payload() {
+		Ok(payload) => payload,
+		Err(e) => {
+			warn!("invalid message signature: {}", e);
+			return Ok(true);
+		}
+	};
+
+	// messages signed by a linked device are refused once the user revokes the device
+	match message.device_certificate() {
+		Some(device_certificate) => {
+			if !ds_context.check_device_certificate(&device_certificate)? {
+				warn!("message signed by a revoked device: {:?}", message);
+				return Ok(true);
+			}
+		}
+		None => {}
+	}
+
+	let channel_identifier = message.channel_identifier();
+	let server_pubkey = channel_identifier.server_pubkey.to_bytes();
+	let server_id = channel_identifier.server_id.to_bytes();
+	let channel_id = channel_identifier.channel_id;
+	let user_pubkey = message.user_pubkey().to_bytes();
+
+	let permissions =
+		ds_context.channel_permissions(server_pubkey, server_id, channel_id, user_pubkey)?;
+	if permissions & CHANNEL_PERMISSION_SEND == 0 {
+		info!(
+			"user does not have send permission in {:?}",
+			channel_identifier
+		);
+		return Ok(false);
+	}
+
+	let announcement = match ds_context.get_channel(server_pubkey, server_id, channel_id)? {
+		Some(channel) => channel.kind == CHANNEL_KIND_ANNOUNCEMENT,
+		None => false,
+	};
+
+	publish_message(ds_context, subscriptions, message.clone(), payload.clone())?;
+
+	if announcement {
+		push_to_followers(
+			ds_context,
+			subscriptions,
+			conn_manager,
+			config,
+			&message,
+			payload,
+		)?;
+	}
+
 	Ok(false)
 }
 
+// store a verified message and send it to the subscribers of its channel.
+pub fn publish_message(
+	ds_context: &DSContext,
+	subscriptions: &SubscriptionManager,
+	message: Message,
+	payload: Vec<u8>,
+) -> Result<(), Error> {
+	let channel_identifier = message.channel_identifier();
+	ds_context.post_message(DataMessage {
+		payload,
+		signature: message.signature().0,
+		message_type: DataMessageType::Text,
+		server_pubkey: channel_identifier.server_pubkey.to_bytes(),
+		server_id: channel_identifier.server_id.to_bytes(),
+		channel_id: channel_identifier.channel_id,
+		timestamp: message.timestamp().try_into()?,
+		user_pubkey: message.user_pubkey().to_bytes(),
+		nonce: message.nonce(),
+		seqno: 0,
+		user_name: "".to_string(),
+		user_bio: "".to_string(),
+		device_certificate: message.device_certificate(),
+	})?;
+
+	notify_subscribers(ds_context, subscriptions, message)
+}
+
+// send a posted message to the subscribers of its channel that can still view it.
+fn notify_subscribers(
+	ds_context: &DSContext,
+	subscriptions: &SubscriptionManager,
+	message: Message,
+) -> Result<(), Error> {
+	let channel_identifier = message.channel_identifier();
+	let server_pubkey = channel_identifier.server_pubkey.to_bytes();
+	let server_id = channel_identifier.server_id.to_bytes();
+	let channel_id = channel_identifier.channel_id;
+
+	let notification = Event {
+		body: EventBody::MessageNotification(MessageNotification { message }).into(),
+		..Default::default()
+	};
+	let unread = Event {
+		body: EventBody::UnreadNotification(UnreadNotification { channel_identifier }).into(),
+		..Default::default()
+	};
+
+	for delivery in subscriptions.deliveries(&channel_identifier)? {
+		// permissions may have changed since the subscription was made
+		let permissions = ds_context.channel_permissions(
+			server_pubkey,
+			server_id,
+			channel_id,
+			delivery.user_pubkey,
+		)?;
+		if permissions & CHANNEL_PERMISSION_VIEW == 0 {
+			continue;
+		}
+
+		match delivery.notify_only {
+			true => send!(delivery.handle, unread),
+			false => send!(delivery.handle, notification),
+		}
+	}
+
+	Ok(())
+}
+
 pub fn subscribe_channel(
-	_conn_info: &ConnectionInfo,
-	_ds_context: &DSContext,
-	_event: &Event,
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+	subscriptions: &SubscriptionManager,
 	_conn_manager: Arc<RwLock<ConnManager>>,
 	_config: &ConcordConfig,
 ) -> Result<bool, Error> {
+	let channel_subscriptions = match &event.body {
+		EventBody::SubscribeChannel(event) => event.subscriptions.clone(),
+		_ => {
+			warn!(
+				"Malformed subscribe channel event. No event present: {:?}",
+				event
+			);
+			return Ok(true);
+		}
+	};
+
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+
+	for subscription in channel_subscriptions {
+		let channel_identifier = subscription.channel_identifier;
+		let permissions = match subscription.subscription_type {
+			SubscriptionActionType::UnSubscribe => CHANNEL_PERMISSION_VIEW,
+			_ => ds_context.channel_permissions(
+				channel_identifier.server_pubkey.to_bytes(),
+				channel_identifier.server_id.to_bytes(),
+				channel_identifier.channel_id,
+				user_pubkey,
+			)?,
+		};
+		if permissions & CHANNEL_PERMISSION_VIEW == 0 {
+			info!(
+				"user does not have view permission in {:?}",
+				channel_identifier
+			);
+			continue;
+		}
+
+		subscriptions.update(
+			&channel_identifier,
+			&subscription.subscription_type,
+			&conn_info.handle,
+			user_pubkey,
+		)?;
+	}
+
 	Ok(false)
 }
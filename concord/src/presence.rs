@@ -0,0 +1,483 @@
+// Copyright 2022 37 Miners, LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::send;
+use crate::types::{Event, EventBody, OnlineStatus, PresenceNotification};
+use concorddata::concord::DSContext;
+use concorddata::types::{Pubkey, ServerId};
+use concorderror::Error;
+use concordutil::librustlet;
+use librustlet::nioruntime_log;
+use librustlet::*;
+use nioruntime_log::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+info!();
+
+// a connection with no events for this long is considered idle.
+pub const IDLE_TIMEOUT: u128 = 1000 * 60 * 5;
+
+struct ConnectionPresence {
+	user_pubkey: [u8; 32],
+	last_activity: u128,
+	session: Option<u128>,
+}
+
+struct PresenceState {
+	// authenticated connections, keyed by connection id.
+	connections: HashMap<u128, ConnectionPresence>,
+	// the handle of each authenticated connection, keyed by connection id.
+	handles: HashMap<u128, ConnData>,
+	// the ids of each user's connections.
+	users: HashMap<[u8; 32], HashSet<u128>>,
+	// last status that was reported for each user with at least one connection.
+	statuses: HashMap<[u8; 32], OnlineStatus>,
+	// connections that want presence updates, keyed by (server_pubkey, server_id).
+	subscribers: HashMap<([u8; 32], [u8; 8]), HashMap<u128, ConnData>>,
+}
+
+impl PresenceState {
+	fn new() -> Self {
+		PresenceState {
+			connections: HashMap::new(),
+			handles: HashMap::new(),
+			users: HashMap::new(),
+			statuses: HashMap::new(),
+			subscribers: HashMap::new(),
+		}
+	}
+
+	// add a connection of the user. A connection with the same id is replaced.
+	fn add(&mut self, id: u128, user_pubkey: [u8; 32], session: Option<u128>, time_now: u128) {
+		self.remove(id);
+		self.connections.insert(
+			id,
+			ConnectionPresence {
+				user_pubkey,
+				last_activity: time_now,
+				session,
+			},
+		);
+		self.users.entry(user_pubkey).or_default().insert(id);
+	}
+
+	// record activity on a connection. Returns the user if the connection was idle.
+	fn touch(&mut self, id: u128, time_now: u128) -> Option<[u8; 32]> {
+		match self.connections.get_mut(&id) {
+			Some(connection) => {
+				let was_idle = time_now.saturating_sub(connection.last_activity) > IDLE_TIMEOUT;
+				connection.last_activity = time_now;
+				match was_idle {
+					true => Some(connection.user_pubkey),
+					false => None,
+				}
+			}
+			None => None,
+		}
+	}
+
+	// remove a connection and its handle. Returns the user of the connection.
+	fn remove(&mut self, id: u128) -> Option<[u8; 32]> {
+		self.handles.remove(&id);
+		let user_pubkey = self.connections.remove(&id)?.user_pubkey;
+		let empty = match self.users.get_mut(&user_pubkey) {
+			Some(ids) => {
+				ids.remove(&id);
+				ids.is_empty()
+			}
+			None => false,
+		};
+		if empty {
+			self.users.remove(&user_pubkey);
+		}
+		Some(user_pubkey)
+	}
+
+	// a user is online if any connection had activity within IDLE_TIMEOUT, idle if the user
+	// only has inactive connections and offline otherwise.
+	fn compute_status(&self, user_pubkey: [u8; 32], time_now: u128) -> OnlineStatus {
+		let mut status = OnlineStatus::Offline;
+		for id in self.users.get(&user_pubkey).into_iter().flatten() {
+			match self.connections.get(id) {
+				Some(connection) => {
+					if time_now.saturating_sub(connection.last_activity) <= IDLE_TIMEOUT {
+						return OnlineStatus::Online;
+					}
+					status = OnlineStatus::Idle;
+				}
+				None => {}
+			}
+		}
+		status
+	}
+
+	// returns the users whose status is no longer the one that was last reported.
+	fn changed(&self, time_now: u128) -> Vec<[u8; 32]> {
+		self.statuses
+			.iter()
+			.filter(|(user_pubkey, status)| {
+				self.compute_status(**user_pubkey, time_now) != **status
+			})
+			.map(|(user_pubkey, _)| *user_pubkey)
+			.collect()
+	}
+
+	// store the current status of the user. Returns the status that was reported before.
+	fn update(&mut self, user_pubkey: [u8; 32], time_now: u128) -> (OnlineStatus, OnlineStatus) {
+		let online_status = self.compute_status(user_pubkey, time_now);
+		let prev = match online_status {
+			OnlineStatus::Offline => self.statuses.remove(&user_pubkey),
+			_ => self.statuses.insert(user_pubkey, online_status.clone()),
+		}
+		.unwrap_or(OnlineStatus::Offline);
+		(prev, online_status)
+	}
+}
+
+#[derive(Clone)]
+pub struct PresenceManager {
+	state: Arc<RwLock<PresenceState>>,
+}
+
+impl Default for PresenceManager {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl PresenceManager {
+	pub fn new() -> Self {
+		PresenceManager {
+			state: Arc::new(RwLock::new(PresenceState::new())),
+		}
+	}
+
+	// register a connection that has just completed authentication, along with the session
+	// token it used if any.
+	pub fn connect(
+		&self,
+		handle: &ConnData,
+		user_pubkey: &Pubkey,
+		session: Option<u128>,
+		ds_context: &DSContext,
+	) -> Result<(), Error> {
+		let user_pubkey = user_pubkey.to_bytes();
+		{
+			let mut state = nioruntime_util::lockw!(self.state)?;
+			let id = handle.get_connection_id();
+			state.add(id, user_pubkey, session, now()?);
+			state.handles.insert(id, handle.clone());
+		}
+		self.update_status(user_pubkey, ds_context)
+	}
+
+	// record activity on a connection. An idle user becomes online again.
+	pub fn touch(&self, id: u128, ds_context: &DSContext) -> Result<(), Error> {
+		let user_pubkey = {
+			let mut state = nioruntime_util::lockw!(self.state)?;
+			state.touch(id, now()?)
+		};
+
+		match user_pubkey {
+			Some(user_pubkey) => self.update_status(user_pubkey, ds_context),
+			None => Ok(()),
+		}
+	}
+
+	// remove a closed connection and any presence subscriptions it held.
+	pub fn disconnect(&self, id: u128, ds_context: &DSContext) -> Result<(), Error> {
+		let user_pubkey = {
+			let mut state = nioruntime_util::lockw!(self.state)?;
+			for subscribers in state.subscribers.values_mut() {
+				subscribers.remove(&id);
+			}
+			state
+				.subscribers
+				.retain(|_, subscribers| !subscribers.is_empty());
+			state.remove(id)
+		};
+
+		match user_pubkey {
+			Some(user_pubkey) => self.update_status(user_pubkey, ds_context),
+			None => Ok(()),
+		}
+	}
+
+	// subscribe a connection to presence updates for the specified server.
+	pub fn subscribe(
+		&self,
+		server_pubkey: &Pubkey,
+		server_id: &ServerId,
+		handle: &ConnData,
+	) -> Result<(), Error> {
+		let mut state = nioruntime_util::lockw!(self.state)?;
+		state
+			.subscribers
+			.entry((server_pubkey.to_bytes(), server_id.to_bytes()))
+			.or_default()
+			.insert(handle.get_connection_id(), handle.clone());
+		Ok(())
+	}
+
+	// drop all presence subscriptions for a server that no longer exists.
+	pub fn remove_server(&self, server_pubkey: &Pubkey, server_id: &ServerId) -> Result<(), Error> {
+		let mut state = nioruntime_util::lockw!(self.state)?;
+		state
+			.subscribers
+			.remove(&(server_pubkey.to_bytes(), server_id.to_bytes()));
+		Ok(())
+	}
+
+	// returns the connections of every connected member of the specified server.
+	pub fn member_connections(
+		&self,
+		server_pubkey: &Pubkey,
+		server_id: &ServerId,
+		ds_context: &DSContext,
+	) -> Result<Vec<ConnData>, Error> {
+		let connections: Vec<([u8; 32], ConnData)> = {
+			let state = nioruntime_util::lockr!(self.state)?;
+			state
+				.connections
+				.iter()
+				.filter_map(|(id, c)| state.handles.get(id).map(|h| (c.user_pubkey, h.clone())))
+				.collect()
+		};
+
+		let mut ret = vec![];
+		for (user_pubkey, handle) in connections {
+			if ds_context
+				.get_server_member(server_pubkey.to_bytes(), server_id.to_bytes(), user_pubkey)?
+				.is_some()
+			{
+				ret.push(handle);
+			}
+		}
+		Ok(ret)
+	}
+
+	// returns the connections that authenticated with the specified session token.
+	pub fn session_connections(&self, session: u128) -> Result<Vec<ConnData>, Error> {
+		let state = nioruntime_util::lockr!(self.state)?;
+		Ok(state
+			.connections
+			.iter()
+			.filter(|(_, c)| c.session == Some(session))
+			.filter_map(|(id, _)| state.handles.get(id).cloned())
+			.collect())
+	}
+
+	// returns the connections of the specified user.
+	pub fn user_connections(&self, user_pubkey: &Pubkey) -> Result<Vec<ConnData>, Error> {
+		let user_pubkey = user_pubkey.to_bytes();
+		let state = nioruntime_util::lockr!(self.state)?;
+		Ok(state
+			.users
+			.get(&user_pubkey)
+			.into_iter()
+			.flatten()
+			.filter_map(|id| state.handles.get(id).cloned())
+			.collect())
+	}
+
+	// returns the number of members of the server that are online or idle.
+	pub fn online_member_count(
+		&self,
+		server_pubkey: &Pubkey,
+		server_id: &ServerId,
+		ds_context: &DSContext,
+	) -> Result<u64, Error> {
+		let users: Vec<[u8; 32]> = {
+			let state = nioruntime_util::lockr!(self.state)?;
+			state.statuses.keys().cloned().collect()
+		};
+
+		let mut count = 0;
+		for user_pubkey in users {
+			if ds_context
+				.get_server_member(server_pubkey.to_bytes(), server_id.to_bytes(), user_pubkey)?
+				.is_some()
+			{
+				count += 1;
+			}
+		}
+		Ok(count)
+	}
+
+	pub fn status(&self, user_pubkey: &Pubkey) -> Result<OnlineStatus, Error> {
+		let state = nioruntime_util::lockr!(self.state)?;
+		Ok(state.compute_status(user_pubkey.to_bytes(), now()?))
+	}
+
+	// called periodically to move users without recent activity to idle.
+	pub fn check_idle(&self, ds_context: &DSContext) -> Result<(), Error> {
+		let changed = {
+			let state = nioruntime_util::lockr!(self.state)?;
+			state.changed(now()?)
+		};
+
+		for user_pubkey in changed {
+			self.update_status(user_pubkey, ds_context)?;
+		}
+
+		Ok(())
+	}
+
+	// recompute the status of a user and, if it changed, notify the subscribers
+	// of every server that the user is a member of.
+	fn update_status(&self, user_pubkey: [u8; 32], ds_context: &DSContext) -> Result<(), Error> {
+		let (online_status, subscribers) = {
+			let mut state = nioruntime_util::lockw!(self.state)?;
+			let (prev, online_status) = state.update(user_pubkey, now()?);
+			if prev == online_status {
+				return Ok(());
+			}
+
+			let subscribers: Vec<(([u8; 32], [u8; 8]), Vec<ConnData>)> = state
+				.subscribers
+				.iter()
+				.map(|(k, v)| (*k, v.values().cloned().collect()))
+				.collect();
+			(online_status, subscribers)
+		};
+
+		debug!(
+			"presence of {:?} changed to {:?}",
+			Pubkey::from_bytes(user_pubkey),
+			online_status
+		);
+
+		for ((server_pubkey, server_id), handles) in subscribers {
+			if ds_context
+				.get_server_member(server_pubkey, server_id, user_pubkey)?
+				.is_none()
+			{
+				continue;
+			}
+
+			let event = Event {
+				body: EventBody::PresenceNotification(PresenceNotification {
+					server_pubkey: Pubkey::from_bytes(server_pubkey),
+					server_id: ServerId::from_bytes(server_id),
+					user_pubkey: Pubkey::from_bytes(user_pubkey),
+					online_status: online_status.clone(),
+				}),
+				..Default::default()
+			};
+
+			for handle in handles {
+				send!(handle, event);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+fn now() -> Result<u128, Error> {
+	Ok(std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)?
+		.as_millis())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_connect_touch_disconnect() {
+		let mut state = PresenceState::new();
+		let user = [1u8; 32];
+		assert_eq!(state.compute_status(user, 0), OnlineStatus::Offline);
+
+		state.add(1, user, None, 1000);
+		assert_eq!(
+			state.update(user, 1000),
+			(OnlineStatus::Offline, OnlineStatus::Online)
+		);
+		assert_eq!(
+			state.update(user, 1000),
+			(OnlineStatus::Online, OnlineStatus::Online)
+		);
+
+		// activity on an active connection doesn't change the status
+		assert_eq!(state.touch(1, 2000), None);
+		assert_eq!(state.touch(2, 2000), None);
+
+		assert_eq!(state.remove(1), Some(user));
+		assert_eq!(state.remove(1), None);
+		assert_eq!(
+			state.update(user, 2000),
+			(OnlineStatus::Online, OnlineStatus::Offline)
+		);
+		assert!(state.users.is_empty());
+		assert!(state.statuses.is_empty());
+	}
+
+	#[test]
+	fn test_idle_timeout() {
+		let mut state = PresenceState::new();
+		let user = [1u8; 32];
+		state.add(1, user, None, 1000);
+		state.update(user, 1000);
+
+		// idle once there has been no activity for longer than IDLE_TIMEOUT
+		assert!(state.changed(1000 + IDLE_TIMEOUT).is_empty());
+		let time_now = 1000 + IDLE_TIMEOUT + 1;
+		assert_eq!(state.changed(time_now), vec![user]);
+		assert_eq!(
+			state.update(user, time_now),
+			(OnlineStatus::Online, OnlineStatus::Idle)
+		);
+		assert!(state.changed(time_now).is_empty());
+
+		// activity brings an idle user back online
+		assert_eq!(state.touch(1, time_now + 1), Some(user));
+		assert_eq!(
+			state.update(user, time_now + 1),
+			(OnlineStatus::Idle, OnlineStatus::Online)
+		);
+	}
+
+	#[test]
+	fn test_multiple_connections() {
+		let mut state = PresenceState::new();
+		let user = [1u8; 32];
+		let other = [2u8; 32];
+		state.add(1, user, Some(10), 1000);
+		state.add(2, user, Some(20), 1000 + IDLE_TIMEOUT);
+		state.add(3, other, None, 1000);
+		assert_eq!(state.users[&user].len(), 2);
+
+		// one active connection keeps the user online
+		let time_now = 1000 + IDLE_TIMEOUT + 1;
+		assert_eq!(state.compute_status(user, time_now), OnlineStatus::Online);
+		assert_eq!(state.compute_status(other, time_now), OnlineStatus::Idle);
+
+		// the user stays connected until the last connection closes
+		state.remove(2);
+		assert_eq!(state.compute_status(user, time_now), OnlineStatus::Idle);
+		state.remove(1);
+		assert_eq!(state.compute_status(user, time_now), OnlineStatus::Offline);
+		assert!(!state.users.contains_key(&user));
+		assert_eq!(state.users[&other].len(), 1);
+
+		// a reused connection id moves to the new user
+		state.add(3, user, None, time_now);
+		assert!(!state.users.contains_key(&other));
+		assert_eq!(state.compute_status(user, time_now), OnlineStatus::Online);
+		assert_eq!(state.compute_status(other, time_now), OnlineStatus::Offline);
+	}
+}
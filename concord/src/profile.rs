@@ -35,7 +35,7 @@ use std::sync::{Arc, RwLock};
 
 debug!(); // set log level to debug
 
-fn get_avatar(
+pub fn get_avatar(
 	root_dir: String,
 	server_id: [u8; 8],
 	server_pubkey: [u8; 32],
@@ -119,6 +119,24 @@ class U32 {
         }
 }
 
+class U8 {
+	constructor(value) {
+		this.value = value;
+	}
+
+	serialize(value) {
+		var buffer = new Uint8Array(new ArrayBuffer(1));
+		buffer[0] = Number(value);
+		return buffer;
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new U8(buffer[offset]);
+		ret.offset = offset + 1;
+		return ret;
+	}
+}
+
 class U64 {
 	constructor(big_int) {
 		this.value = big_int;
@@ -358,6 +376,26 @@ class Signature {
 	}
 }
 
+// allows the device key to sign messages on behalf of the user
+class DeviceCertificate {
+	constructor() {
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new DeviceCertificate();
+		ret.user_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset = ret.user_pubkey.offset;
+		ret.device_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset = ret.device_pubkey.offset;
+		ret.timestamp = U128.prototype.deserialize(buffer, offset);
+		offset += 16;
+		ret.signature = Signature.prototype.deserialize(buffer, offset);
+		offset = ret.signature.offset;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
 // note that these must match with the server codes
 const EVENT_TYPE_AUTH                    = 0;
 const EVENT_TYPE_CHALLENGE               = 1;
@@ -387,6 +425,11 @@ const EVENT_TYPE_DELETE_INVITE_REQUEST   = 24;
 const EVENT_TYPE_DELETE_INVITE_RESPONSE  = 25;
 const EVENT_TYPE_SET_PROFILE_REQUEST     = 34;
 const EVENT_TYPE_SET_PROFILE_RESPONSE    = 35;
+const EVENT_TYPE_MESSAGE_NOTIFICATION    = 39;
+const EVENT_TYPE_PRESENCE_NOTIFICATION   = 41;
+const EVENT_TYPE_SERVER_DELETED_NOTIFICATION = 44;
+const EVENT_TYPE_UNREAD_NOTIFICATION     = 57;
+const EVENT_TYPE_JOIN_REQUEST_OUTCOME_NOTIFICATION = 82;
 
 const FIRST_EVENT_DATA = 23; // first byte of event data
 
@@ -472,7 +515,15 @@ class Invite {
                 offset += 8;
 		var invite_id = U128.prototype.deserialize(buffer, offset);
 		offset += 16;	
+		// skip the version byte
+		offset += 1;
+		var roles = U128.prototype.deserialize(buffer, offset);
+		offset += 16;
+		var landing_channel = U64.prototype.deserialize(buffer, offset);
+		offset += 8;
                 var ret = new Invite(invite_id, max, current, expiration, server_id, inviter);
+		ret.roles = roles;
+		ret.landing_channel = landing_channel;
                 ret.offset = offset;
                 return ret;
 	}
@@ -521,15 +572,17 @@ class DeleteInviteRequest {
 }
 
 class CreateInviteRequest {
-	constructor(server_id, server_pubkey, count, expiration) {
+	constructor(server_id, server_pubkey, count, expiration, roles = 0, landing_channel = 0) {
 		this.server_id = server_id;
 		this.server_pubkey = server_pubkey;
 		this.count = count;
 		this.expiration = expiration;
+		this.roles = roles;
+		this.landing_channel = landing_channel;
 	}
 
 	serialize(create_invite_request) {
-                var ret = new Uint8Array(new ArrayBuffer(80));
+                var ret = new Uint8Array(new ArrayBuffer(88));
                 for(var i=0; i<8; i++)
                         ret[i] = create_invite_request.server_id[i];
                 for(var i=0; i<32; i++)
@@ -540,6 +593,12 @@ class CreateInviteRequest {
 			ret[i+40] = count[i];
 		for(var i=0; i<16; i++)
 			ret[i+48] = expiration[i];
+		var roles = U128.prototype.serialize(create_invite_request.roles);
+		var landing_channel = U64.prototype.serialize(create_invite_request.landing_channel);
+		for(var i=0; i<16; i++)
+			ret[i+64] = roles[i];
+		for(var i=0; i<8; i++)
+			ret[i+80] = landing_channel[i];
                 return ret;	
 	}
 
@@ -590,9 +649,20 @@ class Member {
 		offset += 16;
 		ret.profile_seqno = U64.prototype.deserialize(buffer, offset);
 		offset += 8;
-		ret.online_status = buffer[offset] == 0;
+		// 0 = offline, 1 = online, 2 = idle
+		ret.online_status = buffer[offset];
 		offset += 1;
-		
+		ret.join_time = U64.prototype.deserialize(buffer, offset);
+		offset += 8;
+		ret.modified_time = U64.prototype.deserialize(buffer, offset);
+		offset += 8;
+		// the creator of the invite the member joined through, if any
+		if(buffer[offset] != 0) {
+			ret.inviter = Pubkey.prototype.deserialize(buffer, offset + 1);
+			offset += 33;
+		} else {
+			offset += 1;
+		}
 
 		ret.offset = offset;
 		return ret;
@@ -600,11 +670,11 @@ class Member {
 }
 
 class GetMembersResponse {
-	constuctor(members, server_id, server_pubkey, batch_num) {
+	constuctor(members, server_id, server_pubkey, next_cursor) {
 		this.members = members;
 		this.server_id = server_id;
 		this.server_pubkey = server_pubkey;
-		this.batch_num = batch_num;
+		this.next_cursor = next_cursor;
 	}
 
 	serialize() {
@@ -626,31 +696,60 @@ class GetMembersResponse {
 		offset += 8;
 		ret.server_pubkey = Pubkey.prototype.deserialize(buffer, offset);
 		offset += 32;
-		ret.batch_num = U64.prototype.deserialize(buffer, offset);
+		ret.next_cursor = SerOption.prototype.deserialize(buffer, offset, Icon.prototype);
+		offset = ret.next_cursor.offset;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
+class PresenceNotification {
+	constructor() {
+	}
+
+	serialize() {
+		throw "TODO: implement PresenceNotification.serialize";
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new PresenceNotification();
+		ret.server_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset += 32;
+		ret.server_id = ServerId.prototype.deserialize(buffer, offset);
 		offset += 8;
+		ret.user_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset += 32;
+		// 0 = offline, 1 = online, 2 = idle
+		ret.online_status = buffer[offset];
+		offset += 1;
 		ret.offset = offset;
 		return ret;
 	}
 }
 
 class GetMembersRequest {
-	constructor(server_id, server_pubkey, batch_num) {
+	// cursor is the next_cursor of a previous GetMembersResponse or a SerOption with no value
+	constructor(server_id, server_pubkey, cursor, limit) {
 		this.server_id = server_id;
 		this.server_pubkey = server_pubkey;
-		this.batch_num = batch_num;
+		this.cursor = cursor;
+		this.limit = limit;
 	}
 
 	serialize(get_members_request) {
 		var x = ServerId.prototype.serialize(get_members_request.server_id, ServerId.prototype);
  		var y = Pubkey.prototype.serialize(get_members_request.server_pubkey, Pubkey.prototype);
- 		var z = U64.prototype.serialize(get_members_request.batch_num);
- 		var ret = new Uint8Array(new ArrayBuffer(x.length + y.length + z.length));
+ 		var c = SerOption.prototype.serialize(get_members_request.cursor, Icon.prototype);
+ 		var z = U64.prototype.serialize(get_members_request.limit);
+ 		var ret = new Uint8Array(new ArrayBuffer(x.length + y.length + c.length + z.length));
  		for(var i=0; i<x.length; i++)
  			ret[i] = x[i];
  		for(var i=0; i<y.length; i++)
  			ret[i+x.length] = y[i];
+ 		for(var i=0; i<c.length; i++)
+ 			ret[i+x.length+y.length] = c[i];
  		for(var i=0; i<z.length; i++)
- 			ret[i+x.length+y.length] = z[i];
+ 			ret[i+x.length+y.length+c.length] = z[i];
  		return ret;
 	}
 
@@ -744,6 +843,33 @@ class AddChannelRequest {
 	}
 }
 
+class PermissionOverwrite {
+	// exactly one of role and user_pubkey is set
+	constructor(role, user_pubkey, allow, deny) {
+		this.role = role;
+		this.user_pubkey = user_pubkey;
+		this.allow = allow;
+		this.deny = deny;
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new PermissionOverwrite();
+		if(buffer[offset] == 0) {
+			ret.role = U128.prototype.deserialize(buffer, offset + 1);
+			offset = ret.role.offset;
+		} else {
+			ret.user_pubkey = Pubkey.prototype.deserialize(buffer, offset + 1);
+			offset = offset + 33;
+		}
+		ret.allow = U8.prototype.deserialize(buffer, offset);
+		offset = ret.allow.offset;
+		ret.deny = U8.prototype.deserialize(buffer, offset);
+		offset = ret.deny.offset;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
 class Channel {
 	constructor(channel_id, name, description, server_id, server_pubkey) {
 		this.channel_id = channel_id;
@@ -764,7 +890,55 @@ class Channel {
 		offset = name.offset;
 		var description = SerString.prototype.deserialize(buffer, offset);
 		offset = description.offset;
+		var position = U64.prototype.deserialize(buffer, offset);
+		offset = position.offset;
+		var category_id = U64.prototype.deserialize(buffer, offset);
+		offset = category_id.offset;
+		var len = U64.prototype.deserialize(buffer, offset).value;
+		offset += 8;
+		var overwrites = [];
+		for(var i=0; i<len; i++) {
+			var overwrite = PermissionOverwrite.prototype.deserialize(buffer, offset);
+			offset = overwrite.offset;
+			overwrites.push(overwrite);
+		}
+		var permissions = U8.prototype.deserialize(buffer, offset);
+		offset = permissions.offset;
+		// 0 = text, 1 = announcement
+		var kind = U8.prototype.deserialize(buffer, offset);
+		offset = kind.offset;
+		var post_roles = U128.prototype.deserialize(buffer, offset);
+		offset += 16;
+		var archived = buffer[offset] != 0;
+		offset += 1;
 		var ret = new Channel(channel_id, name, description);
+		ret.position = position;
+		ret.category_id = category_id;
+		ret.overwrites = overwrites;
+		ret.permissions = permissions;
+		ret.kind = kind;
+		ret.post_roles = post_roles;
+		ret.archived = archived;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
+class Category {
+	constructor(category_id, name, position) {
+		this.category_id = category_id;
+		this.name = name;
+		this.position = position;
+	}
+
+	deserialize(buffer, offset) {
+		var category_id = U64.prototype.deserialize(buffer, offset);
+		offset = category_id.offset;
+		var name = SerString.prototype.deserialize(buffer, offset);
+		offset = name.offset;
+		var position = U64.prototype.deserialize(buffer, offset);
+		offset = position.offset;
+		var ret = new Category(category_id, name, position);
 		ret.offset = offset;
 		return ret;
 	}
@@ -788,9 +962,18 @@ class GetChannelsResponse {
 			offset = channel.offset;
 			channels.push(channel);
 		}
+		var len = U64.prototype.deserialize(buffer, offset).value;
+		offset += 8;
+		var categories = [];
+		for(var i=0; i<len; i++) {
+			var category = Category.prototype.deserialize(buffer, offset);
+			offset = category.offset;
+			categories.push(category);
+		}
 
 		var ret = new GetChannelsResponse();
 		ret.channels = channels;
+		ret.categories = categories;
 		ret.server_pubkey = server_pubkey;
 		ret.server_id = server_id;
 		return ret;
@@ -798,17 +981,19 @@ class GetChannelsResponse {
 }
 
 class GetChannelsRequest {
-	constructor(server_id, server_pubkey) {
+	constructor(server_id, server_pubkey, include_archived = false) {
 		this.server_id = server_id;
 		this.server_pubkey = server_pubkey;
+		this.include_archived = include_archived;
 	}
 
 	serialize(get_channels_request) {
-		var ret = new Uint8Array(new ArrayBuffer(40));
+		var ret = new Uint8Array(new ArrayBuffer(41));
 		for(var i=0; i<8; i++)
 			ret[i] = get_channels_request.server_id[i];
 		for(var i=0; i<32; i++)
 			ret[i+8] = get_channels_request.server_pubkey[i];
+		ret[40] = get_channels_request.include_archived ? 1 : 0;
 		return ret;
 	}
 
@@ -837,33 +1022,171 @@ class DeleteServerEvent {
 	}
 }
 
+class ChannelIdentifier {
+	constructor(server_pubkey, server_id, channel_id) {
+		this.server_pubkey = server_pubkey;
+		this.server_id = server_id;
+		this.channel_id = channel_id;
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new ChannelIdentifier();
+		ret.server_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset += 32;
+		ret.server_id = ServerId.prototype.deserialize(buffer, offset);
+		offset += 8;
+		ret.channel_id = U64.prototype.deserialize(buffer, offset);
+		offset += 8;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
+class Message {
+	constructor() {
+	}
+
+	serialize() {
+		throw "TODO: implement Message.serialize";
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new Message();
+		ret.channel_identifier = ChannelIdentifier.prototype.deserialize(buffer, offset);
+		offset = ret.channel_identifier.offset;
+		ret.user_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset += 32;
+		// the payload is length prefixed bytes, the same as an icon
+		ret.payload = Icon.prototype.deserialize(buffer, offset);
+		offset = ret.payload.offset;
+		ret.timestamp = U128.prototype.deserialize(buffer, offset);
+		offset += 16;
+		ret.nonce = (buffer[offset] << 8) | buffer[offset + 1];
+		offset += 2;
+		ret.signature = Signature.prototype.deserialize(buffer, offset);
+		offset += 64;
+		// present if the message was signed by a linked device of the user
+		ret.device_certificate = SerOption.prototype.deserialize(buffer, offset, DeviceCertificate.prototype);
+		offset = ret.device_certificate.offset;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
+class MessageNotification {
+	constructor() {
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new MessageNotification();
+		ret.message = Message.prototype.deserialize(buffer, offset);
+		ret.offset = ret.message.offset;
+		return ret;
+	}
+}
+
+// sent instead of the message to connections that subscribed as notify only
+class UnreadNotification {
+	constructor() {
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new UnreadNotification();
+		ret.channel_identifier = ChannelIdentifier.prototype.deserialize(buffer, offset);
+		ret.offset = ret.channel_identifier.offset;
+		return ret;
+	}
+}
+
+class ServerDeletedNotification {
+	constructor() {
+	}
+
+	serialize() {
+		throw "TODO: implement ServerDeletedNotification.serialize";
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new ServerDeletedNotification();
+		ret.server_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset += 32;
+		ret.server_id = ServerId.prototype.deserialize(buffer, offset);
+		offset += 8;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
+class JoinRequestOutcomeNotification {
+	constructor() {
+	}
+
+	deserialize(buffer, offset) {
+		var ret = new JoinRequestOutcomeNotification();
+		ret.server_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset += 32;
+		ret.server_id = ServerId.prototype.deserialize(buffer, offset);
+		offset += 8;
+		ret.approved = U8.prototype.deserialize(buffer, offset).value != 0;
+		offset += 1;
+		ret.landing_channel = U64.prototype.deserialize(buffer, offset);
+		offset += 8;
+		ret.offset = offset;
+		return ret;
+	}
+}
+
 class ModifyServerEvent {
-	constructor(server_id, server_pubkey, name, icon) {
+	constructor(
+		server_id,
+		server_pubkey,
+		name,
+		icon,
+		description = new SerOption(),
+		topic = new SerOption(),
+		default_channel = new SerOption(),
+		rules = new SerOption(),
+		verification_level = new SerOption(),
+		require_approval = new SerOption(),
+	) {
 		this.server_id = server_id;
 		this.server_pubkey = server_pubkey;
 		this.name = name;
 		this.icon = icon;
+		this.description = description;
+		this.topic = topic;
+		this.default_channel = default_channel;
+		this.rules = rules;
+		this.verification_level = verification_level;
+		this.require_approval = require_approval;
 	}
 
 	serialize(modify_server_event) {
-		var w = SerOption.prototype.serialize(modify_server_event.name, SerString.prototype);
-		var x = SerOption.prototype.serialize(modify_server_event.icon, Icon.prototype);
-		var y = ServerId.prototype.serialize(modify_server_event.server_id, ServerId.prototype);
-		var z = Pubkey.prototype.serialize(modify_server_event.server_pubkey, Pubkey.prototype);
-
-		var ret = new Uint8Array(new ArrayBuffer(w.length + x.length + y.length + z.length));
-
-		for(var i=0; i<w.length; i++) {
-			ret[i] = w[i];
+		var parts = [
+			SerOption.prototype.serialize(modify_server_event.name, SerString.prototype),
+			SerOption.prototype.serialize(modify_server_event.icon, Icon.prototype),
+			ServerId.prototype.serialize(modify_server_event.server_id, ServerId.prototype),
+			Pubkey.prototype.serialize(modify_server_event.server_pubkey, Pubkey.prototype),
+			SerOption.prototype.serialize(modify_server_event.description, SerString.prototype),
+			SerOption.prototype.serialize(modify_server_event.topic, SerString.prototype),
+			SerOption.prototype.serialize(modify_server_event.default_channel, U64.prototype),
+			SerOption.prototype.serialize(modify_server_event.rules, SerString.prototype),
+			SerOption.prototype.serialize(modify_server_event.verification_level, U8.prototype),
+			SerOption.prototype.serialize(modify_server_event.require_approval, U8.prototype),
+		];
+
+		var len = 0;
+		for(var i=0; i<parts.length; i++) {
+			len += parts[i].length;
 		}
-		for(var i=0; i<x.length; i++) {
-			ret[i+w.length] = x[i];
-		}
-		for(var i=0; i<y.length; i++) {
-			ret[i+w.length + x.length] = y[i];
-		}
-		for(var i=0; i<z.length; i++) {
-			ret[i+w.length+x.length+y.length] = z[i];
+
+		var ret = new Uint8Array(new ArrayBuffer(len));
+		var offset = 0;
+		for(var i=0; i<parts.length; i++) {
+			for(var j=0; j<parts[i].length; j++) {
+				ret[offset+j] = parts[i][j];
+			}
+			offset += parts[i].length;
 		}
 
 		return ret;
@@ -914,18 +1237,23 @@ class GetServersEvent {
 }
 
 class ChallengeEvent {
-	constructor(challenge) {
+	constructor(challenge, server_pubkey, timestamp) {
 		this.challenge = challenge;
+		this.server_pubkey = server_pubkey;
+		this.timestamp = timestamp;
 	}
 
 	serialize(challenge_event) {
-		var ret = challenge_event.challenge.serialize(challenge_event.challenge);
-		return ret;
+		throw "TODO: implement ChallengeEvent.serialize";
 	}
 
 	deserialize(buffer, offset) {
 		var challenge_event = new ChallengeEvent();
-		challenge_event.challenge = U128.prototype.deserialize(buffer, offset);
+		challenge_event.challenge = buffer.slice(offset, offset + 32);
+		offset += 32;
+		challenge_event.server_pubkey = Pubkey.prototype.deserialize(buffer, offset);
+		offset += 32;
+		challenge_event.timestamp = U128.prototype.deserialize(buffer, offset);
 		challenge_event.offset = offset + 16;
 		return challenge_event;
 	}
@@ -967,7 +1295,9 @@ class AuthEvent {
 		var x = auth_event.signature.serialize(auth_event.signature, Signature.prototype);
 		var y = auth_event.token.serialize(auth_event.token, U128.prototype);
 		var z = auth_event.pubkey.serialize(auth_event.pubkey, Pubkey.prototype);
-		var ret = new ArrayBuffer(x.length + y.length + z.length);
+		// device certificate, the web client always signs with the user's own key
+		var w = [0];
+		var ret = new ArrayBuffer(x.length + y.length + z.length + w.length);
 		var ret = new Uint8Array(ret);
 		var offset = 0;
 		for(var i=0; i<x.length; i++) {
@@ -982,6 +1312,10 @@ class AuthEvent {
                         ret[offset] = z[i];
                         offset++;
                 }
+		for(var i=0; i<w.length; i++) {
+			ret[offset] = w[i];
+			offset++;
+		}
 
 		return ret;
 	}
@@ -999,12 +1333,32 @@ class AuthEvent {
 }
 
 class ServerInfo {
-	constructor(name, description, server_id, server_pubkey, seqno) {
+	constructor(
+		name,
+		description,
+		server_id,
+		server_pubkey,
+		seqno,
+		topic,
+		creation_time,
+		default_channel,
+		rules,
+		verification_level,
+		require_approval,
+		pow_difficulty,
+	) {
 		this.name = name;
 		this.description = description;
 		this.server_id = server_id;
 		this.server_pubkey = server_pubkey;
 		this.seqno = seqno;
+		this.topic = topic;
+		this.creation_time = creation_time;
+		this.default_channel = default_channel;
+		this.rules = rules;
+		this.verification_level = verification_level;
+		this.require_approval = require_approval;
+		this.pow_difficulty = pow_difficulty;
 	}
 }
 
@@ -1032,6 +1386,20 @@ class GetServersResponse {
 			offset = offset + 32;
 			var seqno = U64.prototype.deserialize(buffer, offset);
 			offset = seqno.offset;
+			var topic = SerString.prototype.deserialize(buffer, offset);
+			offset = topic.offset;
+			var creation_time = U128.prototype.deserialize(buffer, offset);
+			offset = creation_time.offset;
+			var default_channel = U64.prototype.deserialize(buffer, offset);
+			offset = default_channel.offset;
+			var rules = SerString.prototype.deserialize(buffer, offset);
+			offset = rules.offset;
+			var verification_level = U8.prototype.deserialize(buffer, offset);
+			offset = verification_level.offset;
+			var require_approval = U8.prototype.deserialize(buffer, offset);
+			offset = require_approval.offset;
+			var pow_difficulty = U8.prototype.deserialize(buffer, offset);
+			offset = pow_difficulty.offset;
 			servers_response.servers.push(
 				new ServerInfo(
 					name,
@@ -1039,6 +1407,13 @@ class GetServersResponse {
 					server_id,
 					server_pubkey,
 					seqno,
+					topic,
+					creation_time,
+					default_channel,
+					rules,
+					verification_level,
+					require_approval,
+					pow_difficulty,
 				)
 			);
 		}
@@ -1246,6 +1621,26 @@ class Event {
 			event.delete_invite_request = DeleteInviteRequest
 				.prototype
 				.deserialize(buffer, FIRST_EVENT_DATA);
+		} else if(event.event_type == EVENT_TYPE_PRESENCE_NOTIFICATION) {
+			event.presence_notification = PresenceNotification
+				.prototype
+				.deserialize(buffer, FIRST_EVENT_DATA);
+		} else if(event.event_type == EVENT_TYPE_SERVER_DELETED_NOTIFICATION) {
+			event.server_deleted_notification = ServerDeletedNotification
+				.prototype
+				.deserialize(buffer, FIRST_EVENT_DATA);
+		} else if(event.event_type == EVENT_TYPE_MESSAGE_NOTIFICATION) {
+			event.message_notification = MessageNotification
+				.prototype
+				.deserialize(buffer, FIRST_EVENT_DATA);
+		} else if(event.event_type == EVENT_TYPE_UNREAD_NOTIFICATION) {
+			event.unread_notification = UnreadNotification
+				.prototype
+				.deserialize(buffer, FIRST_EVENT_DATA);
+		} else if(event.event_type == EVENT_TYPE_JOIN_REQUEST_OUTCOME_NOTIFICATION) {
+			event.join_request_outcome_notification = JoinRequestOutcomeNotification
+				.prototype
+				.deserialize(buffer, FIRST_EVENT_DATA);
 		} else if(event.event_type == EVENT_TYPE_ADD_CHANNEL_RESPONSE ||
 			event.event_type == EVENT_TYPE_MODIFY_CHANNEL_RESPONSE ||
 			event.event_type == EVENT_TYPE_DELETE_CHANNEL_RESPONSE){
@@ -16,6 +16,8 @@ use crate::types::EventBody;
 use concordconfig::ConcordConfig;
 use concorddata::concord::DSContext;
 use concorddata::concord::ServerInfo as DataServerInfo;
+use concorddata::concord::ServerUpdate;
+use concorddata::pow::MAX_POW_DIFFICULTY;
 use concorderror::Error as ConcordError;
 use concordutil::librustlet;
 use librustlet::nioruntime_log;
@@ -25,8 +27,12 @@ use std::fs::File;
 use std::io::Read;
 use std::io::Write;
 
-use crate::types::{ConnectionInfo, Event, GetServersResponse, ServerInfo};
-use crate::{owner, send};
+use crate::presence::PresenceManager;
+use crate::types::{
+	ConnectionInfo, Event, GetServersResponse, ServerDeletedNotification, ServerInfo,
+	SetPowDifficultyResponse,
+};
+use crate::{host, moderator, owner, send};
 use concorddata::types::{Pubkey, ServerId};
 
 info!();
@@ -38,7 +44,7 @@ struct ServerInfoMin {
 	id: String,
 }
 
-fn _get_icon(
+pub fn get_icon(
 	server_id: [u8; 8],
 	pubkey: [u8; 32],
 	root_dir: String,
@@ -92,24 +98,82 @@ fn set_icon(
 	Ok(())
 }
 
+// remove the server icon and all member avatars of a deleted server.
+fn remove_server_files(
+	root_dir: String,
+	server_id: [u8; 8],
+	pubkey: [u8; 32],
+) -> Result<(), ConcordError> {
+	let server_id = ServerId::from_bytes(server_id).to_base58()?;
+	let pubkey = Pubkey::from_bytes(pubkey).to_base58()?;
+	let dir = format!("{}/www/images/user_images", root_dir);
+
+	let icon_name = format!("servers-{}-{}", server_id, pubkey);
+	let avatar_prefix = format!("avatars-{}-{}-", server_id, pubkey);
+	let entries = match std::fs::read_dir(&dir) {
+		Ok(entries) => entries,
+		// no images were ever uploaded
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(e.into()),
+	};
+	for entry in entries {
+		let entry = entry?;
+		let file_name = entry.file_name();
+		let file_name = file_name.to_string_lossy();
+		if file_name == icon_name || file_name.starts_with(&avatar_prefix) {
+			match std::fs::remove_file(entry.path()) {
+				Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+				_ => {}
+			}
+		}
+	}
+
+	Ok(())
+}
+
+// initialize this module. Finish any server and channel deletes that were interrupted and
+// index the names of members that joined before member search existed.
+pub fn init_server(cconfig: &ConcordConfig) -> Result<(), ConcordError> {
+	let ds_context = DSContext::new(cconfig.root_dir.clone())?;
+	ds_context.index_member_names()?;
+	for (server_id, server_pubkey) in ds_context.resume_server_deletes()? {
+		remove_server_files(cconfig.root_dir.clone(), server_id, server_pubkey)?;
+	}
+	ds_context.resume_channel_deletes()?;
+	Ok(())
+}
+
 pub fn get_servers(
 	conn_info: &ConnectionInfo,
 	ds_context: &DSContext,
 ) -> Result<bool, ConcordError> {
-	owner!(conn_info);
+	let user_pubkey = match &conn_info.pubkey {
+		Some(pubkey) => pubkey.to_bytes(),
+		None => return Ok(true),
+	};
+	// the host sees every server, co-owners only see the servers they own
+	let data = match user_pubkey == pubkey!() {
+		true => ds_context.get_servers()?,
+		false => ds_context.get_owned_servers(user_pubkey)?,
+	};
 
 	let mut servers = vec![];
-	let data = ds_context.get_servers()?;
-
 	let now = std::time::Instant::now();
 	for d in data {
 		servers.push(ServerInfo {
 			name: d.name.into(),
-			description: "none".into(),
+			description: d.description.into(),
 			//icon: get_icon(d.server_id, d.pubkey)?.into(),
 			server_id: d.server_id.into(),
 			server_pubkey: Pubkey::from_bytes(d.pubkey),
 			seqno: d.seqno,
+			topic: d.topic.into(),
+			creation_time: d.creation_time,
+			default_channel: d.default_channel,
+			rules: d.rules.into(),
+			verification_level: d.verification_level,
+			require_approval: d.require_approval,
+			pow_difficulty: d.pow_difficulty,
 		});
 	}
 	error!(
@@ -139,7 +203,7 @@ pub fn create_server(
 	event: &Event,
 	config: &ConcordConfig,
 ) -> Result<bool, ConcordError> {
-	owner!(conn_info);
+	host!(conn_info);
 
 	let (icon, name) = match &event.body {
 		EventBody::CreateServerEvent(body) => (body.icon.clone(), body.name.data.clone()),
@@ -155,6 +219,16 @@ pub fn create_server(
 		name,
 		joined: true,
 		seqno: 1,
+		description: "".to_string(),
+		topic: "".to_string(),
+		creation_time: std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis(),
+		default_channel: 0,
+		rules: "".to_string(),
+		verification_level: 0,
+		require_approval: false,
+		pow_difficulty: 0,
 	};
 
 	let server_id = ds_context.add_server(data_server_info, None, None, false)?;
@@ -170,9 +244,9 @@ pub fn delete_server(
 	conn_info: &ConnectionInfo,
 	ds_context: &DSContext,
 	event: &Event,
+	config: &ConcordConfig,
+	presence: &PresenceManager,
 ) -> Result<bool, ConcordError> {
-	owner!(conn_info);
-
 	let (server_id, server_pubkey) = match &event.body {
 		EventBody::DeleteServerEvent(event) => {
 			(event.server_id.to_bytes(), event.server_pubkey.to_bytes())
@@ -186,7 +260,31 @@ pub fn delete_server(
 		}
 	};
 
-	ds_context.delete_server(server_id, server_pubkey)?;
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
+	// find connected members before the member records are gone
+	let server_pubkey = Pubkey::from_bytes(server_pubkey);
+	let server_id = ServerId::from_bytes(server_id);
+	let handles = presence.member_connections(&server_pubkey, &server_id, ds_context)?;
+
+	ds_context.delete_server(server_id.to_bytes(), server_pubkey.to_bytes())?;
+	remove_server_files(
+		config.root_dir.clone(),
+		server_id.to_bytes(),
+		server_pubkey.to_bytes(),
+	)?;
+	presence.remove_server(&server_pubkey, &server_id)?;
+
+	let event = Event {
+		body: EventBody::ServerDeletedNotification(ServerDeletedNotification {
+			server_pubkey,
+			server_id,
+		}),
+		..Default::default()
+	};
+	for handle in handles {
+		send!(handle, event);
+	}
 
 	Ok(false)
 }
@@ -197,14 +295,21 @@ pub fn modify_server(
 	event: &Event,
 	config: &ConcordConfig,
 ) -> Result<bool, ConcordError> {
-	owner!(conn_info);
-
-	let (server_id, server_pubkey, name, icon) = match &event.body {
+	let (server_id, server_pubkey, icon, update) = match &event.body {
 		EventBody::ModifyServerEvent(event) => (
 			event.server_id.to_bytes(),
 			event.server_pubkey.to_bytes(),
-			event.name.clone(),
 			event.icon.clone(),
+			ServerUpdate {
+				name: event.name.0.as_ref().map(|x| x.to_string()),
+				description: event.description.0.as_ref().map(|x| x.to_string()),
+				topic: event.topic.0.as_ref().map(|x| x.to_string()),
+				default_channel: event.default_channel.0,
+				rules: event.rules.0.as_ref().map(|x| x.to_string()),
+				verification_level: event.verification_level.0,
+				require_approval: event.require_approval.0.map(|x| x != 0),
+				pow_difficulty: None,
+			},
 		),
 		_ => {
 			warn!(
@@ -215,6 +320,8 @@ pub fn modify_server(
 		}
 	};
 
+	owner!(conn_info, ds_context, server_pubkey, server_id);
+
 	match icon.0 {
 		Some(icon) => {
 			set_icon(config.root_dir.clone(), server_id, server_pubkey, icon.data)?;
@@ -222,10 +329,51 @@ pub fn modify_server(
 		None => {}
 	}
 
-	match name.0 {
-		Some(name) => ds_context.modify_server(server_id, server_pubkey, name.to_string())?,
-		None => {}
+	// always update so that seqno changes, even if only the icon was modified
+	ds_context.modify_server(server_id, server_pubkey, update)?;
+
+	Ok(false)
+}
+
+// moderators may raise the proof of work of invites when the server is under attack.
+pub fn set_pow_difficulty(
+	conn_info: &ConnectionInfo,
+	ds_context: &DSContext,
+	event: &Event,
+) -> Result<bool, ConcordError> {
+	let request_id = event.request_id;
+	let (server_pubkey, server_id, difficulty) = match &event.body {
+		EventBody::SetPowDifficultyRequest(event) => (
+			event.server_pubkey.to_bytes(),
+			event.server_id.to_bytes(),
+			event.difficulty,
+		),
+		_ => {
+			warn!("Malformed set pow difficulty event: {:?}", event);
+			return Ok(true);
+		}
+	};
+
+	moderator!(conn_info, ds_context, server_pubkey, server_id);
+
+	let success = difficulty <= MAX_POW_DIFFICULTY;
+	if success {
+		ds_context.modify_server(
+			server_id,
+			server_pubkey,
+			ServerUpdate {
+				pow_difficulty: Some(difficulty),
+				..Default::default()
+			},
+		)?;
 	}
 
+	let event = Event {
+		request_id,
+		body: EventBody::SetPowDifficultyResponse(SetPowDifficultyResponse { success }),
+		..Default::default()
+	};
+	send!(conn_info.handle, event);
+
 	Ok(false)
 }
@@ -0,0 +1,142 @@
+// Copyright 2022 37 Miners, LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::types::{ChannelIdentifier, SubscriptionActionType};
+use concorderror::Error;
+use concordutil::librustlet;
+use librustlet::*;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// (server_pubkey, server_id, channel_id)
+type ChannelKey = ([u8; 32], [u8; 8], u64);
+
+struct Subscriber {
+	handle: ConnData,
+	user_pubkey: [u8; 32],
+	notify_only: bool,
+}
+
+// connections that receive the messages of a channel, keyed by connection id.
+#[derive(Clone)]
+pub struct SubscriptionManager {
+	channels: Arc<RwLock<HashMap<ChannelKey, HashMap<u128, Subscriber>>>>,
+}
+
+impl Default for SubscriptionManager {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+// a connection that should be sent a message. If notify_only is set only a notification
+// that there is a new message is sent.
+pub struct Delivery {
+	pub handle: ConnData,
+	pub user_pubkey: [u8; 32],
+	pub notify_only: bool,
+}
+
+impl SubscriptionManager {
+	pub fn new() -> Self {
+		SubscriptionManager {
+			channels: Arc::new(RwLock::new(HashMap::new())),
+		}
+	}
+
+	pub fn update(
+		&self,
+		channel_identifier: &ChannelIdentifier,
+		action: &SubscriptionActionType,
+		handle: &ConnData,
+		user_pubkey: [u8; 32],
+	) -> Result<(), Error> {
+		let key = channel_key(channel_identifier);
+		let id = handle.get_connection_id();
+		let mut channels = nioruntime_util::lockw!(self.channels)?;
+		match action {
+			SubscriptionActionType::UnSubscribe => {
+				let empty = match channels.get_mut(&key) {
+					Some(subscribers) => {
+						subscribers.remove(&id);
+						subscribers.is_empty()
+					}
+					None => false,
+				};
+				if empty {
+					channels.remove(&key);
+				}
+			}
+			_ => {
+				let notify_only = matches!(action, SubscriptionActionType::NotifyOnly);
+				channels.entry(key).or_default().insert(
+					id,
+					Subscriber {
+						handle: handle.clone(),
+						user_pubkey,
+						notify_only,
+					},
+				);
+			}
+		}
+		Ok(())
+	}
+
+	// remove all subscriptions of a closed connection.
+	pub fn disconnect(&self, id: u128) -> Result<(), Error> {
+		let mut channels = nioruntime_util::lockw!(self.channels)?;
+		for subscribers in channels.values_mut() {
+			subscribers.remove(&id);
+		}
+		channels.retain(|_, subscribers| !subscribers.is_empty());
+		Ok(())
+	}
+
+	// returns the subscribers of a channel. Notify only subscriptions are removed since they
+	// are only notified once.
+	pub fn deliveries(
+		&self,
+		channel_identifier: &ChannelIdentifier,
+	) -> Result<Vec<Delivery>, Error> {
+		let key = channel_key(channel_identifier);
+		let mut channels = nioruntime_util::lockw!(self.channels)?;
+		let mut ret = vec![];
+		let empty = match channels.get_mut(&key) {
+			Some(subscribers) => {
+				for subscriber in subscribers.values() {
+					ret.push(Delivery {
+						handle: subscriber.handle.clone(),
+						user_pubkey: subscriber.user_pubkey,
+						notify_only: subscriber.notify_only,
+					});
+				}
+				subscribers.retain(|_, subscriber| !subscriber.notify_only);
+				subscribers.is_empty()
+			}
+			None => false,
+		};
+		if empty {
+			channels.remove(&key);
+		}
+		Ok(ret)
+	}
+}
+
+fn channel_key(channel_identifier: &ChannelIdentifier) -> ChannelKey {
+	(
+		channel_identifier.server_pubkey.to_bytes(),
+		channel_identifier.server_id.to_bytes(),
+		channel_identifier.channel_id,
+	)
+}
@@ -13,9 +13,11 @@
 // limitations under the License.
 
 use crate::librustlet::ConnData;
+use concorddata::concord::{InviteUse, JoinRequest, PermissionOverwrite, SessionInfo};
 use concorddata::ser::{serialize_default, Readable, Reader, Writeable, Writer};
 use concorddata::types::{
-	Image, Invite, ProfileData, Pubkey, SerOption, SerString, ServerId, Signature, U128,
+	DeviceCertificate, DeviceRevocation, Image, Invite, PageCursor, ProfileData, Pubkey, SerOption,
+	SerString, ServerId, Signature, U128,
 };
 use concorderror::{Error, ErrorKind};
 use concordutil::nioruntime_log;
@@ -32,6 +34,11 @@ const PROTOCOL_VERSION: u8 = 1;
 pub struct ConnectionInfo {
 	pub handle: ConnData,
 	pub pubkey: Option<Pubkey>,
+	// the session token the connection authenticated with, if any
+	pub session: Option<u128>,
+	// the challenge issued when the connection opened, consumed from the DSContext when it
+	// is answered
+	pub challenge: Option<[u8; 32]>,
 }
 
 #[derive(Debug, Clone)]
@@ -66,6 +73,13 @@ pub struct ModifyServerEvent {
 	pub icon: SerOption<Image>,
 	pub server_id: ServerId,
 	pub server_pubkey: Pubkey,
+	pub description: SerOption<SerString>,
+	pub topic: SerOption<SerString>,
+	pub default_channel: SerOption<u64>,
+	pub rules: SerOption<SerString>,
+	pub verification_level: SerOption<u8>,
+	// 1 to require moderator approval of new members, 0 to let invites join directly
+	pub require_approval: SerOption<u8>,
 }
 
 impl Writeable for ModifyServerEvent {
@@ -74,6 +88,12 @@ impl Writeable for ModifyServerEvent {
 		Writeable::write(&self.icon, writer)?;
 		Writeable::write(&self.server_id, writer)?;
 		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.description, writer)?;
+		Writeable::write(&self.topic, writer)?;
+		Writeable::write(&self.default_channel, writer)?;
+		Writeable::write(&self.rules, writer)?;
+		Writeable::write(&self.verification_level, writer)?;
+		Writeable::write(&self.require_approval, writer)?;
 		Ok(())
 	}
 }
@@ -84,12 +104,24 @@ impl Readable for ModifyServerEvent {
 		let icon = SerOption::read(reader)?;
 		let server_id = ServerId::read(reader)?;
 		let server_pubkey = Pubkey::read(reader)?;
+		let description = SerOption::read(reader)?;
+		let topic = SerOption::read(reader)?;
+		let default_channel = SerOption::read(reader)?;
+		let rules = SerOption::read(reader)?;
+		let verification_level = SerOption::read(reader)?;
+		let require_approval = SerOption::read(reader)?;
 
 		Ok(Self {
 			server_id,
 			name,
 			icon,
 			server_pubkey,
+			description,
+			topic,
+			default_channel,
+			rules,
+			verification_level,
+			require_approval,
 		})
 	}
 }
@@ -130,6 +162,14 @@ pub struct Channel {
 	pub name: SerString,
 	pub description: SerString,
 	pub channel_id: u64,
+	pub position: u64,
+	pub category_id: u64,
+	pub overwrites: Vec<PermissionOverwrite>,
+	// the permissions of the requesting user in this channel
+	pub permissions: u8,
+	pub kind: u8,
+	pub post_roles: u128,
+	pub archived: bool,
 }
 
 impl Writeable for Channel {
@@ -137,6 +177,19 @@ impl Writeable for Channel {
 		writer.write_u64(self.channel_id)?;
 		Writeable::write(&self.name, writer)?;
 		Writeable::write(&self.description, writer)?;
+		writer.write_u64(self.position)?;
+		writer.write_u64(self.category_id)?;
+		writer.write_u64(self.overwrites.len().try_into()?)?;
+		for overwrite in &self.overwrites {
+			Writeable::write(overwrite, writer)?;
+		}
+		writer.write_u8(self.permissions)?;
+		writer.write_u8(self.kind)?;
+		writer.write_u128(self.post_roles)?;
+		match self.archived {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
 		Ok(())
 	}
 }
@@ -146,10 +199,60 @@ impl Readable for Channel {
 		let channel_id = reader.read_u64()?;
 		let name = SerString::read(reader)?;
 		let description = SerString::read(reader)?;
+		let position = reader.read_u64()?;
+		let category_id = reader.read_u64()?;
+		let len = reader.read_u64()?;
+		let mut overwrites = vec![];
+		for _ in 0..len {
+			overwrites.push(PermissionOverwrite::read(reader)?);
+		}
+		let permissions = reader.read_u8()?;
+		let kind = reader.read_u8()?;
+		let post_roles = reader.read_u128()?;
+		let archived = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
 		Ok(Self {
 			channel_id,
 			name,
 			description,
+			position,
+			category_id,
+			overwrites,
+			permissions,
+			kind,
+			post_roles,
+			archived,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Category {
+	pub name: SerString,
+	pub category_id: u64,
+	pub position: u64,
+}
+
+impl Writeable for Category {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u64(self.category_id)?;
+		Writeable::write(&self.name, writer)?;
+		writer.write_u64(self.position)?;
+		Ok(())
+	}
+}
+
+impl Readable for Category {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let category_id = reader.read_u64()?;
+		let name = SerString::read(reader)?;
+		let position = reader.read_u64()?;
+		Ok(Self {
+			category_id,
+			name,
+			position,
 		})
 	}
 }
@@ -339,6 +442,7 @@ pub struct GetChannelsResponse {
 	pub channels: Vec<Channel>,
 	pub server_id: ServerId,
 	pub server_pubkey: Pubkey,
+	pub categories: Vec<Category>,
 }
 
 impl Writeable for GetChannelsResponse {
@@ -349,6 +453,10 @@ impl Writeable for GetChannelsResponse {
 		for channel in &self.channels {
 			Writeable::write(&channel, writer)?;
 		}
+		writer.write_u64(self.categories.len().try_into()?)?;
+		for category in &self.categories {
+			Writeable::write(&category, writer)?;
+		}
 		Ok(())
 	}
 }
@@ -362,11 +470,17 @@ impl Readable for GetChannelsResponse {
 		for _ in 0..len {
 			channels.push(Channel::read(reader)?);
 		}
+		let len = reader.read_u64()?;
+		let mut categories = vec![];
+		for _ in 0..len {
+			categories.push(Category::read(reader)?);
+		}
 
 		Ok(Self {
 			channels,
 			server_id,
 			server_pubkey,
+			categories,
 		})
 	}
 }
@@ -374,12 +488,18 @@ impl Readable for GetChannelsResponse {
 pub struct GetChannelsRequest {
 	pub server_id: ServerId,
 	pub server_pubkey: Pubkey,
+	// archived channels are only listed if this is set
+	pub include_archived: bool,
 }
 
 impl Writeable for GetChannelsRequest {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
 		Writeable::write(&self.server_id, writer)?;
 		Writeable::write(&self.server_pubkey, writer)?;
+		match self.include_archived {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
 		Ok(())
 	}
 }
@@ -388,10 +508,15 @@ impl Readable for GetChannelsRequest {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
 		let server_id = ServerId::read(reader)?;
 		let server_pubkey = Pubkey::read(reader)?;
+		let include_archived = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
 
 		Ok(Self {
 			server_id,
 			server_pubkey,
+			include_archived,
 		})
 	}
 }
@@ -511,6 +636,8 @@ impl Readable for MessageBody {
 pub struct Message {
 	body: MessageBody,
 	signature: Signature,
+	// present if the message was signed by a linked device of the user
+	device_certificate: Option<DeviceCertificate>,
 }
 
 impl Message {
@@ -537,7 +664,43 @@ impl Message {
 		let signature = secret_key
 			.sign(&body.build_message()?, &user_pubkey.to_dalek()?)
 			.into();
-		Ok(Self { body, signature })
+		Ok(Self {
+			body,
+			signature,
+			device_certificate: None,
+		})
+	}
+
+	// create a message on behalf of the user named in the certificate, signed with the
+	// secret key of the linked device.
+	pub fn new_from_device(
+		channel_identifier: ChannelIdentifier,
+		payload: Vec<u8>,
+		message_type: MessageType,
+		timestamp: u128,
+		nonce: u16,
+		secret_key: ExpandedSecretKey,
+		device_certificate: DeviceCertificate,
+	) -> Result<Self, Error> {
+		let device_pubkey: PublicKey = (&secret_key).into();
+
+		let body = MessageBody {
+			channel_identifier,
+			user_pubkey: device_certificate.user_pubkey,
+			payload,
+			message_type,
+			timestamp,
+			nonce,
+		};
+
+		let signature = secret_key
+			.sign(&body.build_message()?, &device_pubkey)
+			.into();
+		Ok(Self {
+			body,
+			signature,
+			device_certificate: Some(device_certificate),
+		})
 	}
 
 	pub fn payload(&self) -> Result<Vec<u8>, Error> {
@@ -561,20 +724,67 @@ impl Message {
 		self.body.timestamp
 	}
 
+	pub fn nonce(&self) -> u16 {
+		self.body.nonce
+	}
+
+	pub fn signature(&self) -> Signature {
+		self.signature.clone()
+	}
+
+	pub fn device_certificate(&self) -> Option<DeviceCertificate> {
+		self.device_certificate.clone()
+	}
+
 	pub fn verify(&self) -> Result<(), Error> {
 		let message = self.body.build_message()?;
-		self.body
-			.user_pubkey
+		let signer = match &self.device_certificate {
+			Some(device_certificate) => {
+				if device_certificate.user_pubkey != self.body.user_pubkey {
+					return Err(ErrorKind::InvalidSignatureError(
+						"device certificate is for another user".to_string(),
+					)
+					.into());
+				}
+				device_certificate.verify()?;
+				device_certificate.device_pubkey
+			}
+			None => self.body.user_pubkey,
+		};
+		signer
 			.to_dalek()?
 			.verify(&message, &self.signature.to_dalek()?)?;
 		Ok(())
 	}
 }
 
+impl From<concorddata::concord::Message> for Message {
+	fn from(message: concorddata::concord::Message) -> Self {
+		let body = MessageBody {
+			channel_identifier: ChannelIdentifier {
+				server_pubkey: Pubkey::from_bytes(message.server_pubkey),
+				server_id: ServerId::from_bytes(message.server_id),
+				channel_id: message.channel_id,
+			},
+			user_pubkey: Pubkey::from_bytes(message.user_pubkey),
+			payload: message.payload,
+			message_type: MessageType::Text,
+			timestamp: message.timestamp.into(),
+			nonce: message.nonce,
+		};
+		Self {
+			body,
+			signature: Signature(message.signature),
+			device_certificate: message.device_certificate,
+		}
+	}
+}
+
 impl Writeable for Message {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
 		Writeable::write(&self.body, writer)?;
 		Writeable::write(&self.signature, writer)?;
+		Writeable::write(&SerOption(self.device_certificate.clone()), writer)?;
 		Ok(())
 	}
 }
@@ -583,7 +793,12 @@ impl Readable for Message {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
 		let body = MessageBody::read(reader)?;
 		let signature = Signature::read(reader)?;
-		Ok(Self { body, signature })
+		let device_certificate = SerOption::read(reader)?.0;
+		Ok(Self {
+			body,
+			signature,
+			device_certificate,
+		})
 	}
 }
 
@@ -754,6 +969,27 @@ impl Readable for MessageNotification {
 	}
 }
 
+// sent to NotifyOnly subscribers of a channel when a message is posted
+#[derive(Debug, Clone)]
+pub struct UnreadNotification {
+	pub channel_identifier: ChannelIdentifier,
+}
+
+impl Writeable for UnreadNotification {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.channel_identifier, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for UnreadNotification {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		Ok(Self {
+			channel_identifier: ChannelIdentifier::read(reader)?,
+		})
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct SubscribeChannel {
 	pub subscriptions: Vec<ChannelSubscription>,
@@ -789,6 +1025,10 @@ pub struct CreateInviteRequest {
 	pub server_pubkey: Pubkey,
 	pub count: u64,
 	pub expiration: u128,
+	// roles given to members that join through the invite, in addition to AUTH_FLAG_MEMBER
+	pub roles: u128,
+	// channel the member is given access to and lands in, 0 for none
+	pub landing_channel: u64,
 }
 
 impl Writeable for CreateInviteRequest {
@@ -797,6 +1037,8 @@ impl Writeable for CreateInviteRequest {
 		Writeable::write(&self.server_pubkey, writer)?;
 		writer.write_u64(self.count)?;
 		writer.write_u128(self.expiration)?;
+		writer.write_u128(self.roles)?;
+		writer.write_u64(self.landing_channel)?;
 		Ok(())
 	}
 }
@@ -807,11 +1049,15 @@ impl Readable for CreateInviteRequest {
 		let server_pubkey = Pubkey::read(reader)?;
 		let count = reader.read_u64()?;
 		let expiration = reader.read_u128()?;
+		let roles = reader.read_u128()?;
+		let landing_channel = reader.read_u64()?;
 		Ok(Self {
 			server_id,
 			server_pubkey,
 			count,
 			expiration,
+			roles,
+			landing_channel,
 		})
 	}
 }
@@ -1002,11 +1248,14 @@ impl Readable for DeleteInviteResponse {
 #[derive(Debug, Clone)]
 pub struct JoinServerRequest {
 	pub invite_url: SerString,
+	// the proof of work difficulty to solve before accepting, as reported by the server
+	pub pow_difficulty: u8,
 }
 
 impl Writeable for JoinServerRequest {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
 		Writeable::write(&self.invite_url, writer)?;
+		writer.write_u8(self.pow_difficulty)?;
 		Ok(())
 	}
 }
@@ -1014,8 +1263,12 @@ impl Writeable for JoinServerRequest {
 impl Readable for JoinServerRequest {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
 		let invite_url = SerString::read(reader)?;
+		let pow_difficulty = reader.read_u8()?;
 
-		Ok(Self { invite_url })
+		Ok(Self {
+			invite_url,
+			pow_difficulty,
+		})
 	}
 }
 
@@ -1072,6 +1325,12 @@ pub struct InviteResponseInfo {
 	pub server_name: SerString,
 	pub current_members: u64,
 	pub online_members: u64,
+	pub server_description: SerString,
+	pub server_topic: SerString,
+	pub server_rules: SerString,
+	pub server_creation_time: u128,
+	pub verification_level: u8,
+	pub pow_difficulty: u8,
 }
 
 #[derive(Debug, Clone)]
@@ -1090,6 +1349,12 @@ impl Writeable for ViewInviteResponse {
 				writer.write_u64(rinfo.current_members)?;
 				writer.write_u64(rinfo.online_members)?;
 				Writeable::write(&rinfo.server_name, writer)?;
+				Writeable::write(&rinfo.server_description, writer)?;
+				Writeable::write(&rinfo.server_topic, writer)?;
+				Writeable::write(&rinfo.server_rules, writer)?;
+				writer.write_u128(rinfo.server_creation_time)?;
+				writer.write_u8(rinfo.verification_level)?;
+				writer.write_u8(rinfo.pow_difficulty)?;
 			}
 			None => writer.write_u8(0)?,
 		}
@@ -1108,6 +1373,12 @@ impl Readable for ViewInviteResponse {
 				let current_members = reader.read_u64()?;
 				let online_members = reader.read_u64()?;
 				let server_name = SerString::read(reader)?;
+				let server_description = SerString::read(reader)?;
+				let server_topic = SerString::read(reader)?;
+				let server_rules = SerString::read(reader)?;
+				let server_creation_time = reader.read_u128()?;
+				let verification_level = reader.read_u8()?;
+				let pow_difficulty = reader.read_u8()?;
 
 				let rinfo = InviteResponseInfo {
 					inviter_name,
@@ -1116,6 +1387,12 @@ impl Readable for ViewInviteResponse {
 					current_members,
 					online_members,
 					server_name,
+					server_description,
+					server_topic,
+					server_rules,
+					server_creation_time,
+					verification_level,
+					pow_difficulty,
 				};
 				Some(rinfo)
 			}
@@ -1138,6 +1415,8 @@ pub struct AcceptInviteRequest {
 	pub user_name: SerString,
 	pub user_bio: SerString,
 	pub avatar: Image,
+	// solution to the server's proof of work, see concorddata::pow
+	pub pow_nonce: u64,
 }
 
 impl Writeable for AcceptInviteRequest {
@@ -1152,6 +1431,7 @@ impl Writeable for AcceptInviteRequest {
 		Writeable::write(&self.user_name, writer)?;
 		Writeable::write(&self.user_bio, writer)?;
 		Writeable::write(&self.avatar, writer)?;
+		writer.write_u64(self.pow_nonce)?;
 		Ok(())
 	}
 }
@@ -1170,6 +1450,7 @@ impl Readable for AcceptInviteRequest {
 		let user_name = SerString::read(reader)?;
 		let user_bio = SerString::read(reader)?;
 		let avatar = Image::read(reader)?;
+		let pow_nonce = reader.read_u64()?;
 
 		Ok(Self {
 			invite_id,
@@ -1178,6 +1459,7 @@ impl Readable for AcceptInviteRequest {
 			user_name,
 			user_bio,
 			avatar,
+			pow_nonce,
 		})
 	}
 }
@@ -1185,6 +1467,12 @@ impl Readable for AcceptInviteRequest {
 #[derive(Debug, Clone)]
 pub struct AcceptInviteResponse {
 	pub success: bool,
+	// the channel to open after joining, 0 for the server's default
+	pub landing_channel: u64,
+	// the server requires approval and the request is waiting for a moderator
+	pub pending: bool,
+	// the server's proof of work difficulty, so a failed attempt can be retried
+	pub pow_difficulty: u8,
 }
 
 impl Writeable for AcceptInviteResponse {
@@ -1193,6 +1481,12 @@ impl Writeable for AcceptInviteResponse {
 			true => writer.write_u8(1)?,
 			false => writer.write_u8(0)?,
 		}
+		writer.write_u64(self.landing_channel)?;
+		match self.pending {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		writer.write_u8(self.pow_difficulty)?;
 		Ok(())
 	}
 }
@@ -1203,7 +1497,18 @@ impl Readable for AcceptInviteResponse {
 			0 => false,
 			_ => true,
 		};
-		Ok(Self { success })
+		let landing_channel = reader.read_u64()?;
+		let pending = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		let pow_difficulty = reader.read_u8()?;
+		Ok(Self {
+			success,
+			landing_channel,
+			pending,
+			pow_difficulty,
+		})
 	}
 }
 
@@ -1358,50 +1663,49 @@ impl Readable for SetProfileResponse {
 	}
 }
 
-// for now just two
 #[derive(Debug, Eq, PartialEq, IntoPrimitive, TryFromPrimitive, Clone)]
 #[repr(u8)]
 pub enum OnlineStatus {
 	Offline,
 	Online,
+	Idle,
 }
 
 #[derive(Debug, Clone)]
 pub struct Member {
-	user_pubkey: Pubkey,
-	user_name: SerString,
-	user_bio: SerString,
-	roles: u128,
-	profile_seqno: u64,
-	join_time: u64,
-	modified_time: u64,
-	online_status: OnlineStatus,
+	pub user_pubkey: Pubkey,
+	pub user_name: SerString,
+	pub user_bio: SerString,
+	pub roles: u128,
+	pub profile_seqno: u64,
+	pub join_time: u64,
+	pub modified_time: u64,
+	pub online_status: OnlineStatus,
+	// the creator of the invite the member joined through
+	pub inviter: SerOption<Pubkey>,
 }
 
 impl From<concorddata::concord::Member> for Member {
 	fn from(dmember: concorddata::concord::Member) -> Member {
-		info!("==================================dmember={:?}", dmember);
 		let user_pubkey = dmember.user_pubkey;
 		let (user_name, user_bio) = match dmember.profile_data {
 			Some(profile_data) => (profile_data.user_name, profile_data.user_bio),
 			None => ("".to_string().into(), "".to_string().into()),
 		};
-		let roles = 0;
 		let profile_seqno = 0;
+		// the caller fills in online_status from the presence manager
 		let online_status = OnlineStatus::Offline;
-		let user_name = user_name.into();
-		let user_bio = user_bio.into();
-		let user_pubkey = user_pubkey;
 
 		Member {
 			user_pubkey,
 			user_name,
 			user_bio,
-			roles,
+			roles: dmember.roles,
 			profile_seqno,
 			online_status,
-			join_time: 0,
-			modified_time: 0,
+			join_time: dmember.join_time,
+			modified_time: dmember.modified_time,
+			inviter: dmember.inviter.into(),
 		}
 	}
 }
@@ -1416,6 +1720,7 @@ impl Writeable for Member {
 		writer.write_u8(self.online_status.clone().into())?;
 		writer.write_u64(self.join_time)?;
 		writer.write_u64(self.modified_time)?;
+		Writeable::write(&self.inviter, writer)?;
 		Ok(())
 	}
 }
@@ -1439,6 +1744,7 @@ impl Readable for Member {
 			})?;
 		let join_time = reader.read_u64()?;
 		let modified_time = reader.read_u64()?;
+		let inviter = SerOption::read(reader)?;
 		Ok(Self {
 			user_pubkey,
 			user_name,
@@ -1448,6 +1754,7 @@ impl Readable for Member {
 			online_status,
 			join_time,
 			modified_time,
+			inviter,
 		})
 	}
 }
@@ -1456,14 +1763,16 @@ impl Readable for Member {
 pub struct GetMembersRequest {
 	pub server_id: ServerId,
 	pub server_pubkey: Pubkey,
-	pub batch_num: u64,
+	pub cursor: SerOption<PageCursor>,
+	pub limit: u64,
 }
 
 impl Writeable for GetMembersRequest {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
 		Writeable::write(&self.server_id, writer)?;
 		Writeable::write(&self.server_pubkey, writer)?;
-		writer.write_u64(self.batch_num)?;
+		Writeable::write(&self.cursor, writer)?;
+		writer.write_u64(self.limit)?;
 		Ok(())
 	}
 }
@@ -1472,12 +1781,14 @@ impl Readable for GetMembersRequest {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
 		let server_id = ServerId::read(reader)?;
 		let server_pubkey = Pubkey::read(reader)?;
-		let batch_num = reader.read_u64()?;
+		let cursor = SerOption::read(reader)?;
+		let limit = reader.read_u64()?;
 
 		Ok(Self {
 			server_id,
 			server_pubkey,
-			batch_num,
+			cursor,
+			limit,
 		})
 	}
 }
@@ -1487,19 +1798,19 @@ pub struct GetMembersResponse {
 	pub members: Vec<Member>,
 	pub server_id: ServerId,
 	pub server_pubkey: Pubkey,
-	pub batch_num: u64,
+	pub next_cursor: SerOption<PageCursor>,
 }
 
 impl Writeable for GetMembersResponse {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
 		let members_len = self.members.len();
 		writer.write_u64(members_len.try_into()?)?;
-		for _ in 0..members_len {
-			Writeable::write(&self.members, writer)?;
+		for member in &self.members {
+			Writeable::write(member, writer)?;
 		}
 		Writeable::write(&self.server_id, writer)?;
 		Writeable::write(&self.server_pubkey, writer)?;
-		writer.write_u64(self.batch_num)?;
+		Writeable::write(&self.next_cursor, writer)?;
 		Ok(())
 	}
 }
@@ -1513,132 +1824,1729 @@ impl Readable for GetMembersResponse {
 		}
 		let server_id = ServerId::read(reader)?;
 		let server_pubkey = Pubkey::read(reader)?;
-		let batch_num = reader.read_u64()?;
+		let next_cursor = SerOption::read(reader)?;
 
 		Ok(Self {
 			members,
 			server_id,
 			server_pubkey,
-			batch_num,
+			next_cursor,
 		})
 	}
 }
 
 #[derive(Debug, Clone)]
-pub struct GetServersEvent {}
+pub struct SearchMembersRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub query: SerString,
+	pub limit: u64,
+}
 
-impl Writeable for GetServersEvent {
-	fn write<W: Writer>(&self, _: &mut W) -> Result<(), Error> {
+impl Writeable for SearchMembersRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.query, writer)?;
+		writer.write_u64(self.limit)?;
 		Ok(())
 	}
 }
 
-impl Readable for GetServersEvent {
-	fn read<R: Reader>(_: &mut R) -> Result<Self, Error> {
-		Ok(Self {})
+impl Readable for SearchMembersRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let query = SerString::read(reader)?;
+		let limit = reader.read_u64()?;
+
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			query,
+			limit,
+		})
 	}
 }
 
 #[derive(Debug, Clone)]
-pub struct ChallengeEvent {
-	pub challenge: u128,
+pub struct SearchMembersResponse {
+	pub members: Vec<Member>,
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
 }
 
-impl Writeable for ChallengeEvent {
+impl Writeable for SearchMembersResponse {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
-		writer.write_u128(self.challenge)?;
+		let members_len = self.members.len();
+		writer.write_u64(members_len.try_into()?)?;
+		for member in &self.members {
+			Writeable::write(member, writer)?;
+		}
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
 		Ok(())
 	}
 }
 
-impl Readable for ChallengeEvent {
+impl Readable for SearchMembersResponse {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
-		let challenge = reader.read_u128()?;
-		Ok(Self { challenge })
+		let mut members = vec![];
+		let members_len = reader.read_u64()?;
+		for _ in 0..members_len {
+			members.push(Member::read(reader)?);
+		}
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+
+		Ok(Self {
+			members,
+			server_id,
+			server_pubkey,
+		})
 	}
 }
 
-// There are two methods to authenticate.
-// 1.) Use auth token (u128) provided on startup of concord server.
-// 2.) Sign a ChallengeEvent with your pubkey.
 #[derive(Debug, Clone)]
-pub struct AuthEvent {
-	pub signature: SerOption<Signature>,
-	pub token: SerOption<U128>,
-	pub pubkey: SerOption<Pubkey>,
+pub struct PresenceNotification {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+	pub user_pubkey: Pubkey,
+	pub online_status: OnlineStatus,
 }
 
-impl Writeable for AuthEvent {
+impl Writeable for PresenceNotification {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
-		Writeable::write(&self.signature, writer)?;
-		Writeable::write(&self.token, writer)?;
-		Writeable::write(&self.pubkey, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.user_pubkey, writer)?;
+		writer.write_u8(self.online_status.clone().into())?;
 		Ok(())
 	}
 }
 
-impl Readable for AuthEvent {
+impl Readable for PresenceNotification {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
-		let signature = SerOption::read(reader)?;
-		let token = SerOption::read(reader)?;
-		let pubkey = SerOption::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let user_pubkey = Pubkey::read(reader)?;
+		let online_status: OnlineStatus =
+			OnlineStatus::try_from(reader.read_u8()?).map_err(|e| {
+				let error: Error = ErrorKind::SerializationError(format!(
+					"invalid online_status, unkown online_status type: {}",
+					e
+				))
+				.into();
+				error
+			})?;
 
 		Ok(Self {
-			signature,
-			token,
-			pubkey,
+			server_pubkey,
+			server_id,
+			user_pubkey,
+			online_status,
 		})
 	}
 }
 
 #[derive(Debug, Clone)]
-pub struct AuthResponse {
-	pub success: bool,
-	pub redirect: SerOption<SerString>,
+pub struct ServerDeletedNotification {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
 }
 
-impl Writeable for AuthResponse {
+impl Writeable for ServerDeletedNotification {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
-		match self.success {
-			true => writer.write_u8(1)?,
-			false => writer.write_u8(0)?,
-		}
-		debug!("writing self.redirect");
-		Writeable::write(&self.redirect, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
 		Ok(())
 	}
 }
 
-impl Readable for AuthResponse {
+impl Readable for ServerDeletedNotification {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
-		let success = match reader.read_u8()? {
-			0 => false,
-			_ => true,
-		};
-		let redirect = SerOption::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
 
-		Ok(Self { success, redirect })
+		Ok(Self {
+			server_pubkey,
+			server_id,
+		})
 	}
 }
 
 #[derive(Debug, Clone)]
-pub struct ServerInfo {
-	pub name: SerString,
-	pub description: SerString,
-	pub server_id: ServerId,
+pub struct SetMemberRolesRequest {
 	pub server_pubkey: Pubkey,
-	pub seqno: u64,
+	pub server_id: ServerId,
+	pub user_pubkey: Pubkey,
+	pub roles: u128,
 }
 
-impl Writeable for ServerInfo {
+impl Writeable for SetMemberRolesRequest {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
-		Writeable::write(&self.name, writer)?;
-		Writeable::write(&self.description, writer)?;
-		Writeable::write(&self.server_id, writer)?;
 		Writeable::write(&self.server_pubkey, writer)?;
-		writer.write_u64(self.seqno)?;
-		Ok(())
-	}
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.user_pubkey, writer)?;
+		writer.write_u128(self.roles)?;
+		Ok(())
+	}
+}
+
+impl Readable for SetMemberRolesRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let user_pubkey = Pubkey::read(reader)?;
+		let roles = reader.read_u128()?;
+
+		Ok(Self {
+			server_pubkey,
+			server_id,
+			user_pubkey,
+			roles,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SetMemberRolesResponse {
+	pub success: bool,
+}
+
+impl Writeable for SetMemberRolesResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for SetMemberRolesResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct AddCategoryRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub name: SerString,
+}
+
+impl Writeable for AddCategoryRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.name, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for AddCategoryRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let name = SerString::read(reader)?;
+
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			name,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct AddCategoryResponse {
+	pub category_id: u64,
+	pub success: bool,
+}
+
+impl Writeable for AddCategoryResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u64(self.category_id)?;
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for AddCategoryResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let category_id = reader.read_u64()?;
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self {
+			category_id,
+			success,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ModifyCategoryRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub category_id: u64,
+	pub name: SerString,
+}
+
+impl Writeable for ModifyCategoryRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u64(self.category_id)?;
+		Writeable::write(&self.name, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for ModifyCategoryRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let category_id = reader.read_u64()?;
+		let name = SerString::read(reader)?;
+
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			category_id,
+			name,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ModifyCategoryResponse {
+	pub success: bool,
+}
+
+impl Writeable for ModifyCategoryResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ModifyCategoryResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteCategoryRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub category_id: u64,
+}
+
+impl Writeable for DeleteCategoryRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u64(self.category_id)?;
+		Ok(())
+	}
+}
+
+impl Readable for DeleteCategoryRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let category_id = reader.read_u64()?;
+
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			category_id,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteCategoryResponse {
+	pub success: bool,
+}
+
+impl Writeable for DeleteCategoryResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for DeleteCategoryResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+// the new place of a channel in a ReorderChannelsRequest. category_id is 0 for no category.
+#[derive(Debug, Clone)]
+pub struct ChannelPlacement {
+	pub channel_id: u64,
+	pub category_id: u64,
+}
+
+impl Writeable for ChannelPlacement {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u64(self.channel_id)?;
+		writer.write_u64(self.category_id)?;
+		Ok(())
+	}
+}
+
+impl Readable for ChannelPlacement {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let channel_id = reader.read_u64()?;
+		let category_id = reader.read_u64()?;
+		Ok(Self {
+			channel_id,
+			category_id,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ReorderChannelsRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub categories: Vec<u64>,
+	pub channels: Vec<ChannelPlacement>,
+}
+
+impl Writeable for ReorderChannelsRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u64(self.categories.len().try_into()?)?;
+		for category_id in &self.categories {
+			writer.write_u64(*category_id)?;
+		}
+		writer.write_u64(self.channels.len().try_into()?)?;
+		for channel in &self.channels {
+			Writeable::write(channel, writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ReorderChannelsRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let len = reader.read_u64()?;
+		let mut categories = vec![];
+		for _ in 0..len {
+			categories.push(reader.read_u64()?);
+		}
+		let len = reader.read_u64()?;
+		let mut channels = vec![];
+		for _ in 0..len {
+			channels.push(ChannelPlacement::read(reader)?);
+		}
+
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			categories,
+			channels,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ReorderChannelsResponse {
+	pub success: bool,
+}
+
+impl Writeable for ReorderChannelsResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ReorderChannelsResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SetChannelPermissionsRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub channel_id: u64,
+	pub overwrites: Vec<PermissionOverwrite>,
+}
+
+impl Writeable for SetChannelPermissionsRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u64(self.channel_id)?;
+		writer.write_u64(self.overwrites.len().try_into()?)?;
+		for overwrite in &self.overwrites {
+			Writeable::write(overwrite, writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for SetChannelPermissionsRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let channel_id = reader.read_u64()?;
+		let len = reader.read_u64()?;
+		let mut overwrites = vec![];
+		for _ in 0..len {
+			overwrites.push(PermissionOverwrite::read(reader)?);
+		}
+
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			channel_id,
+			overwrites,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SetChannelPermissionsResponse {
+	pub success: bool,
+}
+
+impl Writeable for SetChannelPermissionsResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for SetChannelPermissionsResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SetChannelKindRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub channel_id: u64,
+	pub kind: u8,
+	// in announcement channels, the roles that may post
+	pub post_roles: u128,
+}
+
+impl Writeable for SetChannelKindRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u64(self.channel_id)?;
+		writer.write_u8(self.kind)?;
+		writer.write_u128(self.post_roles)?;
+		Ok(())
+	}
+}
+
+impl Readable for SetChannelKindRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let channel_id = reader.read_u64()?;
+		let kind = reader.read_u8()?;
+		let post_roles = reader.read_u128()?;
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			channel_id,
+			kind,
+			post_roles,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SetChannelKindResponse {
+	pub success: bool,
+}
+
+impl Writeable for SetChannelKindResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for SetChannelKindResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+// sent by a user to the host of target and forwarded by that host to the host of source
+#[derive(Debug, Clone)]
+pub struct FollowChannelRequest {
+	// the announcement channel
+	pub source: ChannelIdentifier,
+	// the channel that republishes its announcements
+	pub target: ChannelIdentifier,
+}
+
+impl Writeable for FollowChannelRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.source, writer)?;
+		Writeable::write(&self.target, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for FollowChannelRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let source = ChannelIdentifier::read(reader)?;
+		let target = ChannelIdentifier::read(reader)?;
+		Ok(Self { source, target })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct FollowChannelResponse {
+	pub success: bool,
+}
+
+impl Writeable for FollowChannelResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for FollowChannelResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+// sent by a user to the host of target and forwarded by that host to the host of source
+#[derive(Debug, Clone)]
+pub struct UnfollowChannelRequest {
+	// the announcement channel
+	pub source: ChannelIdentifier,
+	// the channel that republishes its announcements
+	pub target: ChannelIdentifier,
+}
+
+impl Writeable for UnfollowChannelRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.source, writer)?;
+		Writeable::write(&self.target, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for UnfollowChannelRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let source = ChannelIdentifier::read(reader)?;
+		let target = ChannelIdentifier::read(reader)?;
+		Ok(Self { source, target })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct UnfollowChannelResponse {
+	pub success: bool,
+}
+
+impl Writeable for UnfollowChannelResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for UnfollowChannelResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+// sent by the host of an announcement channel to the hosts of its followers
+#[derive(Debug, Clone)]
+pub struct CrossPostRequest {
+	pub source: ChannelIdentifier,
+	pub target: ChannelIdentifier,
+	// the message as posted in the source channel
+	pub message: Message,
+}
+
+impl Writeable for CrossPostRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.source, writer)?;
+		Writeable::write(&self.target, writer)?;
+		Writeable::write(&self.message, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for CrossPostRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let source = ChannelIdentifier::read(reader)?;
+		let target = ChannelIdentifier::read(reader)?;
+		let message = Message::read(reader)?;
+		Ok(Self {
+			source,
+			target,
+			message,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct CrossPostResponse {
+	pub success: bool,
+}
+
+impl Writeable for CrossPostResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for CrossPostResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveChannelRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub channel_id: u64,
+	// false to unarchive the channel
+	pub archived: bool,
+}
+
+impl Writeable for ArchiveChannelRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u64(self.channel_id)?;
+		match self.archived {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ArchiveChannelRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let channel_id = reader.read_u64()?;
+		let archived = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			channel_id,
+			archived,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveChannelResponse {
+	pub success: bool,
+}
+
+impl Writeable for ArchiveChannelResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ArchiveChannelResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchMessagesRequest {
+	pub channel_identifier: ChannelIdentifier,
+	pub query: SerString,
+	pub cursor: SerOption<PageCursor>,
+}
+
+impl Writeable for SearchMessagesRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.channel_identifier, writer)?;
+		Writeable::write(&self.query, writer)?;
+		Writeable::write(&self.cursor, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for SearchMessagesRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let channel_identifier = ChannelIdentifier::read(reader)?;
+		let query = SerString::read(reader)?;
+		let cursor = SerOption::read(reader)?;
+		Ok(Self {
+			channel_identifier,
+			query,
+			cursor,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchMessagesResponse {
+	pub channel_identifier: ChannelIdentifier,
+	pub messages: Vec<Message>,
+	pub next_cursor: SerOption<PageCursor>,
+}
+
+impl Writeable for SearchMessagesResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.channel_identifier, writer)?;
+		writer.write_u64(self.messages.len().try_into()?)?;
+		for message in &self.messages {
+			Writeable::write(message, writer)?;
+		}
+		Writeable::write(&self.next_cursor, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for SearchMessagesResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let channel_identifier = ChannelIdentifier::read(reader)?;
+		let len = reader.read_u64()?;
+		let mut messages = vec![];
+		for _ in 0..len {
+			messages.push(Message::read(reader)?);
+		}
+		let next_cursor = SerOption::read(reader)?;
+		Ok(Self {
+			channel_identifier,
+			messages,
+			next_cursor,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportChannelRequest {
+	pub channel_identifier: ChannelIdentifier,
+	pub cursor: SerOption<PageCursor>,
+}
+
+impl Writeable for ExportChannelRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.channel_identifier, writer)?;
+		Writeable::write(&self.cursor, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for ExportChannelRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let channel_identifier = ChannelIdentifier::read(reader)?;
+		let cursor = SerOption::read(reader)?;
+		Ok(Self {
+			channel_identifier,
+			cursor,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportChannelResponse {
+	pub channel_identifier: ChannelIdentifier,
+	pub messages: Vec<Message>,
+	pub next_cursor: SerOption<PageCursor>,
+}
+
+impl Writeable for ExportChannelResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.channel_identifier, writer)?;
+		writer.write_u64(self.messages.len().try_into()?)?;
+		for message in &self.messages {
+			Writeable::write(message, writer)?;
+		}
+		Writeable::write(&self.next_cursor, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for ExportChannelResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let channel_identifier = ChannelIdentifier::read(reader)?;
+		let len = reader.read_u64()?;
+		let mut messages = vec![];
+		for _ in 0..len {
+			messages.push(Message::read(reader)?);
+		}
+		let next_cursor = SerOption::read(reader)?;
+		Ok(Self {
+			channel_identifier,
+			messages,
+			next_cursor,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct GetInviteUsesRequest {
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub invite_id: u128,
+}
+
+impl Writeable for GetInviteUsesRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u128(self.invite_id)?;
+		Ok(())
+	}
+}
+
+impl Readable for GetInviteUsesRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_id = ServerId::read(reader)?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let invite_id = reader.read_u128()?;
+		Ok(Self {
+			server_id,
+			server_pubkey,
+			invite_id,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct GetInviteUsesResponse {
+	pub invite_id: u128,
+	pub uses: Vec<InviteUse>,
+}
+
+impl Writeable for GetInviteUsesResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u128(self.invite_id)?;
+		writer.write_u64(self.uses.len().try_into()?)?;
+		for invite_use in &self.uses {
+			Writeable::write(invite_use, writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for GetInviteUsesResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let invite_id = reader.read_u128()?;
+		let len = reader.read_u64()?;
+		let mut uses = vec![];
+		for _ in 0..len {
+			uses.push(InviteUse::read(reader)?);
+		}
+		Ok(Self { invite_id, uses })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ListJoinRequestsRequest {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+}
+
+impl Writeable for ListJoinRequestsRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for ListJoinRequestsRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		Ok(Self {
+			server_pubkey,
+			server_id,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ListJoinRequestsResponse {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+	pub join_requests: Vec<JoinRequest>,
+}
+
+impl Writeable for ListJoinRequestsResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		writer.write_u64(self.join_requests.len().try_into()?)?;
+		for join_request in &self.join_requests {
+			Writeable::write(join_request, writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ListJoinRequestsResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let len = reader.read_u64()?;
+		let mut join_requests = vec![];
+		for _ in 0..len {
+			join_requests.push(JoinRequest::read(reader)?);
+		}
+		Ok(Self {
+			server_pubkey,
+			server_id,
+			join_requests,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ApproveJoinRequestRequest {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+	pub user_pubkey: Pubkey,
+}
+
+impl Writeable for ApproveJoinRequestRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.user_pubkey, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for ApproveJoinRequestRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let user_pubkey = Pubkey::read(reader)?;
+		Ok(Self {
+			server_pubkey,
+			server_id,
+			user_pubkey,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ApproveJoinRequestResponse {
+	pub success: bool,
+}
+
+impl Writeable for ApproveJoinRequestResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ApproveJoinRequestResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct RejectJoinRequestRequest {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+	pub user_pubkey: Pubkey,
+}
+
+impl Writeable for RejectJoinRequestRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.user_pubkey, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for RejectJoinRequestRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let user_pubkey = Pubkey::read(reader)?;
+		Ok(Self {
+			server_pubkey,
+			server_id,
+			user_pubkey,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct RejectJoinRequestResponse {
+	pub success: bool,
+}
+
+impl Writeable for RejectJoinRequestResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for RejectJoinRequestResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+// sent by the host of a server to the applicant's host once a join request is decided
+#[derive(Debug, Clone)]
+pub struct JoinRequestOutcomeRequest {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+	pub approved: bool,
+	// the channel to open after joining, 0 for the server's default
+	pub landing_channel: u64,
+}
+
+impl Writeable for JoinRequestOutcomeRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		match self.approved {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		writer.write_u64(self.landing_channel)?;
+		Ok(())
+	}
+}
+
+impl Readable for JoinRequestOutcomeRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let approved = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		let landing_channel = reader.read_u64()?;
+		Ok(Self {
+			server_pubkey,
+			server_id,
+			approved,
+			landing_channel,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct JoinRequestOutcomeResponse {}
+
+impl Writeable for JoinRequestOutcomeResponse {
+	fn write<W: Writer>(&self, _: &mut W) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+impl Readable for JoinRequestOutcomeResponse {
+	fn read<R: Reader>(_: &mut R) -> Result<Self, Error> {
+		Ok(Self {})
+	}
+}
+
+// pushed to the applicant's connections when a join request is decided
+#[derive(Debug, Clone)]
+pub struct JoinRequestOutcomeNotification {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+	pub approved: bool,
+	// the channel to open after joining, 0 for the server's default
+	pub landing_channel: u64,
+}
+
+impl Writeable for JoinRequestOutcomeNotification {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		match self.approved {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		writer.write_u64(self.landing_channel)?;
+		Ok(())
+	}
+}
+
+impl Readable for JoinRequestOutcomeNotification {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let approved = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		let landing_channel = reader.read_u64()?;
+		Ok(Self {
+			server_pubkey,
+			server_id,
+			approved,
+			landing_channel,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SetPowDifficultyRequest {
+	pub server_pubkey: Pubkey,
+	pub server_id: ServerId,
+	pub difficulty: u8,
+}
+
+impl Writeable for SetPowDifficultyRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		writer.write_u8(self.difficulty)?;
+		Ok(())
+	}
+}
+
+impl Readable for SetPowDifficultyRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let server_pubkey = Pubkey::read(reader)?;
+		let server_id = ServerId::read(reader)?;
+		let difficulty = reader.read_u8()?;
+		Ok(Self {
+			server_pubkey,
+			server_id,
+			difficulty,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SetPowDifficultyResponse {
+	pub success: bool,
+}
+
+impl Writeable for SetPowDifficultyResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for SetPowDifficultyResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ListSessionsRequest {}
+
+impl Writeable for ListSessionsRequest {
+	fn write<W: Writer>(&self, _: &mut W) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+impl Readable for ListSessionsRequest {
+	fn read<R: Reader>(_: &mut R) -> Result<Self, Error> {
+		Ok(Self {})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ListSessionsResponse {
+	// session_id of the requesting connection, 0 if it did not use a session
+	pub current_session: u64,
+	pub sessions: Vec<SessionInfo>,
+}
+
+impl Writeable for ListSessionsResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u64(self.current_session)?;
+		writer.write_u64(self.sessions.len().try_into()?)?;
+		for session in &self.sessions {
+			Writeable::write(session, writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ListSessionsResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let current_session = reader.read_u64()?;
+		let len = reader.read_u64()?;
+		let mut sessions = vec![];
+		for _ in 0..len {
+			sessions.push(SessionInfo::read(reader)?);
+		}
+		Ok(Self {
+			current_session,
+			sessions,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct RevokeSessionRequest {
+	pub session_id: u64,
+}
+
+impl Writeable for RevokeSessionRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u64(self.session_id)?;
+		Ok(())
+	}
+}
+
+impl Readable for RevokeSessionRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let session_id = reader.read_u64()?;
+		Ok(Self { session_id })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct RevokeSessionResponse {
+	pub success: bool,
+}
+
+impl Writeable for RevokeSessionResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for RevokeSessionResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct RotateAuthTokenRequest {}
+
+impl Writeable for RotateAuthTokenRequest {
+	fn write<W: Writer>(&self, _: &mut W) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+impl Readable for RotateAuthTokenRequest {
+	fn read<R: Reader>(_: &mut R) -> Result<Self, Error> {
+		Ok(Self {})
+	}
+}
+
+// the new token is only returned to the instance owner that requested the rotation.
+#[derive(Debug, Clone)]
+pub struct RotateAuthTokenResponse {
+	pub success: bool,
+	pub token: u128,
+}
+
+impl Writeable for RotateAuthTokenResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		writer.write_u128(self.token)?;
+		Ok(())
+	}
+}
+
+impl Readable for RotateAuthTokenResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		let token = reader.read_u128()?;
+		Ok(Self { success, token })
+	}
+}
+
+// revocations are signed by the user's primary key, so any connection may relay them.
+#[derive(Debug, Clone)]
+pub struct RevokeDeviceRequest {
+	pub revocation: DeviceRevocation,
+}
+
+impl Writeable for RevokeDeviceRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.revocation, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for RevokeDeviceRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let revocation = DeviceRevocation::read(reader)?;
+		Ok(Self { revocation })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct RevokeDeviceResponse {
+	pub success: bool,
+}
+
+impl Writeable for RevokeDeviceResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for RevokeDeviceResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		Ok(Self { success })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ListDeviceRevocationsRequest {
+	pub user_pubkey: Pubkey,
+}
+
+impl Writeable for ListDeviceRevocationsRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.user_pubkey, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for ListDeviceRevocationsRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let user_pubkey = Pubkey::read(reader)?;
+		Ok(Self { user_pubkey })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ListDeviceRevocationsResponse {
+	pub revocations: Vec<DeviceRevocation>,
+}
+
+impl Writeable for ListDeviceRevocationsResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u64(self.revocations.len().try_into()?)?;
+		for revocation in &self.revocations {
+			Writeable::write(revocation, writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ListDeviceRevocationsResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let len = reader.read_u64()?;
+		let mut revocations = vec![];
+		for _ in 0..len {
+			revocations.push(DeviceRevocation::read(reader)?);
+		}
+		Ok(Self { revocations })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct GetServersEvent {}
+
+impl Writeable for GetServersEvent {
+	fn write<W: Writer>(&self, _: &mut W) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+impl Readable for GetServersEvent {
+	fn read<R: Reader>(_: &mut R) -> Result<Self, Error> {
+		Ok(Self {})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ChallengeEvent {
+	pub challenge: [u8; 32],
+	pub server_pubkey: Pubkey,
+	// time the challenge was issued in milliseconds since the epoch
+	pub timestamp: u128,
+}
+
+impl ChallengeEvent {
+	// the bytes that are signed to answer the challenge: challenge|server_pubkey|timestamp
+	pub fn message(&self) -> Vec<u8> {
+		let mut message = self.challenge.to_vec();
+		message.append(&mut self.server_pubkey.to_bytes().to_vec());
+		message.append(&mut self.timestamp.to_be_bytes().to_vec());
+		message
+	}
+}
+
+impl Writeable for ChallengeEvent {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_fixed_bytes(&self.challenge)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u128(self.timestamp)?;
+		Ok(())
+	}
+}
+
+impl Readable for ChallengeEvent {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let challenge = reader.read_fixed_bytes(32)?.as_slice().try_into()?;
+		let server_pubkey = Pubkey::read(reader)?;
+		let timestamp = reader.read_u128()?;
+		Ok(Self {
+			challenge,
+			server_pubkey,
+			timestamp,
+		})
+	}
+}
+
+// There are three methods to authenticate.
+// 1.) Use auth token (u128) provided on startup of concord server.
+// 2.) Sign the message of a ChallengeEvent with your pubkey.
+// 3.) Use a session token together with the pubkey it was issued to.
+#[derive(Debug, Clone)]
+pub struct AuthEvent {
+	pub signature: SerOption<Signature>,
+	pub token: SerOption<U128>,
+	pub pubkey: SerOption<Pubkey>,
+	// set when pubkey is a linked device key rather than the user's own key
+	pub device_certificate: SerOption<DeviceCertificate>,
+}
+
+impl Writeable for AuthEvent {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.signature, writer)?;
+		Writeable::write(&self.token, writer)?;
+		Writeable::write(&self.pubkey, writer)?;
+		Writeable::write(&self.device_certificate, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for AuthEvent {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let signature = SerOption::read(reader)?;
+		let token = SerOption::read(reader)?;
+		let pubkey = SerOption::read(reader)?;
+		let device_certificate = SerOption::read(reader)?;
+
+		Ok(Self {
+			signature,
+			token,
+			pubkey,
+			device_certificate,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthResponse {
+	pub success: bool,
+	pub redirect: SerOption<SerString>,
+}
+
+impl Writeable for AuthResponse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.success {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		debug!("writing self.redirect");
+		Writeable::write(&self.redirect, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for AuthResponse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let success = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		let redirect = SerOption::read(reader)?;
+
+		Ok(Self { success, redirect })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+	pub name: SerString,
+	pub description: SerString,
+	pub server_id: ServerId,
+	pub server_pubkey: Pubkey,
+	pub seqno: u64,
+	pub topic: SerString,
+	pub creation_time: u128,
+	pub default_channel: u64,
+	pub rules: SerString,
+	pub verification_level: u8,
+	pub require_approval: bool,
+	pub pow_difficulty: u8,
+}
+
+impl Writeable for ServerInfo {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.name, writer)?;
+		Writeable::write(&self.description, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		writer.write_u64(self.seqno)?;
+		Writeable::write(&self.topic, writer)?;
+		writer.write_u128(self.creation_time)?;
+		writer.write_u64(self.default_channel)?;
+		Writeable::write(&self.rules, writer)?;
+		writer.write_u8(self.verification_level)?;
+		match self.require_approval {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		writer.write_u8(self.pow_difficulty)?;
+		Ok(())
+	}
 }
 
 impl Readable for ServerInfo {
@@ -1648,12 +3556,29 @@ impl Readable for ServerInfo {
 		let server_id = ServerId::read(reader)?;
 		let server_pubkey = Pubkey::read(reader)?;
 		let seqno = reader.read_u64()?;
+		let topic = SerString::read(reader)?;
+		let creation_time = reader.read_u128()?;
+		let default_channel = reader.read_u64()?;
+		let rules = SerString::read(reader)?;
+		let verification_level = reader.read_u8()?;
+		let require_approval = match reader.read_u8()? {
+			0 => false,
+			_ => true,
+		};
+		let pow_difficulty = reader.read_u8()?;
 		Ok(Self {
 			name,
 			description,
 			server_id,
 			server_pubkey,
 			seqno,
+			topic,
+			creation_time,
+			default_channel,
+			rules,
+			verification_level,
+			require_approval,
+			pow_difficulty,
 		})
 	}
 }
@@ -1734,6 +3659,60 @@ pub enum EventType {
 	SendMessage,
 	MessageNotification,
 	SubscribeChannel,
+	PresenceNotification,
+	SearchMembersRequest,
+	SearchMembersResponse,
+	ServerDeletedNotification,
+	SetMemberRolesRequest,
+	SetMemberRolesResponse,
+	AddCategoryRequest,
+	AddCategoryResponse,
+	ModifyCategoryRequest,
+	ModifyCategoryResponse,
+	DeleteCategoryRequest,
+	DeleteCategoryResponse,
+	ReorderChannelsRequest,
+	ReorderChannelsResponse,
+	SetChannelPermissionsRequest,
+	SetChannelPermissionsResponse,
+	UnreadNotification,
+	SetChannelKindRequest,
+	SetChannelKindResponse,
+	FollowChannelRequest,
+	FollowChannelResponse,
+	UnfollowChannelRequest,
+	UnfollowChannelResponse,
+	CrossPostRequest,
+	CrossPostResponse,
+	ArchiveChannelRequest,
+	ArchiveChannelResponse,
+	SearchMessagesRequest,
+	SearchMessagesResponse,
+	ExportChannelRequest,
+	ExportChannelResponse,
+	GetInviteUsesRequest,
+	GetInviteUsesResponse,
+	ListJoinRequestsRequest,
+	ListJoinRequestsResponse,
+	ApproveJoinRequestRequest,
+	ApproveJoinRequestResponse,
+	RejectJoinRequestRequest,
+	RejectJoinRequestResponse,
+	JoinRequestOutcomeRequest,
+	JoinRequestOutcomeResponse,
+	JoinRequestOutcomeNotification,
+	SetPowDifficultyRequest,
+	SetPowDifficultyResponse,
+	ListSessionsRequest,
+	ListSessionsResponse,
+	RevokeSessionRequest,
+	RevokeSessionResponse,
+	RotateAuthTokenRequest,
+	RotateAuthTokenResponse,
+	RevokeDeviceRequest,
+	RevokeDeviceResponse,
+	ListDeviceRevocationsRequest,
+	ListDeviceRevocationsResponse,
 }
 
 #[derive(Debug, Clone)]
@@ -1779,6 +3758,60 @@ pub enum EventBody {
 	SendMessage(SendMessage),
 	MessageNotification(MessageNotification),
 	SubscribeChannel(SubscribeChannel),
+	PresenceNotification(PresenceNotification),
+	SearchMembersRequest(SearchMembersRequest),
+	SearchMembersResponse(SearchMembersResponse),
+	ServerDeletedNotification(ServerDeletedNotification),
+	SetMemberRolesRequest(SetMemberRolesRequest),
+	SetMemberRolesResponse(SetMemberRolesResponse),
+	AddCategoryRequest(AddCategoryRequest),
+	AddCategoryResponse(AddCategoryResponse),
+	ModifyCategoryRequest(ModifyCategoryRequest),
+	ModifyCategoryResponse(ModifyCategoryResponse),
+	DeleteCategoryRequest(DeleteCategoryRequest),
+	DeleteCategoryResponse(DeleteCategoryResponse),
+	ReorderChannelsRequest(ReorderChannelsRequest),
+	ReorderChannelsResponse(ReorderChannelsResponse),
+	SetChannelPermissionsRequest(SetChannelPermissionsRequest),
+	SetChannelPermissionsResponse(SetChannelPermissionsResponse),
+	UnreadNotification(UnreadNotification),
+	SetChannelKindRequest(SetChannelKindRequest),
+	SetChannelKindResponse(SetChannelKindResponse),
+	FollowChannelRequest(FollowChannelRequest),
+	FollowChannelResponse(FollowChannelResponse),
+	UnfollowChannelRequest(UnfollowChannelRequest),
+	UnfollowChannelResponse(UnfollowChannelResponse),
+	CrossPostRequest(CrossPostRequest),
+	CrossPostResponse(CrossPostResponse),
+	ArchiveChannelRequest(ArchiveChannelRequest),
+	ArchiveChannelResponse(ArchiveChannelResponse),
+	SearchMessagesRequest(SearchMessagesRequest),
+	SearchMessagesResponse(SearchMessagesResponse),
+	ExportChannelRequest(ExportChannelRequest),
+	ExportChannelResponse(ExportChannelResponse),
+	GetInviteUsesRequest(GetInviteUsesRequest),
+	GetInviteUsesResponse(GetInviteUsesResponse),
+	ListJoinRequestsRequest(ListJoinRequestsRequest),
+	ListJoinRequestsResponse(ListJoinRequestsResponse),
+	ApproveJoinRequestRequest(ApproveJoinRequestRequest),
+	ApproveJoinRequestResponse(ApproveJoinRequestResponse),
+	RejectJoinRequestRequest(RejectJoinRequestRequest),
+	RejectJoinRequestResponse(RejectJoinRequestResponse),
+	JoinRequestOutcomeRequest(JoinRequestOutcomeRequest),
+	JoinRequestOutcomeResponse(JoinRequestOutcomeResponse),
+	JoinRequestOutcomeNotification(JoinRequestOutcomeNotification),
+	SetPowDifficultyRequest(SetPowDifficultyRequest),
+	SetPowDifficultyResponse(SetPowDifficultyResponse),
+	ListSessionsRequest(ListSessionsRequest),
+	ListSessionsResponse(ListSessionsResponse),
+	RevokeSessionRequest(RevokeSessionRequest),
+	RevokeSessionResponse(RevokeSessionResponse),
+	RotateAuthTokenRequest(RotateAuthTokenRequest),
+	RotateAuthTokenResponse(RotateAuthTokenResponse),
+	RevokeDeviceRequest(RevokeDeviceRequest),
+	RevokeDeviceResponse(RevokeDeviceResponse),
+	ListDeviceRevocationsRequest(ListDeviceRevocationsRequest),
+	ListDeviceRevocationsResponse(ListDeviceRevocationsResponse),
 }
 
 impl Writeable for EventBody {
@@ -1948,6 +3981,222 @@ impl Writeable for EventBody {
 				writer.write_u16(40)?;
 				Writeable::write(e, writer)?;
 			}
+			EventBody::PresenceNotification(e) => {
+				writer.write_u16(41)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SearchMembersRequest(e) => {
+				writer.write_u16(42)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SearchMembersResponse(e) => {
+				writer.write_u16(43)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ServerDeletedNotification(e) => {
+				writer.write_u16(44)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetMemberRolesRequest(e) => {
+				writer.write_u16(45)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetMemberRolesResponse(e) => {
+				writer.write_u16(46)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::AddCategoryRequest(e) => {
+				writer.write_u16(47)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::AddCategoryResponse(e) => {
+				writer.write_u16(48)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ModifyCategoryRequest(e) => {
+				writer.write_u16(49)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ModifyCategoryResponse(e) => {
+				writer.write_u16(50)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::DeleteCategoryRequest(e) => {
+				writer.write_u16(51)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::DeleteCategoryResponse(e) => {
+				writer.write_u16(52)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ReorderChannelsRequest(e) => {
+				writer.write_u16(53)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ReorderChannelsResponse(e) => {
+				writer.write_u16(54)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetChannelPermissionsRequest(e) => {
+				writer.write_u16(55)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetChannelPermissionsResponse(e) => {
+				writer.write_u16(56)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::UnreadNotification(e) => {
+				writer.write_u16(57)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetChannelKindRequest(e) => {
+				writer.write_u16(58)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetChannelKindResponse(e) => {
+				writer.write_u16(59)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::FollowChannelRequest(e) => {
+				writer.write_u16(60)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::FollowChannelResponse(e) => {
+				writer.write_u16(61)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::UnfollowChannelRequest(e) => {
+				writer.write_u16(62)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::UnfollowChannelResponse(e) => {
+				writer.write_u16(63)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::CrossPostRequest(e) => {
+				writer.write_u16(64)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::CrossPostResponse(e) => {
+				writer.write_u16(65)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ArchiveChannelRequest(e) => {
+				writer.write_u16(66)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ArchiveChannelResponse(e) => {
+				writer.write_u16(67)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SearchMessagesRequest(e) => {
+				writer.write_u16(68)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SearchMessagesResponse(e) => {
+				writer.write_u16(69)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ExportChannelRequest(e) => {
+				writer.write_u16(70)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ExportChannelResponse(e) => {
+				writer.write_u16(71)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::GetInviteUsesRequest(e) => {
+				writer.write_u16(72)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::GetInviteUsesResponse(e) => {
+				writer.write_u16(73)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ListJoinRequestsRequest(e) => {
+				writer.write_u16(74)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ListJoinRequestsResponse(e) => {
+				writer.write_u16(75)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ApproveJoinRequestRequest(e) => {
+				writer.write_u16(76)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ApproveJoinRequestResponse(e) => {
+				writer.write_u16(77)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RejectJoinRequestRequest(e) => {
+				writer.write_u16(78)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RejectJoinRequestResponse(e) => {
+				writer.write_u16(79)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::JoinRequestOutcomeRequest(e) => {
+				writer.write_u16(80)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::JoinRequestOutcomeResponse(e) => {
+				writer.write_u16(81)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::JoinRequestOutcomeNotification(e) => {
+				writer.write_u16(82)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetPowDifficultyRequest(e) => {
+				writer.write_u16(83)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::SetPowDifficultyResponse(e) => {
+				writer.write_u16(84)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ListSessionsRequest(e) => {
+				writer.write_u16(85)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ListSessionsResponse(e) => {
+				writer.write_u16(86)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RevokeSessionRequest(e) => {
+				writer.write_u16(87)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RevokeSessionResponse(e) => {
+				writer.write_u16(88)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RotateAuthTokenRequest(e) => {
+				writer.write_u16(89)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RotateAuthTokenResponse(e) => {
+				writer.write_u16(90)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RevokeDeviceRequest(e) => {
+				writer.write_u16(91)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::RevokeDeviceResponse(e) => {
+				writer.write_u16(92)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ListDeviceRevocationsRequest(e) => {
+				writer.write_u16(93)?;
+				Writeable::write(e, writer)?;
+			}
+			EventBody::ListDeviceRevocationsResponse(e) => {
+				writer.write_u16(94)?;
+				Writeable::write(e, writer)?;
+			}
 		}
 		Ok(())
 	}
@@ -2068,6 +4317,166 @@ impl Readable for EventBody {
 				reader,
 			)?)),
 			40 => Ok(EventBody::SubscribeChannel(SubscribeChannel::read(reader)?)),
+			41 => Ok(EventBody::PresenceNotification(PresenceNotification::read(
+				reader,
+			)?)),
+			42 => Ok(EventBody::SearchMembersRequest(SearchMembersRequest::read(
+				reader,
+			)?)),
+			43 => Ok(EventBody::SearchMembersResponse(
+				SearchMembersResponse::read(reader)?,
+			)),
+			44 => Ok(EventBody::ServerDeletedNotification(
+				ServerDeletedNotification::read(reader)?,
+			)),
+			45 => Ok(EventBody::SetMemberRolesRequest(
+				SetMemberRolesRequest::read(reader)?,
+			)),
+			46 => Ok(EventBody::SetMemberRolesResponse(
+				SetMemberRolesResponse::read(reader)?,
+			)),
+			47 => Ok(EventBody::AddCategoryRequest(AddCategoryRequest::read(
+				reader,
+			)?)),
+			48 => Ok(EventBody::AddCategoryResponse(AddCategoryResponse::read(
+				reader,
+			)?)),
+			49 => Ok(EventBody::ModifyCategoryRequest(
+				ModifyCategoryRequest::read(reader)?,
+			)),
+			50 => Ok(EventBody::ModifyCategoryResponse(
+				ModifyCategoryResponse::read(reader)?,
+			)),
+			51 => Ok(EventBody::DeleteCategoryRequest(
+				DeleteCategoryRequest::read(reader)?,
+			)),
+			52 => Ok(EventBody::DeleteCategoryResponse(
+				DeleteCategoryResponse::read(reader)?,
+			)),
+			53 => Ok(EventBody::ReorderChannelsRequest(
+				ReorderChannelsRequest::read(reader)?,
+			)),
+			54 => Ok(EventBody::ReorderChannelsResponse(
+				ReorderChannelsResponse::read(reader)?,
+			)),
+			55 => Ok(EventBody::SetChannelPermissionsRequest(
+				SetChannelPermissionsRequest::read(reader)?,
+			)),
+			56 => Ok(EventBody::SetChannelPermissionsResponse(
+				SetChannelPermissionsResponse::read(reader)?,
+			)),
+			57 => Ok(EventBody::UnreadNotification(UnreadNotification::read(
+				reader,
+			)?)),
+			58 => Ok(EventBody::SetChannelKindRequest(
+				SetChannelKindRequest::read(reader)?,
+			)),
+			59 => Ok(EventBody::SetChannelKindResponse(
+				SetChannelKindResponse::read(reader)?,
+			)),
+			60 => Ok(EventBody::FollowChannelRequest(FollowChannelRequest::read(
+				reader,
+			)?)),
+			61 => Ok(EventBody::FollowChannelResponse(
+				FollowChannelResponse::read(reader)?,
+			)),
+			62 => Ok(EventBody::UnfollowChannelRequest(
+				UnfollowChannelRequest::read(reader)?,
+			)),
+			63 => Ok(EventBody::UnfollowChannelResponse(
+				UnfollowChannelResponse::read(reader)?,
+			)),
+			64 => Ok(EventBody::CrossPostRequest(CrossPostRequest::read(reader)?)),
+			65 => Ok(EventBody::CrossPostResponse(CrossPostResponse::read(
+				reader,
+			)?)),
+			66 => Ok(EventBody::ArchiveChannelRequest(
+				ArchiveChannelRequest::read(reader)?,
+			)),
+			67 => Ok(EventBody::ArchiveChannelResponse(
+				ArchiveChannelResponse::read(reader)?,
+			)),
+			68 => Ok(EventBody::SearchMessagesRequest(
+				SearchMessagesRequest::read(reader)?,
+			)),
+			69 => Ok(EventBody::SearchMessagesResponse(
+				SearchMessagesResponse::read(reader)?,
+			)),
+			70 => Ok(EventBody::ExportChannelRequest(ExportChannelRequest::read(
+				reader,
+			)?)),
+			71 => Ok(EventBody::ExportChannelResponse(
+				ExportChannelResponse::read(reader)?,
+			)),
+			72 => Ok(EventBody::GetInviteUsesRequest(GetInviteUsesRequest::read(
+				reader,
+			)?)),
+			73 => Ok(EventBody::GetInviteUsesResponse(
+				GetInviteUsesResponse::read(reader)?,
+			)),
+			74 => Ok(EventBody::ListJoinRequestsRequest(
+				ListJoinRequestsRequest::read(reader)?,
+			)),
+			75 => Ok(EventBody::ListJoinRequestsResponse(
+				ListJoinRequestsResponse::read(reader)?,
+			)),
+			76 => Ok(EventBody::ApproveJoinRequestRequest(
+				ApproveJoinRequestRequest::read(reader)?,
+			)),
+			77 => Ok(EventBody::ApproveJoinRequestResponse(
+				ApproveJoinRequestResponse::read(reader)?,
+			)),
+			78 => Ok(EventBody::RejectJoinRequestRequest(
+				RejectJoinRequestRequest::read(reader)?,
+			)),
+			79 => Ok(EventBody::RejectJoinRequestResponse(
+				RejectJoinRequestResponse::read(reader)?,
+			)),
+			80 => Ok(EventBody::JoinRequestOutcomeRequest(
+				JoinRequestOutcomeRequest::read(reader)?,
+			)),
+			81 => Ok(EventBody::JoinRequestOutcomeResponse(
+				JoinRequestOutcomeResponse::read(reader)?,
+			)),
+			82 => Ok(EventBody::JoinRequestOutcomeNotification(
+				JoinRequestOutcomeNotification::read(reader)?,
+			)),
+			83 => Ok(EventBody::SetPowDifficultyRequest(
+				SetPowDifficultyRequest::read(reader)?,
+			)),
+			84 => Ok(EventBody::SetPowDifficultyResponse(
+				SetPowDifficultyResponse::read(reader)?,
+			)),
+			85 => Ok(EventBody::ListSessionsRequest(ListSessionsRequest::read(
+				reader,
+			)?)),
+			86 => Ok(EventBody::ListSessionsResponse(ListSessionsResponse::read(
+				reader,
+			)?)),
+			87 => Ok(EventBody::RevokeSessionRequest(RevokeSessionRequest::read(
+				reader,
+			)?)),
+			88 => Ok(EventBody::RevokeSessionResponse(
+				RevokeSessionResponse::read(reader)?,
+			)),
+			89 => Ok(EventBody::RotateAuthTokenRequest(
+				RotateAuthTokenRequest::read(reader)?,
+			)),
+			90 => Ok(EventBody::RotateAuthTokenResponse(
+				RotateAuthTokenResponse::read(reader)?,
+			)),
+			91 => Ok(EventBody::RevokeDeviceRequest(RevokeDeviceRequest::read(
+				reader,
+			)?)),
+			92 => Ok(EventBody::RevokeDeviceResponse(RevokeDeviceResponse::read(
+				reader,
+			)?)),
+			93 => Ok(EventBody::ListDeviceRevocationsRequest(
+				ListDeviceRevocationsRequest::read(reader)?,
+			)),
+			94 => Ok(EventBody::ListDeviceRevocationsResponse(
+				ListDeviceRevocationsResponse::read(reader)?,
+			)),
 			_ => Err(ErrorKind::CorruptedData("corrupted data in EventBody".to_string()).into()),
 		}
 	}
@@ -17,9 +17,29 @@ use concordutil::librustlet;
 use librustlet::nioruntime_log;
 use librustlet::*;
 use nioruntime_log::*;
+use std::sync::RwLock;
 
 info!();
 
+// the host secret when it was unlocked in memory from an encrypted secret file at startup
+static HOST_SECRET: RwLock<Option<[u8; 64]>> = RwLock::new(None);
+
+pub fn set_host_secret(secret: Option<[u8; 64]>) -> Result<(), concorderror::Error> {
+	let mut host_secret = nioruntime_util::lockw!(HOST_SECRET)?;
+	*host_secret = secret;
+	Ok(())
+}
+
+// the secret of this host. An unlocked secret is only held in memory, otherwise it is the one
+// the rustlet container loaded.
+pub fn host_secret() -> Result<Option<[u8; 64]>, concorderror::Error> {
+	let host_secret = nioruntime_util::lockr!(HOST_SECRET)?;
+	match *host_secret {
+		Some(secret) => Ok(Some(secret)),
+		None => Ok(secret!()),
+	}
+}
+
 pub fn _extract_server_id_from_query() -> Result<ServerId, librustlet::Error> {
 	let server_id = match query!("server_id") {
 		Some(server_id) => ServerId::from_urlencoding(server_id).map_err(|e| {
@@ -139,7 +159,7 @@ macro_rules! close {
 }
 
 #[macro_export]
-macro_rules! owner {
+macro_rules! host {
 	($conn_info:expr) => {{
 		match &$conn_info.pubkey {
 			None => {
@@ -148,6 +168,29 @@ macro_rules! owner {
 			Some(pubkey) => {
 				let server_pubkey = pubkey!();
 				if pubkey.to_bytes() != server_pubkey {
+					info!("not the host!");
+					return Ok(true);
+				}
+			}
+		}
+	}};
+}
+
+#[macro_export]
+macro_rules! owner {
+	($conn_info:expr, $ds_context:expr, $server_pubkey:expr, $server_id:expr) => {{
+		match &$conn_info.pubkey {
+			None => {
+				return Ok(true);
+			}
+			Some(pubkey) => {
+				let member =
+					$ds_context.get_server_member($server_pubkey, $server_id, pubkey.to_bytes())?;
+				let is_owner = match member {
+					Some(member) => member.roles & concorddata::concord::AUTH_FLAG_OWNER != 0,
+					None => false,
+				};
+				if !is_owner {
 					info!("not the owner!");
 					return Ok(true);
 				}
@@ -156,6 +199,34 @@ macro_rules! owner {
 	}};
 }
 
+#[macro_export]
+macro_rules! moderator {
+	($conn_info:expr, $ds_context:expr, $server_pubkey:expr, $server_id:expr) => {{
+		match &$conn_info.pubkey {
+			None => {
+				return Ok(true);
+			}
+			Some(pubkey) => {
+				let member =
+					$ds_context.get_server_member($server_pubkey, $server_id, pubkey.to_bytes())?;
+				let is_moderator = match member {
+					Some(member) => {
+						member.roles
+							& (concorddata::concord::AUTH_FLAG_OWNER
+								| concorddata::concord::AUTH_FLAG_MODERATOR)
+							!= 0
+					}
+					None => false,
+				};
+				if !is_moderator {
+					info!("not a moderator!");
+					return Ok(true);
+				}
+			}
+		}
+	}};
+}
+
 #[macro_export]
 macro_rules! member {
 	($conn_info:expr, $ds_context:expr) => {{
@@ -12,21 +12,34 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::auth::ws_auth;
-use crate::channel::{add_channel, delete_channel, get_channels, modify_channel};
+use crate::announcement::{cross_post, follow_channel, unfollow_channel};
+use crate::auth::{
+	list_device_revocations, list_sessions, revoke_device, revoke_session, rotate_auth_token,
+	ws_auth,
+};
+use crate::channel::{
+	add_category, add_channel, archive_channel, delete_category, delete_channel, get_channels,
+	modify_category, modify_channel, reorder_channels, set_channel_kind, set_channel_permissions,
+};
 use crate::conn_manager::ConnManager;
 use crate::invite::{
-	accept_invite, create_invite, delete_invite, join_server, list_invites, modify_invite,
-	view_invite,
+	accept_invite, approve_join_request, create_invite, delete_invite, get_invite_uses,
+	join_request_outcome, join_server, list_invites, list_join_requests, modify_invite,
+	reject_join_request, view_invite,
+};
+use crate::members::{get_members, search_members, set_member_roles};
+use crate::message::{
+	export_channel, get_messages, search_messages, send_message, subscribe_channel,
 };
-use crate::members::get_members;
-use crate::message::{get_messages, send_message, subscribe_channel};
+use crate::presence::PresenceManager;
 use crate::profile::{get_profile, set_profile};
-use crate::server::{create_server, delete_server, get_servers, modify_server};
+use crate::server::{create_server, delete_server, get_servers, modify_server, set_pow_difficulty};
+use crate::subscription::SubscriptionManager;
 use crate::types::*;
 use crate::{bin_event, close, send, try2};
 use concordconfig::ConcordConfig;
 use concorddata::concord::DSContext;
+use concorddata::types::Pubkey;
 use concorderror::Error as ConcordError;
 use concordutil::librustlet;
 use librustlet::nioruntime_log;
@@ -40,25 +53,41 @@ info!();
 fn process_open(
 	handle: ConnData,
 	conn_info: Arc<RwLock<HashMap<u128, ConnectionInfo>>>,
+	ds_context: &DSContext,
 ) -> Result<(), Error> {
 	let id = handle.get_connection_id();
 	debug!("websocket open: {}", id);
 
+	// the challenge is answered over the same socket, so the connection remembers which one
+	// it was sent. It is recorded before it is sent so that an answer can't arrive first.
+	let challenge = try2!(
+		ds_context.create_auth_challenge(pubkey!()),
+		"create challenge error"
+	);
 	let event = Event {
-		body: EventBody::ChallengeEvent(ChallengeEvent { challenge: id }).into(),
+		body: EventBody::ChallengeEvent(ChallengeEvent {
+			challenge: challenge.challenge,
+			server_pubkey: Pubkey::from_bytes(challenge.server_pubkey),
+			timestamp: challenge.timestamp,
+		})
+		.into(),
 		..Default::default()
 	};
-	send!(handle, event);
 
-	let mut conn_info = nioruntime_util::lockw!(conn_info)?;
+	{
+		let mut conn_info = nioruntime_util::lockw!(conn_info)?;
+		conn_info.insert(
+			id,
+			ConnectionInfo {
+				handle: handle.clone(),
+				pubkey: None,
+				session: None,
+				challenge: Some(challenge.challenge),
+			},
+		);
+	}
 
-	conn_info.insert(
-		id,
-		ConnectionInfo {
-			handle: handle.clone(),
-			pubkey: None,
-		},
-	);
+	send!(handle, event);
 
 	Ok(())
 }
@@ -69,6 +98,8 @@ fn process_authed_event(
 	ds_context: &DSContext,
 	config: &ConcordConfig,
 	conn_manager: Arc<RwLock<ConnManager>>,
+	presence: &PresenceManager,
+	subscriptions: &SubscriptionManager,
 	id: u128,
 ) -> Result<bool, Error> {
 	let res = match event.body {
@@ -86,7 +117,7 @@ fn process_authed_event(
 		}
 		EventBody::DeleteServerEvent(_) => {
 			try2!(
-				delete_server(connection_info, ds_context, &event),
+				delete_server(connection_info, ds_context, &event, config, presence),
 				"delete_server error"
 			)
 		}
@@ -120,12 +151,96 @@ fn process_authed_event(
 				"delete channel error"
 			)
 		}
+		EventBody::SetChannelPermissionsRequest(_) => {
+			try2!(
+				set_channel_permissions(connection_info, ds_context, &event),
+				"set channel permissions error"
+			)
+		}
+		EventBody::ArchiveChannelRequest(_) => {
+			try2!(
+				archive_channel(connection_info, ds_context, &event),
+				"archive channel error"
+			)
+		}
+		EventBody::SearchMessagesRequest(_) => {
+			try2!(
+				search_messages(connection_info, ds_context, &event),
+				"search messages error"
+			)
+		}
+		EventBody::ExportChannelRequest(_) => {
+			try2!(
+				export_channel(connection_info, ds_context, &event),
+				"export channel error"
+			)
+		}
+		EventBody::SetChannelKindRequest(_) => {
+			try2!(
+				set_channel_kind(connection_info, ds_context, &event),
+				"set channel kind error"
+			)
+		}
+		EventBody::FollowChannelRequest(_) => {
+			try2!(
+				follow_channel(connection_info, ds_context, &event, conn_manager, config),
+				"follow channel error"
+			)
+		}
+		EventBody::UnfollowChannelRequest(_) => {
+			try2!(
+				unfollow_channel(connection_info, ds_context, &event, conn_manager, config),
+				"unfollow channel error"
+			)
+		}
+		EventBody::CrossPostRequest(_) => {
+			try2!(
+				cross_post(connection_info, ds_context, &event, subscriptions),
+				"cross post error"
+			)
+		}
+		EventBody::AddCategoryRequest(_) => {
+			try2!(
+				add_category(connection_info, ds_context, &event),
+				"add category error"
+			)
+		}
+		EventBody::ModifyCategoryRequest(_) => {
+			try2!(
+				modify_category(connection_info, ds_context, &event),
+				"modify category error"
+			)
+		}
+		EventBody::DeleteCategoryRequest(_) => {
+			try2!(
+				delete_category(connection_info, ds_context, &event),
+				"delete category error"
+			)
+		}
+		EventBody::ReorderChannelsRequest(_) => {
+			try2!(
+				reorder_channels(connection_info, ds_context, &event),
+				"reorder channels error"
+			)
+		}
 		EventBody::GetMembersRequest(_) => {
 			try2!(
-				get_members(connection_info, ds_context, &event),
+				get_members(connection_info, ds_context, &event, presence),
 				"get members error"
 			)
 		}
+		EventBody::SearchMembersRequest(_) => {
+			try2!(
+				search_members(connection_info, ds_context, &event, presence),
+				"search members error"
+			)
+		}
+		EventBody::SetMemberRolesRequest(_) => {
+			try2!(
+				set_member_roles(connection_info, ds_context, &event),
+				"set member roles error"
+			)
+		}
 		EventBody::CreateInviteRequest(_) => {
 			try2!(
 				create_invite(connection_info, ds_context, &event),
@@ -144,6 +259,12 @@ fn process_authed_event(
 				"modify invite error"
 			)
 		}
+		EventBody::GetInviteUsesRequest(_) => {
+			try2!(
+				get_invite_uses(connection_info, ds_context, &event),
+				"get invite uses error"
+			)
+		}
 		EventBody::DeleteInviteRequest(_) => {
 			try2!(
 				delete_invite(connection_info, ds_context, &event),
@@ -152,7 +273,14 @@ fn process_authed_event(
 		}
 		EventBody::ViewInviteRequest(_) => {
 			try2!(
-				view_invite(connection_info, ds_context, &event, conn_manager, config),
+				view_invite(
+					connection_info,
+					ds_context,
+					&event,
+					conn_manager,
+					config,
+					presence
+				),
 				"view invite request error"
 			)
 		}
@@ -162,6 +290,66 @@ fn process_authed_event(
 				"accept invite request error"
 			)
 		}
+		EventBody::ListSessionsRequest(_) => {
+			try2!(
+				list_sessions(connection_info, ds_context, &event),
+				"list sessions error"
+			)
+		}
+		EventBody::RevokeSessionRequest(_) => {
+			try2!(
+				revoke_session(connection_info, ds_context, &event, presence),
+				"revoke session error"
+			)
+		}
+		EventBody::RevokeDeviceRequest(_) => {
+			try2!(
+				revoke_device(connection_info, ds_context, &event),
+				"revoke device error"
+			)
+		}
+		EventBody::ListDeviceRevocationsRequest(_) => {
+			try2!(
+				list_device_revocations(connection_info, ds_context, &event),
+				"list device revocations error"
+			)
+		}
+		EventBody::RotateAuthTokenRequest(_) => {
+			try2!(
+				rotate_auth_token(connection_info, ds_context, &event, config, presence),
+				"rotate auth token error"
+			)
+		}
+		EventBody::SetPowDifficultyRequest(_) => {
+			try2!(
+				set_pow_difficulty(connection_info, ds_context, &event),
+				"set pow difficulty error"
+			)
+		}
+		EventBody::ListJoinRequestsRequest(_) => {
+			try2!(
+				list_join_requests(connection_info, ds_context, &event),
+				"list join requests error"
+			)
+		}
+		EventBody::ApproveJoinRequestRequest(_) => {
+			try2!(
+				approve_join_request(connection_info, ds_context, &event, conn_manager, config),
+				"approve join request error"
+			)
+		}
+		EventBody::RejectJoinRequestRequest(_) => {
+			try2!(
+				reject_join_request(connection_info, ds_context, &event, conn_manager, config),
+				"reject join request error"
+			)
+		}
+		EventBody::JoinRequestOutcomeRequest(_) => {
+			try2!(
+				join_request_outcome(connection_info, &event, presence),
+				"join request outcome error"
+			)
+		}
 		EventBody::JoinServerRequest(_) => {
 			try2!(
 				join_server(connection_info, ds_context, &event, conn_manager, config),
@@ -188,13 +376,27 @@ fn process_authed_event(
 		}
 		EventBody::SendMessage(_) => {
 			try2!(
-				send_message(connection_info, ds_context, &event, conn_manager, config),
+				send_message(
+					connection_info,
+					ds_context,
+					&event,
+					subscriptions,
+					conn_manager,
+					config
+				),
 				"send message error"
 			)
 		}
 		EventBody::SubscribeChannel(_) => {
 			try2!(
-				subscribe_channel(connection_info, ds_context, &event, conn_manager, config),
+				subscribe_channel(
+					connection_info,
+					ds_context,
+					&event,
+					subscriptions,
+					conn_manager,
+					config
+				),
 				"subscribe channel error"
 			)
 		}
@@ -216,6 +418,8 @@ fn process_binary(
 	ds_context: &DSContext,
 	config: &ConcordConfig,
 	conn_manager: Arc<RwLock<ConnManager>>,
+	presence: &PresenceManager,
+	subscriptions: &SubscriptionManager,
 ) -> Result<(), Error> {
 	let id = handle.get_connection_id();
 	let event = bin_event!();
@@ -232,6 +436,23 @@ fn process_binary(
 			if close {
 				let mut conn_info = nioruntime_util::lockw!(conn_info)?;
 				close!(handle, conn_info);
+			} else {
+				let (pubkey, session) = {
+					let conn_info = nioruntime_util::lockr!(conn_info)?;
+					match conn_info.get(&id) {
+						Some(info) => (info.pubkey.clone(), info.session),
+						None => (None, None),
+					}
+				};
+				match pubkey {
+					Some(pubkey) => {
+						try2!(
+							presence.connect(&handle, &pubkey, session, ds_context),
+							"presence connect error"
+						);
+					}
+					None => {}
+				}
 			}
 		}
 		_ => {
@@ -246,6 +467,7 @@ fn process_binary(
 						match &connection_info.pubkey {
 							Some(pubkey) => {
 								debug!("authed event on {}: {:?}, pubkey={:?}", id, event, pubkey);
+								try2!(presence.touch(id, ds_context), "presence touch error");
 								// we know the user is authed, now process events
 
 								process_authed_event(
@@ -254,6 +476,8 @@ fn process_binary(
 									ds_context,
 									config,
 									conn_manager,
+									presence,
+									subscriptions,
 									id,
 								)?
 							}
@@ -276,11 +500,24 @@ fn process_binary(
 fn process_close(
 	handle: ConnData,
 	conn_info: Arc<RwLock<HashMap<u128, ConnectionInfo>>>,
+	ds_context: &DSContext,
+	presence: &PresenceManager,
+	subscriptions: &SubscriptionManager,
 ) -> Result<(), Error> {
 	let id = handle.get_connection_id();
 	debug!("close : {},", id);
-	let mut conn_info = nioruntime_util::lockw!(conn_info)?;
-	conn_info.remove(&id);
+	{
+		let mut conn_info = nioruntime_util::lockw!(conn_info)?;
+		conn_info.remove(&id);
+	}
+	try2!(
+		presence.disconnect(id, ds_context),
+		"presence disconnect error"
+	);
+	try2!(
+		subscriptions.disconnect(id),
+		"subscriptions disconnect error"
+	);
 	Ok(())
 }
 
@@ -288,6 +525,23 @@ pub fn init_ws(cconfig: ConcordConfig) -> Result<(), ConcordError> {
 	let conn_info = Arc::new(RwLock::new(HashMap::new()));
 	let ds_context = DSContext::new(cconfig.root_dir.clone())?;
 	let conn_manager = Arc::new(RwLock::new(ConnManager::new()));
+	let presence = PresenceManager::new();
+	let subscriptions = SubscriptionManager::new();
+
+	// move users without recent activity to idle
+	{
+		let ds_context = DSContext::new(cconfig.root_dir.clone())?;
+		let presence = presence.clone();
+		std::thread::spawn(move || loop {
+			std::thread::sleep(std::time::Duration::from_millis(1000 * 30));
+			match presence.check_idle(&ds_context) {
+				Ok(_) => {}
+				Err(e) => {
+					error!("Presence idle check generated error: {}", e.to_string());
+				}
+			}
+		});
+	}
 
 	socklet!("ws", {
 		let conn_info = conn_info.clone();
@@ -295,13 +549,21 @@ pub fn init_ws(cconfig: ConcordConfig) -> Result<(), ConcordError> {
 		let handle = handle!()?;
 		match event!()? {
 			Socklet::Open => {
-				process_open(handle, conn_info)?;
+				process_open(handle, conn_info, &ds_context)?;
 			}
 			Socklet::Binary => {
-				process_binary(handle, conn_info, &ds_context, &cconfig, conn_manager)?;
+				process_binary(
+					handle,
+					conn_info,
+					&ds_context,
+					&cconfig,
+					conn_manager,
+					&presence,
+					&subscriptions,
+				)?;
 			}
 			Socklet::Close => {
-				process_close(handle, conn_info)?;
+				process_close(handle, conn_info, &ds_context, &presence, &subscriptions)?;
 			}
 			_ => {
 				warn!(
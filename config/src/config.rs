@@ -19,6 +19,12 @@ pub struct ConcordConfig {
 	pub port: u16,
 	pub host: String,
 	pub root_dir: String,
+	/// Store only a hash of the instance owner auth token
+	pub hash_auth_token: bool,
+	/// Print the instance owner auth token to stdout in addition to the main log
+	pub print_auth_token: bool,
+	/// Host secret unlocked from an encrypted secret file. It is only held in memory
+	pub secret: Option<[u8; 64]>,
 }
 
 impl Default for ConcordConfig {
@@ -28,6 +34,9 @@ impl Default for ConcordConfig {
 			port: 9919,
 			host: "127.0.0.1".to_string(),
 			root_dir: "~/.concord".to_string(),
+			hash_auth_token: false,
+			print_auth_token: true,
+			secret: None,
 		}
 	}
 }
@@ -0,0 +1,177 @@
+// Copyright 2022 37 Miners, LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+// Backup and restore of the host identity. The identity is the ed25519 secret of the onion
+// service, stored by tor in its hs_ed25519_secret_key file. A backup holds the expanded secret
+// encrypted with a passphrase, see keystore. Restoring writes the secret, public key and
+// hostname files of the onion service so that the onion address, and with it the server
+// pubkeys, are the same as before.
+
+use crate::keystore;
+use crate::types::Pubkey;
+use concorderror::{Error, ErrorKind};
+use ed25519_dalek::{ExpandedSecretKey, PublicKey};
+use std::path::Path;
+use zeroize::Zeroize;
+
+// tor key files start with a 32 byte header
+const TOR_SECRET_HEADER: &[u8; 32] = b"== ed25519v1-secret: type0 ==\0\0\0";
+const TOR_PUBLIC_HEADER: &[u8; 32] = b"== ed25519v1-public: type0 ==\0\0\0";
+const SECRET_LEN: usize = 64;
+
+// write an encrypted backup of the secret in secret_file. If the secret file is itself stored
+// encrypted, the same passphrase unlocks it.
+pub fn export_backup(secret_file: &str, passphrase: &str, backup_file: &str) -> Result<(), Error> {
+	let encrypted_path = keystore::encrypted_path(secret_file);
+	let mut data = if Path::new(&encrypted_path).exists() {
+		keystore::decrypt(&std::fs::read(&encrypted_path)?, passphrase)?
+	} else {
+		std::fs::read(secret_file)?
+	};
+
+	let res = match strip_header(&data) {
+		Ok(secret) => keystore::encrypt(secret, passphrase),
+		Err(e) => Err(e),
+	};
+	data.zeroize();
+	std::fs::write(backup_file, res?)?;
+	Ok(())
+}
+
+// restore the secret in backup_file to secret_file, along with the public key and hostname
+// files next to it. Returns the restored onion address.
+pub fn import_backup(
+	backup_file: &str,
+	passphrase: &str,
+	secret_file: &str,
+) -> Result<String, Error> {
+	let mut secret = keystore::decrypt(&std::fs::read(backup_file)?, passphrase)?;
+	let res = restore(&secret, passphrase, secret_file);
+	secret.zeroize();
+	res
+}
+
+fn restore(secret: &[u8], passphrase: &str, secret_file: &str) -> Result<String, Error> {
+	if secret.len() != SECRET_LEN {
+		return Err(ErrorKind::KeystoreError("backup is not an ed25519 secret".to_string()).into());
+	}
+	let secret_key = ExpandedSecretKey::from_bytes(secret)?;
+	let public_key: PublicKey = (&secret_key).into();
+	let onion = format!("{}.onion", Pubkey::from_dalek(public_key).to_onion()?);
+
+	let mut secret_data = TOR_SECRET_HEADER.to_vec();
+	secret_data.append(&mut secret.to_vec());
+	let res = keystore::write_private(secret_file, &secret_data);
+	secret_data.zeroize();
+	res?;
+
+	// keep the secret encrypted at rest if it was before
+	if Path::new(&keystore::encrypted_path(secret_file)).exists() {
+		keystore::encrypt_file(secret_file, passphrase)?;
+	}
+
+	let dir = match Path::new(secret_file).parent() {
+		Some(dir) => dir.to_path_buf(),
+		None => Path::new(".").to_path_buf(),
+	};
+	let mut public_data = TOR_PUBLIC_HEADER.to_vec();
+	public_data.append(&mut public_key.as_bytes().to_vec());
+	std::fs::write(dir.join("hs_ed25519_public_key"), public_data)?;
+	std::fs::write(dir.join("hostname"), format!("{}\n", onion))?;
+
+	Ok(onion)
+}
+
+// returns the secret without the tor header, if there is one.
+pub(crate) fn strip_header(data: &[u8]) -> Result<&[u8], Error> {
+	if data.len() == SECRET_LEN {
+		Ok(data)
+	} else if data.len() == TOR_SECRET_HEADER.len() + SECRET_LEN
+		&& data[0..TOR_SECRET_HEADER.len()] == TOR_SECRET_HEADER[..]
+	{
+		Ok(&data[TOR_SECRET_HEADER.len()..])
+	} else {
+		Err(ErrorKind::KeystoreError("unrecognized secret key file".to_string()).into())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use ed25519_dalek::SecretKey;
+
+	fn test_secret() -> Result<(Vec<u8>, String), Error> {
+		let secret_key = ExpandedSecretKey::from(&SecretKey::from_bytes(&[7u8; 32])?);
+		let public_key: PublicKey = (&secret_key).into();
+		let onion = format!("{}.onion", Pubkey::from_dalek(public_key).to_onion()?);
+		Ok((secret_key.to_bytes().to_vec(), onion))
+	}
+
+	#[test]
+	fn test_export_import() -> Result<(), Error> {
+		let (secret, onion) = test_secret()?;
+		let dir = tempfile::tempdir()?;
+		let secret_file = dir.path().join("hs_ed25519_secret_key");
+		let secret_file = secret_file.display().to_string();
+		let mut secret_data = TOR_SECRET_HEADER.to_vec();
+		secret_data.append(&mut secret.clone());
+		std::fs::write(&secret_file, &secret_data)?;
+
+		let backup_file = dir.path().join("backup").display().to_string();
+		export_backup(&secret_file, "passphrase", &backup_file)?;
+		assert!(import_backup(&backup_file, "wrong passphrase", &secret_file).is_err());
+
+		let restore_dir = tempfile::tempdir()?;
+		let restored_file = restore_dir.path().join("hs_ed25519_secret_key");
+		let restored_file = restored_file.display().to_string();
+		assert_eq!(
+			import_backup(&backup_file, "passphrase", &restored_file)?,
+			onion
+		);
+		assert_eq!(std::fs::read(&restored_file)?, secret_data);
+		let hostname = std::fs::read_to_string(restore_dir.path().join("hostname"))?;
+		assert_eq!(hostname, format!("{}\n", onion));
+		let public_data = std::fs::read(restore_dir.path().join("hs_ed25519_public_key"))?;
+		assert_eq!(
+			&public_data[0..TOR_PUBLIC_HEADER.len()],
+			&TOR_PUBLIC_HEADER[..]
+		);
+		assert_eq!(public_data.len(), TOR_PUBLIC_HEADER.len() + 32);
+		Ok(())
+	}
+
+	#[test]
+	fn test_export_import_encrypted() -> Result<(), Error> {
+		let (secret, onion) = test_secret()?;
+		let dir = tempfile::tempdir()?;
+		let secret_file = dir.path().join("hs_ed25519_secret_key");
+		let secret_file = secret_file.display().to_string();
+		std::fs::write(&secret_file, &secret)?;
+		keystore::encrypt_file(&secret_file, "passphrase")?;
+
+		let backup_file = dir.path().join("backup").display().to_string();
+		export_backup(&secret_file, "passphrase", &backup_file)?;
+
+		// a secret that was encrypted at rest stays encrypted when it is restored
+		assert_eq!(
+			import_backup(&backup_file, "passphrase", &secret_file)?,
+			onion
+		);
+		assert!(!Path::new(&secret_file).exists());
+		assert_eq!(
+			keystore::unlock(&secret_file, "passphrase")?.map(|s| s.to_vec()),
+			Some(secret.to_vec())
+		);
+		Ok(())
+	}
+}
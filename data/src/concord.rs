@@ -12,11 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::hash::Hashed;
 use crate::lmdb::{Batch, Store};
 use crate::nioruntime_log;
+use crate::pow;
 use crate::ser::serialize_default;
 use crate::ser::{BinReader, ProtocolVersion, Readable, Reader, Writeable, Writer};
-use crate::types::{Invite, ProfileData, Pubkey, SerString, ServerId};
+use crate::types::{
+	DeviceCertificate, DeviceRevocation, Invite, PageCursor, ProfileData, Pubkey, SerString,
+	ServerId,
+};
 use concorderror::{Error, ErrorKind};
 use nioruntime_log::*;
 
@@ -27,9 +32,20 @@ use std::path::PathBuf;
 const DB_NAME: &str = "concord";
 const MESSAGE_BATCH_SIZE: u64 = 100;
 const MEMBER_BATCH_SIZE: u64 = 100;
+const SERVER_DELETE_CHUNK_SIZE: u64 = 1000;
+// maximum number of messages returned by a search
+pub const MAX_SEARCH_RESULTS: usize = 100;
+// maximum number of message batches scanned by a single search call
+const MAX_SEARCH_BATCHES: u64 = 10;
 
 pub const TOKEN_EXPIRATION: u128 = 1000 * 60 * 60;
 
+// challenges must be answered within this many milliseconds.
+pub const CHALLENGE_EXPIRATION: u128 = 1000 * 30;
+
+// device certificates may be dated at most this many milliseconds in the future.
+pub const CERTIFICATE_MAX_SKEW: u128 = 1000 * 60 * 5;
+
 info!();
 
 pub fn get_default_profile() -> Profile {
@@ -174,15 +190,36 @@ impl Readable for UserPubKey {
 	}
 }
 
+// a single use challenge, bound to the server that issued it and the time it was issued
+#[derive(Debug, Clone)]
 pub struct Challenge {
-	pub challenge: [u8; 8],
+	pub challenge: [u8; 32],
+	pub server_pubkey: [u8; 32],
+	pub timestamp: u128,
+}
+
+impl Challenge {
+	// a random challenge issued now by the server
+	pub fn new(server_pubkey: [u8; 32]) -> Result<Self, Error> {
+		Ok(Challenge {
+			challenge: rand::random(),
+			server_pubkey,
+			timestamp: std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)?
+				.as_millis(),
+		})
+	}
+
+	pub fn expired(&self, time_now: u128) -> bool {
+		time_now.saturating_sub(self.timestamp) > CHALLENGE_EXPIRATION
+	}
 }
 
 impl Writeable for Challenge {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
-		for i in 0..8 {
-			writer.write_u8(self.challenge[i])?;
-		}
+		writer.write_fixed_bytes(self.challenge)?;
+		writer.write_fixed_bytes(self.server_pubkey)?;
+		writer.write_u128(self.timestamp)?;
 
 		Ok(())
 	}
@@ -190,13 +227,15 @@ impl Writeable for Challenge {
 
 impl Readable for Challenge {
 	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
-		let mut challenge = [0u8; 8];
-
-		for i in 0..8 {
-			challenge[i] = reader.read_u8()?;
-		}
+		let challenge = reader.read_fixed_bytes(32)?.as_slice().try_into()?;
+		let server_pubkey = reader.read_fixed_bytes(32)?.as_slice().try_into()?;
+		let timestamp = reader.read_u128()?;
 
-		Ok(Challenge { challenge })
+		Ok(Challenge {
+			challenge,
+			server_pubkey,
+			timestamp,
+		})
 	}
 }
 
@@ -254,6 +293,8 @@ pub struct Member {
 	pub roles: u128,
 	pub join_time: u64,
 	pub modified_time: u64,
+	// the creator of the invite the member joined through
+	pub inviter: Option<Pubkey>,
 }
 
 // internal member datastructure
@@ -266,6 +307,7 @@ pub struct MemberImpl {
 	pub batch_num: u64,
 	pub join_time: u64,
 	pub modified_time: u64,
+	pub inviter: Option<Pubkey>,
 }
 
 struct MemberMetaDataKey {
@@ -300,18 +342,13 @@ struct MemberKeyAuthImpl {
 	user_pubkey: Pubkey,
 }
 
-#[derive(Debug)]
-struct MemberBatchLookupKeyImpl {
-	server_pubkey: Pubkey,
-	server_id: ServerId,
-	batch_num: u64,
-}
-
-#[derive(Debug)]
-struct AuthBatchLookupKeyImpl {
+// index of members by lower case user_name used for prefix searches.
+// the user_pubkey is left out when building a search prefix.
+struct MemberNameKeyImpl {
 	server_pubkey: Pubkey,
 	server_id: ServerId,
-	batch_num: u64,
+	user_name: String,
+	user_pubkey: Option<Pubkey>,
 }
 
 struct MemberValueImpl {
@@ -319,6 +356,7 @@ struct MemberValueImpl {
 	join_time: u64,
 	modified_time: u64,
 	batch_num: u64,
+	inviter: Option<Pubkey>,
 }
 
 impl From<MemberImpl> for Member {
@@ -329,6 +367,7 @@ impl From<MemberImpl> for Member {
 			profile_data: None,
 			join_time: mi.join_time,
 			modified_time: mi.modified_time,
+			inviter: mi.inviter,
 		}
 	}
 }
@@ -416,58 +455,6 @@ impl Readable for MemberKeyIttImpl {
 	}
 }
 
-impl Writeable for AuthBatchLookupKeyImpl {
-	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
-		writer.write_u8(MEMBER_AUTH_PREFIX)?;
-		Writeable::write(&self.server_pubkey, writer)?;
-		Writeable::write(&self.server_id, writer)?;
-		writer.write_u64(self.batch_num)?;
-
-		Ok(())
-	}
-}
-
-impl Readable for AuthBatchLookupKeyImpl {
-	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
-		reader.read_u8()?;
-		let server_pubkey = Pubkey::read(reader)?;
-		let server_id = ServerId::read(reader)?;
-		let batch_num = reader.read_u64()?;
-
-		Ok(Self {
-			server_pubkey,
-			server_id,
-			batch_num,
-		})
-	}
-}
-
-impl Writeable for MemberBatchLookupKeyImpl {
-	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
-		writer.write_u8(MEMBER_ITT_PREFIX)?;
-		Writeable::write(&self.server_pubkey, writer)?;
-		Writeable::write(&self.server_id, writer)?;
-		writer.write_u64(self.batch_num)?;
-
-		Ok(())
-	}
-}
-
-impl Readable for MemberBatchLookupKeyImpl {
-	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
-		reader.read_u8()?;
-		let server_pubkey = Pubkey::read(reader)?;
-		let server_id = ServerId::read(reader)?;
-		let batch_num = reader.read_u64()?;
-
-		Ok(Self {
-			server_pubkey,
-			server_id,
-			batch_num,
-		})
-	}
-}
-
 impl From<&MemberImpl> for MemberKeyHashImpl {
 	fn from(member: &MemberImpl) -> MemberKeyHashImpl {
 		Self {
@@ -552,6 +539,25 @@ impl Readable for MemberKeyAuthImpl {
 	}
 }
 
+impl Writeable for MemberNameKeyImpl {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u8(MEMBER_NAME_PREFIX)?;
+		Writeable::write(&self.server_pubkey, writer)?;
+		Writeable::write(&self.server_id, writer)?;
+		// no length prefix so that a partial name is a prefix of the full key
+		writer.write_fixed_bytes(self.user_name.as_bytes())?;
+		match &self.user_pubkey {
+			Some(user_pubkey) => {
+				writer.write_u8(0)?;
+				Writeable::write(user_pubkey, writer)?;
+			}
+			None => {}
+		}
+
+		Ok(())
+	}
+}
+
 impl From<&MemberImpl> for MemberValueImpl {
 	fn from(member: &MemberImpl) -> MemberValueImpl {
 		Self {
@@ -559,6 +565,7 @@ impl From<&MemberImpl> for MemberValueImpl {
 			join_time: member.join_time,
 			modified_time: member.modified_time,
 			batch_num: member.batch_num,
+			inviter: member.inviter,
 		}
 	}
 }
@@ -569,6 +576,16 @@ impl Writeable for MemberValueImpl {
 		writer.write_u64(self.join_time)?;
 		writer.write_u64(self.modified_time)?;
 		writer.write_u64(self.batch_num)?;
+
+		// version of the fields that follow
+		writer.write_u8(1)?;
+		match &self.inviter {
+			Some(inviter) => {
+				writer.write_u8(1)?;
+				Writeable::write(inviter, writer)?;
+			}
+			None => writer.write_u8(0)?,
+		}
 		Ok(())
 	}
 }
@@ -580,15 +597,139 @@ impl Readable for MemberValueImpl {
 		let modified_time = reader.read_u64()?;
 		let batch_num = reader.read_u64()?;
 
+		// members saved before the inviter was recorded end here
+		let inviter = match reader.read_u8() {
+			Ok(1) => match reader.read_u8()? {
+				0 => None,
+				_ => Some(Pubkey::read(reader)?),
+			},
+			Ok(_) => {
+				return Err(ErrorKind::CorruptedData("unknown Member version".to_string()).into())
+			}
+			Err(_) => None,
+		};
+
 		Ok(MemberValueImpl {
 			roles,
 			join_time,
 			modified_time,
 			batch_num,
+			inviter,
+		})
+	}
+}
+
+// a record of an invite being accepted
+#[derive(Debug, Clone)]
+pub struct InviteUse {
+	pub invite_id: u128,
+	pub user_pubkey: [u8; 32],
+	pub time: u64,
+	// the roles and join time of the resulting member. If the user was already a member
+	// these are the existing values and new_member is false.
+	pub roles: u128,
+	pub join_time: u64,
+	pub new_member: bool,
+}
+
+impl Writeable for InviteUse {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u128(self.invite_id)?;
+		writer.write_fixed_bytes(self.user_pubkey)?;
+		writer.write_u64(self.time)?;
+		writer.write_u128(self.roles)?;
+		writer.write_u64(self.join_time)?;
+		match self.new_member {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		Ok(())
+	}
+}
+
+impl Readable for InviteUse {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let invite_id = reader.read_u128()?;
+		let user_pubkey = reader.read_fixed_bytes(32)?.as_slice().try_into()?;
+		let time = reader.read_u64()?;
+		let roles = reader.read_u128()?;
+		let join_time = reader.read_u64()?;
+		let new_member = reader.read_u8()? != 0;
+		Ok(Self {
+			invite_id,
+			user_pubkey,
+			time,
+			roles,
+			join_time,
+			new_member,
+		})
+	}
+}
+
+// a pending request to join a server that requires approval. The roles and landing channel
+// of the invite are copied so that the invite may change or expire while the request waits.
+#[derive(Debug, Clone)]
+pub struct JoinRequest {
+	pub user_pubkey: [u8; 32],
+	pub invite_id: u128,
+	pub inviter: [u8; 32],
+	pub roles: u128,
+	pub landing_channel: u64,
+	pub user_name: String,
+	pub user_bio: String,
+	pub request_time: u64,
+}
+
+impl Writeable for JoinRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_fixed_bytes(self.user_pubkey)?;
+		writer.write_u128(self.invite_id)?;
+		writer.write_fixed_bytes(self.inviter)?;
+		writer.write_u128(self.roles)?;
+		writer.write_u64(self.landing_channel)?;
+		Writeable::write(&SerString::from(self.user_name.as_str()), writer)?;
+		Writeable::write(&SerString::from(self.user_bio.as_str()), writer)?;
+		writer.write_u64(self.request_time)?;
+		Ok(())
+	}
+}
+
+impl Readable for JoinRequest {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let user_pubkey = reader.read_fixed_bytes(32)?.as_slice().try_into()?;
+		let invite_id = reader.read_u128()?;
+		let inviter = reader.read_fixed_bytes(32)?.as_slice().try_into()?;
+		let roles = reader.read_u128()?;
+		let landing_channel = reader.read_u64()?;
+		let user_name = SerString::read(reader)?.data;
+		let user_bio = SerString::read(reader)?.data;
+		let request_time = reader.read_u64()?;
+		Ok(Self {
+			user_pubkey,
+			invite_id,
+			inviter,
+			roles,
+			landing_channel,
+			user_name,
+			user_bio,
+			request_time,
 		})
 	}
 }
 
+// the outcome of accepting an invite
+#[derive(Debug)]
+pub enum AcceptInviteResult {
+	// the user is a member, with the landing channel of the invite, 0 if there is none.
+	Joined(ServerInfoReply, u64),
+	// the server requires approval and a join request has been queued.
+	Pending,
+	// the proof of work is missing or too weak for the server's current difficulty.
+	ProofOfWorkRequired(u8),
+	// the invite is not valid.
+	Rejected,
+}
+
 // type of message
 #[derive(Debug, Clone)]
 pub enum MessageType {
@@ -611,6 +752,8 @@ pub struct Message {
 	pub seqno: u64,
 	pub user_name: String,
 	pub user_bio: String,
+	// present if the message was signed by a linked device instead of the user's own key
+	pub device_certificate: Option<DeviceCertificate>,
 }
 
 #[derive(Debug)]
@@ -629,6 +772,7 @@ struct MessageValueImpl {
 	payload: Vec<u8>,
 	signature: [u8; 64],
 	message_type: MessageType,
+	device_certificate: Option<DeviceCertificate>,
 }
 
 #[derive(Debug)]
@@ -716,6 +860,13 @@ impl Writeable for MessageValueImpl {
 			MessageType::Text => writer.write_u8(0)?,
 			MessageType::Binary => writer.write_u8(1)?,
 		}
+		match &self.device_certificate {
+			Some(device_certificate) => {
+				writer.write_u8(1)?;
+				Writeable::write(device_certificate, writer)?;
+			}
+			None => writer.write_u8(0)?,
+		}
 
 		Ok(())
 	}
@@ -738,12 +889,25 @@ impl Readable for MessageValueImpl {
 			_ => MessageType::Binary,
 		};
 
+		// messages saved before device certificates end here
+		let device_certificate = match reader.read_u8() {
+			Ok(1) => Some(DeviceCertificate::read(reader)?),
+			Ok(0) | Err(_) => None,
+			Ok(_) => {
+				return Err(ErrorKind::CorruptedData(
+					"unknown MessageValueImpl device certificate flag".to_string(),
+				)
+				.into())
+			}
+		};
+
 		let signature = signature.as_slice().try_into()?;
 
 		Ok(MessageValueImpl {
 			payload,
 			signature,
 			message_type,
+			device_certificate,
 		})
 	}
 }
@@ -810,6 +974,12 @@ pub struct JoinInfoReply {
 	pub server_id: [u8; 8],
 	pub name: String,
 	pub inviter_pubkey: [u8; 32],
+	pub description: String,
+	pub topic: String,
+	pub creation_time: u128,
+	pub rules: String,
+	pub verification_level: u8,
+	pub pow_difficulty: u8,
 }
 
 // information about the server
@@ -819,6 +989,36 @@ pub struct ServerInfo {
 	pub name: String,
 	pub joined: bool,
 	pub seqno: u64,
+	pub description: String,
+	pub topic: String,
+	pub creation_time: u128,
+	// channel_id of the channel clients open first, 0 if not set.
+	pub default_channel: u64,
+	pub rules: String,
+	pub verification_level: u8,
+	// accepting an invite creates a join request that a moderator must approve.
+	pub require_approval: bool,
+	// leading zero bits required of the proof of work when accepting an invite, 0 for none.
+	pub pow_difficulty: u8,
+}
+
+impl ServerInfo {
+	fn reply(self, server_id: [u8; 8]) -> ServerInfoReply {
+		ServerInfoReply {
+			pubkey: self.pubkey,
+			server_id,
+			name: self.name,
+			seqno: self.seqno,
+			description: self.description,
+			topic: self.topic,
+			creation_time: self.creation_time,
+			default_channel: self.default_channel,
+			rules: self.rules,
+			verification_level: self.verification_level,
+			require_approval: self.require_approval,
+			pow_difficulty: self.pow_difficulty,
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -827,6 +1027,28 @@ pub struct ServerInfoReply {
 	pub server_id: [u8; 8],
 	pub name: String,
 	pub seqno: u64,
+	pub description: String,
+	pub topic: String,
+	pub creation_time: u128,
+	pub default_channel: u64,
+	pub rules: String,
+	pub verification_level: u8,
+	pub require_approval: bool,
+	pub pow_difficulty: u8,
+}
+
+// the fields of a server that can be changed with modify_server. Fields that are None are
+// left unchanged.
+#[derive(Debug, Default)]
+pub struct ServerUpdate {
+	pub name: Option<String>,
+	pub description: Option<String>,
+	pub topic: Option<String>,
+	pub default_channel: Option<u64>,
+	pub rules: Option<String>,
+	pub verification_level: Option<u8>,
+	pub require_approval: Option<bool>,
+	pub pow_difficulty: Option<u8>,
 }
 
 // the Writeable implmenetation for serializing ServerInfo
@@ -850,6 +1072,20 @@ impl Writeable for ServerInfo {
 
 		writer.write_u64(self.seqno)?;
 
+		// version of the fields that follow
+		writer.write_u8(3)?;
+		Writeable::write(&SerString::from(self.description.as_str()), writer)?;
+		Writeable::write(&SerString::from(self.topic.as_str()), writer)?;
+		writer.write_u128(self.creation_time)?;
+		writer.write_u64(self.default_channel)?;
+		Writeable::write(&SerString::from(self.rules.as_str()), writer)?;
+		writer.write_u8(self.verification_level)?;
+		match self.require_approval {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+		writer.write_u8(self.pow_difficulty)?;
+
 		Ok(())
 	}
 }
@@ -876,16 +1112,69 @@ impl Readable for ServerInfo {
 
 		let seqno = reader.read_u64()?;
 
+		// servers saved before these fields existed end here, version 1 has no approval setting
+		// and version 2 has no proof of work.
+		let (
+			description,
+			topic,
+			creation_time,
+			default_channel,
+			rules,
+			verification_level,
+			require_approval,
+			pow_difficulty,
+		) = match reader.read_u8() {
+			Ok(version @ 1..=3) => (
+				SerString::read(reader)?.data,
+				SerString::read(reader)?.data,
+				reader.read_u128()?,
+				reader.read_u64()?,
+				SerString::read(reader)?.data,
+				reader.read_u8()?,
+				match version {
+					1 => false,
+					_ => reader.read_u8()? != 0,
+				},
+				match version {
+					3 => reader.read_u8()?,
+					_ => 0,
+				},
+			),
+			Ok(_) => {
+				return Err(
+					ErrorKind::CorruptedData("unknown ServerInfo version".to_string()).into(),
+				)
+			}
+			Err(_) => (
+				"".to_string(),
+				"".to_string(),
+				0,
+				0,
+				"".to_string(),
+				0,
+				false,
+				0,
+			),
+		};
+
 		Ok(ServerInfo {
 			pubkey,
 			name,
 			joined,
 			seqno,
+			description,
+			topic,
+			creation_time,
+			default_channel,
+			rules,
+			verification_level,
+			require_approval,
+			pow_difficulty,
 		})
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ChannelKey {
 	pub server_pubkey: [u8; 32],
 	pub server_id: [u8; 8],
@@ -936,6 +1225,135 @@ pub struct Channel {
 	pub name: String,
 	pub description: String,
 	pub channel_id: u64,
+	pub position: u64,
+	// 0 if the channel is not in a category.
+	pub category_id: u64,
+	pub overwrites: Vec<PermissionOverwrite>,
+	// CHANNEL_KIND_TEXT or CHANNEL_KIND_ANNOUNCEMENT
+	pub kind: u8,
+	// in announcement channels, the roles that may post.
+	pub post_roles: u128,
+	// archived channels are read-only and not listed by default.
+	pub archived: bool,
+}
+
+impl Channel {
+	// resolve the channel permissions of a user. roles is None if the user is not a member.
+	// Owners have every permission. Other members start with view and send (only view in an
+	// announcement channel unless they hold one of the post roles), then the overwrites of
+	// every role they hold are applied, then the overwrite for their pubkey. Nobody can send
+	// in an archived channel.
+	pub fn permissions(&self, roles: Option<u128>, user_pubkey: [u8; 32]) -> u8 {
+		let permissions = match roles {
+			Some(roles) => self.member_permissions(roles, Some(user_pubkey)),
+			None => return 0,
+		};
+		self.apply_archived(permissions)
+	}
+
+	// the permissions of a plain member with no user overwrite. Used when the request comes
+	// from another host rather than from a user of this server.
+	pub fn everyone_permissions(&self) -> u8 {
+		self.apply_archived(self.member_permissions(AUTH_FLAG_MEMBER, None))
+	}
+
+	fn apply_archived(&self, permissions: u8) -> u8 {
+		match self.archived {
+			true => permissions & !CHANNEL_PERMISSION_SEND,
+			false => permissions,
+		}
+	}
+
+	fn member_permissions(&self, roles: u128, user_pubkey: Option<[u8; 32]>) -> u8 {
+		if roles & AUTH_FLAG_OWNER != 0 {
+			return CHANNEL_PERMISSION_ALL;
+		}
+
+		let mut permissions =
+			match self.kind == CHANNEL_KIND_ANNOUNCEMENT && roles & self.post_roles == 0 {
+				true => CHANNEL_PERMISSION_VIEW,
+				false => CHANNEL_PERMISSION_VIEW | CHANNEL_PERMISSION_SEND,
+			};
+		let mut allow = 0;
+		let mut deny = 0;
+		for overwrite in &self.overwrites {
+			match overwrite.target {
+				OverwriteTarget::Role(role) => {
+					if roles & role != 0 {
+						allow |= overwrite.allow;
+						deny |= overwrite.deny;
+					}
+				}
+				OverwriteTarget::User(_) => {}
+			}
+		}
+		permissions = (permissions & !deny) | allow;
+
+		for overwrite in &self.overwrites {
+			match overwrite.target {
+				OverwriteTarget::User(pubkey) => {
+					if Some(pubkey) == user_pubkey {
+						permissions = (permissions & !overwrite.deny) | overwrite.allow;
+					}
+				}
+				OverwriteTarget::Role(_) => {}
+			}
+		}
+
+		permissions
+	}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum OverwriteTarget {
+	// applies to members holding any of these role flags. AUTH_FLAG_MEMBER matches everyone.
+	Role(u128),
+	User([u8; 32]),
+}
+
+// permissions explicitly allowed or denied in a channel for a role or a user
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionOverwrite {
+	pub target: OverwriteTarget,
+	pub allow: u8,
+	pub deny: u8,
+}
+
+impl Writeable for PermissionOverwrite {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		match self.target {
+			OverwriteTarget::Role(role) => {
+				writer.write_u8(0)?;
+				writer.write_u128(role)?;
+			}
+			OverwriteTarget::User(user_pubkey) => {
+				writer.write_u8(1)?;
+				writer.write_fixed_bytes(user_pubkey)?;
+			}
+		}
+		writer.write_u8(self.allow)?;
+		writer.write_u8(self.deny)?;
+		Ok(())
+	}
+}
+
+impl Readable for PermissionOverwrite {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let target = match reader.read_u8()? {
+			0 => OverwriteTarget::Role(reader.read_u128()?),
+			1 => OverwriteTarget::User(reader.read_fixed_bytes(32)?.as_slice().try_into()?),
+			_ => {
+				return Err(ErrorKind::CorruptedData("unknown overwrite target".to_string()).into())
+			}
+		};
+		let allow = reader.read_u8()?;
+		let deny = reader.read_u8()?;
+		Ok(Self {
+			target,
+			allow,
+			deny,
+		})
+	}
 }
 
 // the Writeable implmenetation for serializing Channel
@@ -955,6 +1373,21 @@ impl Writeable for Channel {
 		}
 		writer.write_u64(self.channel_id)?;
 
+		// version of the fields that follow
+		writer.write_u8(4)?;
+		writer.write_u64(self.position)?;
+		writer.write_u64(self.category_id)?;
+		writer.write_u64(self.overwrites.len().try_into()?)?;
+		for overwrite in &self.overwrites {
+			Writeable::write(overwrite, writer)?;
+		}
+		writer.write_u8(self.kind)?;
+		writer.write_u128(self.post_roles)?;
+		match self.archived {
+			true => writer.write_u8(1)?,
+			false => writer.write_u8(0)?,
+		}
+
 		Ok(())
 	}
 }
@@ -977,16 +1410,95 @@ impl Readable for Channel {
 		let description = std::str::from_utf8(&description)?.to_string();
 		let channel_id = reader.read_u64()?;
 
+		// channels saved before ordering existed end here, version 1 has no overwrites,
+		// version 2 has no kind and version 3 can't be archived.
+		let (position, category_id, overwrites, kind, post_roles, archived) = match reader.read_u8()
+		{
+			Ok(1) => (
+				reader.read_u64()?,
+				reader.read_u64()?,
+				vec![],
+				CHANNEL_KIND_TEXT,
+				0,
+				false,
+			),
+			Ok(version @ 2..=4) => {
+				let position = reader.read_u64()?;
+				let category_id = reader.read_u64()?;
+				let len = reader.read_u64()?;
+				let mut overwrites = vec![];
+				for _ in 0..len {
+					overwrites.push(PermissionOverwrite::read(reader)?);
+				}
+				let (kind, post_roles) = match version {
+					2 => (CHANNEL_KIND_TEXT, 0),
+					_ => (reader.read_u8()?, reader.read_u128()?),
+				};
+				let archived = match version {
+					4 => reader.read_u8()? != 0,
+					_ => false,
+				};
+				(
+					position,
+					category_id,
+					overwrites,
+					kind,
+					post_roles,
+					archived,
+				)
+			}
+			Ok(_) => {
+				return Err(ErrorKind::CorruptedData("unknown Channel version".to_string()).into())
+			}
+			Err(_) => (0, 0, vec![], CHANNEL_KIND_TEXT, 0, false),
+		};
+
 		let channel = Channel {
 			name,
 			description,
 			channel_id,
+			position,
+			category_id,
+			overwrites,
+			kind,
+			post_roles,
+			archived,
 		};
 
 		Ok(channel)
 	}
 }
 
+// a collapsible group of channels
+#[derive(Debug, Serialize)]
+pub struct Category {
+	pub name: String,
+	pub category_id: u64,
+	pub position: u64,
+}
+
+impl Writeable for Category {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&SerString::from(self.name.as_str()), writer)?;
+		writer.write_u64(self.category_id)?;
+		writer.write_u64(self.position)?;
+		Ok(())
+	}
+}
+
+impl Readable for Category {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let name = SerString::read(reader)?.data;
+		let category_id = reader.read_u64()?;
+		let position = reader.read_u64()?;
+		Ok(Self {
+			name,
+			category_id,
+			position,
+		})
+	}
+}
+
 pub struct WSAuthToken {
 	pub token: u128,
 }
@@ -1009,9 +1521,14 @@ impl Readable for WSAuthToken {
 }
 
 pub struct AuthInfo {
-	creation_time: u128,
+	pub creation_time: u128,
 	pub last_access_time: u128,
 	pub expiration_millis: u128,
+	// name of the device or browser the session was created for, may be empty
+	pub device_label: String,
+	// the roles requested when the session was created. AUTH_FLAG_OWNER marks sessions that
+	// were created with the instance owner auth token.
+	pub roles: u128,
 }
 
 impl Writeable for AuthInfo {
@@ -1019,6 +1536,11 @@ impl Writeable for AuthInfo {
 		writer.write_u128(self.creation_time)?;
 		writer.write_u128(self.last_access_time)?;
 		writer.write_u128(self.expiration_millis)?;
+
+		// version of the fields that follow
+		writer.write_u8(2)?;
+		Writeable::write(&SerString::from(self.device_label.as_str()), writer)?;
+		writer.write_u128(self.roles)?;
 		Ok(())
 	}
 }
@@ -1029,14 +1551,104 @@ impl Readable for AuthInfo {
 		let last_access_time = reader.read_u128()?;
 		let expiration_millis = reader.read_u128()?;
 
-		Ok(AuthInfo {
+		// tokens saved before sessions had labels end here, version 1 has no roles
+		let (device_label, roles) = match reader.read_u8() {
+			Ok(1) => (SerString::read(reader)?.data, 0),
+			Ok(2) => (SerString::read(reader)?.data, reader.read_u128()?),
+			Ok(_) => {
+				return Err(ErrorKind::CorruptedData("unknown AuthInfo version".to_string()).into())
+			}
+			Err(_) => ("".to_string(), 0),
+		};
+
+		Ok(AuthInfo {
+			creation_time,
+			last_access_time,
+			expiration_millis,
+			device_label,
+			roles,
+		})
+	}
+}
+
+// a session as shown to its user. The token itself is never listed, sessions are identified
+// by session_id instead.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+	pub session_id: u64,
+	pub creation_time: u128,
+	pub last_access_time: u128,
+	pub expiration_millis: u128,
+	pub device_label: String,
+}
+
+impl Writeable for SessionInfo {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u64(self.session_id)?;
+		writer.write_u128(self.creation_time)?;
+		writer.write_u128(self.last_access_time)?;
+		writer.write_u128(self.expiration_millis)?;
+		Writeable::write(&SerString::from(self.device_label.as_str()), writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for SessionInfo {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let session_id = reader.read_u64()?;
+		let creation_time = reader.read_u128()?;
+		let last_access_time = reader.read_u128()?;
+		let expiration_millis = reader.read_u128()?;
+		let device_label = SerString::read(reader)?.data;
+		Ok(SessionInfo {
+			session_id,
 			creation_time,
 			last_access_time,
 			expiration_millis,
+			device_label,
 		})
 	}
 }
 
+// the public identifier of the session with this token.
+pub fn session_id(token: u128) -> u64 {
+	token.to_be_bytes().to_vec().hash().to_u64()
+}
+
+// key of the user's entry in the member name index of the server.
+fn member_name_key(profile_key: &ProfileKey, user_name: &str) -> Result<Vec<u8>, Error> {
+	let mut key = vec![];
+	serialize_default(
+		&mut key,
+		&MemberNameKeyImpl {
+			server_pubkey: profile_key.server_pubkey.clone(),
+			server_id: profile_key.server_id.clone(),
+			user_name: user_name.to_lowercase(),
+			user_pubkey: Some(profile_key.user_pubkey.clone()),
+		},
+	)?;
+	Ok(key)
+}
+
+fn device_revocation_key(user_pubkey: [u8; 32], device_pubkey: [u8; 32]) -> Vec<u8> {
+	let mut key = vec![DEVICE_REVOCATION_PREFIX];
+	key.append(&mut user_pubkey.to_vec());
+	key.append(&mut device_pubkey.to_vec());
+	key
+}
+
+// key of the instance owner auth token, either the token itself or its hash.
+fn ws_auth_token_key(token: u128, hashed: bool) -> Result<Vec<u8>, Error> {
+	let mut key = vec![];
+	if hashed {
+		key.push(WS_AUTH_TOKEN);
+		key.append(&mut token.to_be_bytes().to_vec().hash().to_vec());
+	} else {
+		serialize_default(&mut key, &WSAuthToken { token })?;
+	}
+	Ok(key)
+}
+
 // data prefixes
 const SERVER_PREFIX: u8 = 0;
 const TOKEN_PREFIX: u8 = 1;
@@ -1053,10 +1665,149 @@ const MEMBER_HASH_PREFIX: u8 = 11;
 const MEMBER_META_DATA_PREFIX: u8 = 12;
 const MEMBER_AUTH_PREFIX: u8 = 13;
 const WS_AUTH_TOKEN: u8 = 14;
+const MEMBER_NAME_PREFIX: u8 = 15;
+const SERVER_DELETE_PREFIX: u8 = 16;
+const CATEGORY_PREFIX: u8 = 17;
+const FOLLOWER_PREFIX: u8 = 18;
+const FOLLOWING_PREFIX: u8 = 19;
+const INVITE_USE_PREFIX: u8 = 20;
+const JOIN_REQUEST_PREFIX: u8 = 21;
+const DEVICE_REVOCATION_PREFIX: u8 = 23;
+const CHANNEL_DELETE_PREFIX: u8 = 24;
+const MEMBER_NAME_MIGRATION: u8 = 25;
 
 // auth levels
 pub const AUTH_FLAG_OWNER: u128 = 1;
 pub const AUTH_FLAG_MEMBER: u128 = 1 << 1;
+pub const AUTH_FLAG_MODERATOR: u128 = 1 << 2;
+
+// channel permissions
+pub const CHANNEL_PERMISSION_VIEW: u8 = 1;
+pub const CHANNEL_PERMISSION_SEND: u8 = 1 << 1;
+pub const CHANNEL_PERMISSION_MANAGE: u8 = 1 << 2;
+pub const CHANNEL_PERMISSION_ALL: u8 =
+	CHANNEL_PERMISSION_VIEW | CHANNEL_PERMISSION_SEND | CHANNEL_PERMISSION_MANAGE;
+
+// channel kinds
+pub const CHANNEL_KIND_TEXT: u8 = 0;
+pub const CHANNEL_KIND_ANNOUNCEMENT: u8 = 1;
+
+// [CATEGORY_PREFIX]|server_pubkey|server_id|category_id, or the prefix for all categories of
+// the server if category_id is None.
+fn category_key(server_pubkey: [u8; 32], server_id: [u8; 8], category_id: Option<u64>) -> Vec<u8> {
+	let mut key = vec![CATEGORY_PREFIX];
+	key.append(&mut server_pubkey.to_vec());
+	key.append(&mut server_id.to_vec());
+	if let Some(category_id) = category_id {
+		key.append(&mut category_id.to_be_bytes().to_vec());
+	}
+	key
+}
+
+// [INVITE_PREFIX]|server_id|inviter|id
+fn invite_key(invite: &Invite) -> Result<Vec<u8>, Error> {
+	let invite_key = InviteKey {
+		server_id: invite.server_id,
+		inviter: invite.inviter,
+		id: invite.id,
+	};
+	let mut key = vec![INVITE_PREFIX];
+	serialize_default(&mut key, &invite_key)?;
+	Ok(key)
+}
+
+// [INVITE_USE_PREFIX]|server_pubkey|server_id|invite_id|time|user_pubkey, or the prefix for
+// all uses of the server's invites if invite_use is None.
+fn invite_use_key(
+	server_pubkey: [u8; 32],
+	server_id: [u8; 8],
+	invite_use: Option<&InviteUse>,
+) -> Vec<u8> {
+	let mut key = vec![INVITE_USE_PREFIX];
+	key.append(&mut server_pubkey.to_vec());
+	key.append(&mut server_id.to_vec());
+	if let Some(invite_use) = invite_use {
+		key.append(&mut invite_use.invite_id.to_be_bytes().to_vec());
+		key.append(&mut invite_use.time.to_be_bytes().to_vec());
+		key.append(&mut invite_use.user_pubkey.to_vec());
+	}
+	key
+}
+
+// [JOIN_REQUEST_PREFIX]|server_pubkey|server_id|user_pubkey, or the prefix for all join
+// requests of the server if user_pubkey is None.
+fn join_request_key(
+	server_pubkey: [u8; 32],
+	server_id: [u8; 8],
+	user_pubkey: Option<[u8; 32]>,
+) -> Vec<u8> {
+	let mut key = vec![JOIN_REQUEST_PREFIX];
+	key.append(&mut server_pubkey.to_vec());
+	key.append(&mut server_id.to_vec());
+	if let Some(user_pubkey) = user_pubkey {
+		key.append(&mut user_pubkey.to_vec());
+	}
+	key
+}
+
+// [INVITE_ID_PREFIX]|id
+fn invite_id_key(invite_id: u128) -> Vec<u8> {
+	let mut key = vec![INVITE_ID_PREFIX];
+	key.append(&mut invite_id.to_be_bytes().to_vec());
+	key
+}
+
+// [MESSAGE_PREFIX]|server_pubkey|server_id|channel_id, the prefix of all messages of a channel.
+fn message_prefix(server_pubkey: [u8; 32], server_id: [u8; 8], channel_id: u64) -> Vec<u8> {
+	let mut prefix = vec![MESSAGE_PREFIX];
+	prefix.append(&mut server_pubkey.to_vec());
+	prefix.append(&mut server_id.to_vec());
+	prefix.append(&mut channel_id.to_be_bytes().to_vec());
+	prefix
+}
+
+// the position in a message listing: the batch to read next and the number of entries of
+// that batch that were already returned.
+fn message_cursor(batch_num: u64, skip: u64) -> PageCursor {
+	let mut data = batch_num.to_be_bytes().to_vec();
+	data.append(&mut skip.to_be_bytes().to_vec());
+	PageCursor { data }
+}
+
+fn read_message_cursor(cursor: &PageCursor) -> Result<(u64, u64), Error> {
+	if cursor.data.len() != 16 {
+		return Err(
+			ErrorKind::IllegalArgument(format!("invalid message cursor: {:?}", cursor)).into(),
+		);
+	}
+	let batch_num = u64::from_be_bytes(cursor.data[0..8].try_into()?);
+	let skip = u64::from_be_bytes(cursor.data[8..16].try_into()?);
+	Ok((batch_num, skip))
+}
+
+// [CHANNEL_DELETE_PREFIX]|server_pubkey|server_id|channel_id
+fn channel_delete_key(server_pubkey: [u8; 32], server_id: [u8; 8], channel_id: u64) -> Vec<u8> {
+	let mut key = vec![CHANNEL_DELETE_PREFIX];
+	key.append(&mut server_pubkey.to_vec());
+	key.append(&mut server_id.to_vec());
+	key.append(&mut channel_id.to_be_bytes().to_vec());
+	key
+}
+
+// [prefix]|channel|other, or the prefix for all links of the channel if other is None.
+// Used for both the followers of a local channel and the channels a local channel follows.
+fn follow_key(
+	prefix: u8,
+	channel: &ChannelKey,
+	other: Option<&ChannelKey>,
+) -> Result<Vec<u8>, Error> {
+	let mut key = vec![prefix];
+	serialize_default(&mut key, channel)?;
+	if let Some(other) = other {
+		serialize_default(&mut key, other)?;
+	}
+	Ok(key)
+}
 
 impl DSContext {
 	// get a list of servers in the local database
@@ -1078,12 +1829,7 @@ impl DSContext {
 				Some((server, server_id)) => {
 					let server_id = *(&server_id[..].try_into()?);
 					if server.joined {
-						ret.push(ServerInfoReply {
-							server_id,
-							name: server.name,
-							pubkey: server.pubkey,
-							seqno: server.seqno,
-						});
+						ret.push(server.reply(server_id));
 					}
 				}
 				None => break,
@@ -1093,6 +1839,18 @@ impl DSContext {
 		Ok(ret)
 	}
 
+	// returns the joined servers that the user is an owner of.
+	pub fn get_owned_servers(&self, user_pubkey: [u8; 32]) -> Result<Vec<ServerInfoReply>, Error> {
+		let mut ret = vec![];
+		for server in self.get_servers()? {
+			match self.get_server_member(server.pubkey, server.server_id, user_pubkey)? {
+				Some(member) if member.roles & AUTH_FLAG_OWNER != 0 => ret.push(server),
+				_ => {}
+			}
+		}
+		Ok(ret)
+	}
+
 	pub fn get_server_info(
 		&self,
 		server_id: [u8; 8],
@@ -1105,20 +1863,17 @@ impl DSContext {
 		let ret: Option<ServerInfo> = batch.get_ser(&key)?;
 		match ret {
 			None => Ok(None),
-			Some(ret) => Ok(Some(ServerInfoReply {
-				server_id,
-				pubkey: ret.pubkey,
-				name: ret.name,
-				seqno: ret.seqno,
-			})),
+			Some(ret) => Ok(Some(ret.reply(server_id))),
 		}
 	}
 
+	// apply the update and increment seqno so clients know to refresh the server. An empty
+	// update still increments seqno, which is used when only the icon changed.
 	pub fn modify_server(
 		&self,
 		server_id: [u8; 8],
 		server_pubkey: [u8; 32],
-		name: String,
+		update: ServerUpdate,
 	) -> Result<(), Error> {
 		let batch = self.store.batch()?;
 		let mut key = vec![SERVER_PREFIX];
@@ -1129,7 +1884,30 @@ impl DSContext {
 		let server_info = match server_info {
 			None => return Ok(()), // shouldn't happen, but deal with it in client
 			Some(mut server_info) => {
-				server_info.name = name;
+				if let Some(name) = update.name {
+					server_info.name = name;
+				}
+				if let Some(description) = update.description {
+					server_info.description = description;
+				}
+				if let Some(topic) = update.topic {
+					server_info.topic = topic;
+				}
+				if let Some(default_channel) = update.default_channel {
+					server_info.default_channel = default_channel;
+				}
+				if let Some(rules) = update.rules {
+					server_info.rules = rules;
+				}
+				if let Some(verification_level) = update.verification_level {
+					server_info.verification_level = verification_level;
+				}
+				if let Some(require_approval) = update.require_approval {
+					server_info.require_approval = require_approval;
+				}
+				if let Some(pow_difficulty) = update.pow_difficulty {
+					server_info.pow_difficulty = pow_difficulty;
+				}
 				server_info.seqno = server_info.seqno + 1;
 				server_info
 			}
@@ -1191,6 +1969,7 @@ impl DSContext {
 			roles,
 			None,
 			None,
+			None,
 			&batch,
 		)?;
 
@@ -1205,6 +1984,12 @@ impl DSContext {
 			name: "mainchat".to_string(),
 			description: "Welcome to mainchat!".to_string(),
 			channel_id,
+			position: 0,
+			category_id: 0,
+			overwrites: vec![],
+			kind: CHANNEL_KIND_TEXT,
+			post_roles: 0,
+			archived: false,
 		};
 		self.set_channel_impl(channel_key, channel, &batch)?;
 
@@ -1212,16 +1997,152 @@ impl DSContext {
 		Ok(server_id)
 	}
 
+	// delete a server and everything that belongs to it. The server row is removed and a
+	// tombstone is written in a single transaction, then the rest of the data is purged
+	// in chunks. If we are interrupted, resume_server_deletes finishes the job.
 	pub fn delete_server(&self, server_id: [u8; 8], pubkey: [u8; 32]) -> Result<(), Error> {
 		let batch = self.store.batch()?;
 		let mut key = vec![SERVER_PREFIX];
 		key.append(&mut server_id.to_vec());
 		key.append(&mut pubkey.to_vec());
 		batch.delete(&key)?;
+
+		let mut tombstone_key = vec![SERVER_DELETE_PREFIX];
+		tombstone_key.append(&mut pubkey.to_vec());
+		tombstone_key.append(&mut server_id.to_vec());
+		batch.put_ser(&tombstone_key, &0u8)?;
+		batch.commit()?;
+
+		self.purge_server(server_id, pubkey)
+	}
+
+	// finish any server deletes that were interrupted. Returns the (server_id, server_pubkey)
+	// of each server that was purged so that the caller can clean up associated files.
+	pub fn resume_server_deletes(&self) -> Result<Vec<([u8; 8], [u8; 32])>, Error> {
+		// the batch must be released before purging, which opens its own
+		let ret: Vec<([u8; 8], [u8; 32])> = {
+			let batch = self.store.batch()?;
+			let itt = batch.iter(&[SERVER_DELETE_PREFIX], |k, _| {
+				let server_pubkey: [u8; 32] = k[1..33].try_into()?;
+				let server_id: [u8; 8] = k[33..41].try_into()?;
+				Ok((server_id, server_pubkey))
+			})?;
+			itt.collect()
+		};
+
+		for (server_id, server_pubkey) in &ret {
+			warn!(
+				"resuming delete of server {:?}",
+				ServerId::from_bytes(*server_id)
+			);
+			self.purge_server(*server_id, *server_pubkey)?;
+		}
+
+		Ok(ret)
+	}
+
+	fn purge_server(&self, server_id: [u8; 8], server_pubkey: [u8; 32]) -> Result<(), Error> {
+		while !self.purge_server_chunk(server_id, server_pubkey)? {}
+
+		let batch = self.store.batch()?;
+		let mut tombstone_key = vec![SERVER_DELETE_PREFIX];
+		tombstone_key.append(&mut server_pubkey.to_vec());
+		tombstone_key.append(&mut server_id.to_vec());
+		batch.delete(&tombstone_key)?;
 		batch.commit()?;
 		Ok(())
 	}
 
+	// delete up to SERVER_DELETE_CHUNK_SIZE keys of the server's data in one transaction.
+	// returns true when nothing is left to delete.
+	fn purge_server_chunk(
+		&self,
+		server_id: [u8; 8],
+		server_pubkey: [u8; 32],
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let mut count = 0;
+
+		// member hash entries first so that we know which profiles to delete
+		let mut prefixes = vec![];
+		for table_prefix in &[
+			MEMBER_HASH_PREFIX,
+			MEMBER_ITT_PREFIX,
+			MEMBER_AUTH_PREFIX,
+			MEMBER_META_DATA_PREFIX,
+			MEMBER_NAME_PREFIX,
+			CHANNEL_PREFIX,
+			CATEGORY_PREFIX,
+			FOLLOWER_PREFIX,
+			FOLLOWING_PREFIX,
+			INVITE_USE_PREFIX,
+			JOIN_REQUEST_PREFIX,
+			MESSAGE_PREFIX,
+			MESSAGE_METADATA_PREFIX,
+			CHANNEL_DELETE_PREFIX,
+		] {
+			let mut prefix = vec![*table_prefix];
+			prefix.append(&mut server_pubkey.to_vec());
+			prefix.append(&mut server_id.to_vec());
+			prefixes.push(prefix);
+		}
+
+		for prefix in prefixes {
+			let mut itt = batch.iter(&prefix, |k, _| Ok(k.to_vec()))?;
+			loop {
+				match itt.next() {
+					Some(key) => {
+						if key[0] == MEMBER_HASH_PREFIX {
+							let user_pubkey: [u8; 32] = key[41..73].try_into()?;
+							let profile_key = ProfileKey {
+								user_pubkey: Pubkey::from_bytes(user_pubkey),
+								server_pubkey: Pubkey::from_bytes(server_pubkey),
+								server_id: ServerId::from_bytes(server_id),
+							};
+							let mut profile_key_buffer = vec![];
+							serialize_default(&mut profile_key_buffer, &profile_key)?;
+							let _ = batch.delete(&profile_key_buffer);
+						}
+						batch.delete(&key)?;
+						count += 1;
+						if count >= SERVER_DELETE_CHUNK_SIZE {
+							batch.commit()?;
+							return Ok(false);
+						}
+					}
+					None => break,
+				}
+			}
+		}
+
+		// invites are only indexed by server_id
+		let mut prefix = vec![INVITE_PREFIX];
+		prefix.append(&mut server_id.to_vec());
+		let mut itt = batch.iter(&prefix, |k, _| {
+			let invite_id = u128::from_be_bytes(k[k.len() - 16..].try_into()?);
+			Ok((k.to_vec(), invite_id))
+		})?;
+		loop {
+			match itt.next() {
+				Some((key, invite_id)) => {
+					let mut invite_id_key = vec![INVITE_ID_PREFIX];
+					invite_id_key.append(&mut invite_id.to_be_bytes().to_vec());
+					let _ = batch.delete(&invite_id_key);
+					batch.delete(&key)?;
+					count += 1;
+					if count >= SERVER_DELETE_CHUNK_SIZE {
+						batch.commit()?;
+						return Ok(false);
+					}
+				}
+				None => break,
+			}
+		}
+
+		batch.commit()?;
+		Ok(true)
+	}
+
 	/*
 		// add a remote server
 		pub fn add_remote_server(
@@ -1307,6 +2228,7 @@ impl DSContext {
 			payload: message.payload,
 			signature: message.signature,
 			message_type: message.message_type,
+			device_certificate: message.device_certificate,
 		};
 		let message_key_impl = MessageKeyImpl {
 			server_pubkey: message.server_pubkey,
@@ -1357,57 +2279,10 @@ impl DSContext {
 					batch_num
 				};
 
-				let mut prefix = vec![MESSAGE_PREFIX];
-				prefix.append(&mut server_pubkey.to_vec());
-				prefix.append(&mut server_id.to_vec());
-				prefix.append(&mut channel_id.to_be_bytes().to_vec());
+				let mut prefix = message_prefix(server_pubkey, server_id, channel_id);
 				prefix.append(&mut batch_num.to_be_bytes().to_vec());
 
-				let mut message_num = batch_num * 100;
-
-				let mut itt = batch.iter(&(prefix[..]), move |k, v| {
-					let mut cursor = Cursor::new(k.to_vec());
-					cursor.set_position(0);
-					let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
-					let mkey = MessageKeyImpl::read(&mut reader)?;
-
-					let mut cursor = Cursor::new(v.to_vec());
-					cursor.set_position(0);
-					let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
-					let mval = MessageValueImpl::read(&mut reader)?;
-
-					Ok(Message {
-						payload: mval.payload,
-						signature: mval.signature,
-						message_type: mval.message_type,
-						server_pubkey: mkey.server_pubkey,
-						server_id: mkey.server_id,
-						channel_id: mkey.channel_id,
-						timestamp: mkey.timestamp,
-						user_pubkey: mkey.user_pubkey,
-						user_name: "".to_string(),
-						user_bio: "".to_string(),
-						nonce: mkey.nonce,
-						seqno: 0,
-					})
-				})?;
-
-				let mut ret = vec![];
-				loop {
-					let next = itt.next();
-					match next {
-						Some(mut m) => {
-							m.user_name = "not implemented".to_string();
-							m.user_bio = "not implemented".to_string();
-							m.seqno = message_num;
-							message_num += 1;
-							ret.push(m);
-						}
-						None => {
-							break;
-						}
-					}
-				}
+				let ret = self.read_messages(&batch, &prefix, batch_num * MESSAGE_BATCH_SIZE)?;
 
 				Ok((batches, ret))
 			}
@@ -1415,12 +2290,199 @@ impl DSContext {
 		}
 	}
 
+	// returns the messages of a channel oldest first, one batch per page. The returned cursor
+	// is passed back in to get the next page.
+	pub fn export_messages(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+		cursor: Option<PageCursor>,
+	) -> Result<(Vec<Message>, Option<PageCursor>), Error> {
+		let batch = self.store.batch()?;
+		let batches = match self.message_batches(server_pubkey, server_id, channel_id, &batch)? {
+			Some(batches) => batches,
+			None => return Ok((vec![], None)),
+		};
+		let batch_num = match cursor {
+			Some(cursor) => read_message_cursor(&cursor)?.0,
+			None => 0,
+		};
+		if batch_num > batches {
+			return Ok((vec![], None));
+		}
+
+		let mut prefix = message_prefix(server_pubkey, server_id, channel_id);
+		prefix.append(&mut batch_num.to_be_bytes().to_vec());
+		let messages = self.read_messages(&batch, &prefix, batch_num * MESSAGE_BATCH_SIZE)?;
+
+		let next_cursor = match batch_num < batches {
+			true => Some(message_cursor(batch_num + 1, 0)),
+			false => None,
+		};
+		Ok((messages, next_cursor))
+	}
+
+	// returns up to MAX_SEARCH_RESULTS messages of a channel whose text contains the query,
+	// ignoring case, most recent first. At most MAX_SEARCH_BATCHES batches are scanned per
+	// call so a page may hold fewer results even though more exist. The returned cursor is
+	// passed back in to continue the search, it is None once the oldest batch was scanned.
+	pub fn search_messages(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+		query: &str,
+		cursor: Option<PageCursor>,
+	) -> Result<(Vec<Message>, Option<PageCursor>), Error> {
+		let batch = self.store.batch()?;
+		let batches = match self.message_batches(server_pubkey, server_id, channel_id, &batch)? {
+			Some(batches) => batches,
+			None => return Ok((vec![], None)),
+		};
+		// skip is the number of matches of batch_num that were already returned
+		let (mut batch_num, mut skip) = match cursor {
+			Some(cursor) => read_message_cursor(&cursor)?,
+			None => (batches, 0),
+		};
+		if batch_num > batches {
+			return Ok((vec![], None));
+		}
+
+		let query = query.to_lowercase();
+		let mut ret = vec![];
+		let mut scanned = 0;
+		loop {
+			let mut prefix = message_prefix(server_pubkey, server_id, channel_id);
+			prefix.append(&mut batch_num.to_be_bytes().to_vec());
+			let mut matches: Vec<Message> = self
+				.read_messages(&batch, &prefix, batch_num * MESSAGE_BATCH_SIZE)?
+				.into_iter()
+				.filter(|m| match std::str::from_utf8(&m.payload) {
+					Ok(text) => text.to_lowercase().contains(&query),
+					Err(_) => false,
+				})
+				.collect();
+			matches.reverse();
+
+			let match_count: u64 = matches.len().try_into()?;
+			let remaining: u64 = (MAX_SEARCH_RESULTS - ret.len()).try_into()?;
+			let take = match_count.saturating_sub(skip).min(remaining);
+			ret.extend(
+				matches
+					.into_iter()
+					.skip(skip.try_into()?)
+					.take(take.try_into()?),
+			);
+			if skip + take < match_count {
+				// the page filled up part way through this batch
+				return Ok((ret, Some(message_cursor(batch_num, skip + take))));
+			}
+
+			scanned += 1;
+			if batch_num == 0 {
+				return Ok((ret, None));
+			}
+			batch_num -= 1;
+			skip = 0;
+			if ret.len() >= MAX_SEARCH_RESULTS || scanned >= MAX_SEARCH_BATCHES {
+				return Ok((ret, Some(message_cursor(batch_num, 0))));
+			}
+		}
+	}
+
+	// returns the highest message batch number of a channel, None if it has no messages.
+	fn message_batches(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+		batch: &Batch,
+	) -> Result<Option<u64>, Error> {
+		let message_metadata_key = MessageMetaDataKey {
+			server_pubkey,
+			server_id,
+			channel_id,
+		};
+		let mut buffer = vec![];
+		serialize_default(&mut buffer, &message_metadata_key)?;
+		let res: Option<MessageMetaDataValue> = batch.get_ser(&buffer)?;
+		Ok(res.map(|mmdv| mmdv.message_count / MESSAGE_BATCH_SIZE))
+	}
+
+	// read the messages under the specified prefix in order, numbering them from first_seqno.
+	fn read_messages(
+		&self,
+		batch: &Batch,
+		prefix: &[u8],
+		first_seqno: u64,
+	) -> Result<Vec<Message>, Error> {
+		let mut message_num = first_seqno;
+
+		let mut itt = batch.iter(prefix, move |k, v| {
+			let mut cursor = Cursor::new(k.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			let mkey = MessageKeyImpl::read(&mut reader)?;
+
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			let mval = MessageValueImpl::read(&mut reader)?;
+
+			Ok(Message {
+				payload: mval.payload,
+				signature: mval.signature,
+				message_type: mval.message_type,
+				server_pubkey: mkey.server_pubkey,
+				server_id: mkey.server_id,
+				channel_id: mkey.channel_id,
+				timestamp: mkey.timestamp,
+				user_pubkey: mkey.user_pubkey,
+				user_name: "".to_string(),
+				user_bio: "".to_string(),
+				nonce: mkey.nonce,
+				seqno: 0,
+				device_certificate: mval.device_certificate,
+			})
+		})?;
+
+		let mut ret = vec![];
+		loop {
+			let next = itt.next();
+			match next {
+				Some(mut m) => {
+					m.user_name = "not implemented".to_string();
+					m.user_bio = "not implemented".to_string();
+					m.seqno = message_num;
+					message_num += 1;
+					ret.push(m);
+				}
+				None => {
+					break;
+				}
+			}
+		}
+
+		Ok(ret)
+	}
+
+	// returns the channels of a server ordered by position.
 	pub fn get_channels(
 		&self,
 		server_pubkey: [u8; 32],
 		server_id: [u8; 8],
 	) -> Result<Vec<Channel>, Error> {
 		let batch = self.store.batch()?;
+		self.get_channels_impl(server_pubkey, server_id, &batch)
+	}
+
+	fn get_channels_impl(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		batch: &Batch,
+	) -> Result<Vec<Channel>, Error> {
 		// get the iterator for each channel
 		let mut key_vec = vec![CHANNEL_PREFIX];
 		key_vec.append(&mut server_pubkey.to_vec());
@@ -1447,6 +2509,9 @@ impl DSContext {
 			}
 		}
 
+		// channels that were never positioned keep a stable order by id
+		ret.sort_by_key(|c| (c.position, c.channel_id));
+
 		Ok(ret)
 	}
 
@@ -1457,18 +2522,35 @@ impl DSContext {
 		name: String,
 		description: String,
 	) -> Result<u64, Error> {
+		let batch = self.store.batch()?;
 		let channel_id = rand::random();
 		let channel_key = ChannelKey {
 			channel_id,
 			server_id,
 			server_pubkey,
 		};
+
+		// new channels go to the end of the list
+		let position = self
+			.get_channels_impl(server_pubkey, server_id, &batch)?
+			.iter()
+			.map(|c| c.position + 1)
+			.max()
+			.unwrap_or(0);
+
 		let channel = Channel {
 			name,
 			description,
 			channel_id,
+			position,
+			category_id: 0,
+			overwrites: vec![],
+			kind: CHANNEL_KIND_TEXT,
+			post_roles: 0,
+			archived: false,
 		};
-		self.set_channel(channel_key, channel)?;
+		self.set_channel_impl(channel_key, channel, &batch)?;
+		batch.commit()?;
 		Ok(channel_id)
 	}
 
@@ -1480,206 +2562,785 @@ impl DSContext {
 		name: String,
 		description: String,
 	) -> Result<(), Error> {
+		let batch = self.store.batch()?;
 		let channel_key = ChannelKey {
 			channel_id,
 			server_id,
 			server_pubkey,
 		};
-		let channel = Channel {
-			name,
-			description,
-			channel_id,
+
+		// keep the position and category of the existing channel
+		let channel = match self.get_channel_impl(&channel_key, &batch)? {
+			None => return Ok(()), // shouldn't happen, but deal with it in client
+			Some(mut channel) => {
+				channel.name = name;
+				channel.description = description;
+				channel
+			}
 		};
-		self.set_channel(channel_key, channel)?;
-		Ok(())
-	}
 
-	fn set_channel(&self, channel_key: ChannelKey, channel: Channel) -> Result<(), Error> {
-		let batch = self.store.batch()?;
 		self.set_channel_impl(channel_key, channel, &batch)?;
 		batch.commit()?;
 		Ok(())
 	}
 
-	fn set_channel_impl(
+	pub fn get_channel(
 		&self,
-		channel_key: ChannelKey,
-		channel: Channel,
-		batch: &Batch,
-	) -> Result<(), Error> {
-		let mut buffer = vec![];
-		serialize_default(&mut buffer, &channel_key)?;
-		let mut buffer2 = vec![CHANNEL_PREFIX];
-		buffer2.append(&mut buffer);
-		batch.put_ser(&buffer2, &channel)?;
-		Ok(())
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+	) -> Result<Option<Channel>, Error> {
+		let batch = self.store.batch()?;
+		let channel_key = ChannelKey {
+			channel_id,
+			server_id,
+			server_pubkey,
+		};
+		self.get_channel_impl(&channel_key, &batch)
 	}
 
-	pub fn delete_channel(
+	// returns the permissions the user has in the channel, 0 if the channel does not exist.
+	pub fn channel_permissions(
 		&self,
-		server_id: [u8; 8],
 		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
 		channel_id: u64,
-	) -> Result<(), Error> {
+		user_pubkey: [u8; 32],
+	) -> Result<u8, Error> {
 		let batch = self.store.batch()?;
 		let channel_key = ChannelKey {
 			channel_id,
 			server_id,
 			server_pubkey,
 		};
-		self.delete_channel_impl(channel_key, &batch)?;
+		let channel = match self.get_channel_impl(&channel_key, &batch)? {
+			Some(channel) => channel,
+			None => return Ok(0),
+		};
+		let roles = self
+			.get_member(
+				Pubkey::from_bytes(user_pubkey),
+				ServerId::from_bytes(server_id),
+				Pubkey::from_bytes(server_pubkey),
+				&batch,
+			)?
+			.map(|m| m.roles);
+		Ok(channel.permissions(roles, user_pubkey))
+	}
+
+	// replace the permission overwrites of a channel. Returns false if the channel does not exist.
+	pub fn set_channel_overwrites(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+		overwrites: Vec<PermissionOverwrite>,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let channel_key = ChannelKey {
+			channel_id,
+			server_id,
+			server_pubkey,
+		};
+		match self.get_channel_impl(&channel_key, &batch)? {
+			Some(mut channel) => {
+				channel.overwrites = overwrites;
+				self.set_channel_impl(channel_key, channel, &batch)?;
+				batch.commit()?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	// set the kind of a channel and the roles that may post if it is an announcement
+	// channel. Returns false if the channel does not exist.
+	pub fn set_channel_kind(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+		kind: u8,
+		post_roles: u128,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let channel_key = ChannelKey {
+			channel_id,
+			server_id,
+			server_pubkey,
+		};
+		match self.get_channel_impl(&channel_key, &batch)? {
+			Some(mut channel) => {
+				channel.kind = kind;
+				channel.post_roles = post_roles;
+				self.set_channel_impl(channel_key, channel, &batch)?;
+				batch.commit()?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
 
+	// archive or unarchive a channel. Returns false if the channel does not exist.
+	pub fn set_channel_archived(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+		archived: bool,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let channel_key = ChannelKey {
+			channel_id,
+			server_id,
+			server_pubkey,
+		};
+		match self.get_channel_impl(&channel_key, &batch)? {
+			Some(mut channel) => {
+				channel.archived = archived;
+				self.set_channel_impl(channel_key, channel, &batch)?;
+				batch.commit()?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	// record that a channel, possibly on another host, follows a local announcement channel.
+	// user_pubkey is the local user making the request, or None if the host of follower is
+	// asking, in which case the channel must be visible to everyone. Returns false if the
+	// channel does not exist, is not an announcement channel or can't be viewed.
+	pub fn add_follower(
+		&self,
+		channel: &ChannelKey,
+		follower: &ChannelKey,
+		user_pubkey: Option<[u8; 32]>,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let c = match self.get_channel_impl(channel, &batch)? {
+			Some(c) => c,
+			None => return Ok(false),
+		};
+		if c.kind != CHANNEL_KIND_ANNOUNCEMENT {
+			return Ok(false);
+		}
+		let permissions = match user_pubkey {
+			Some(user_pubkey) => {
+				let roles = self
+					.get_member(
+						Pubkey::from_bytes(user_pubkey),
+						ServerId::from_bytes(channel.server_id),
+						Pubkey::from_bytes(channel.server_pubkey),
+						&batch,
+					)?
+					.map(|m| m.roles);
+				c.permissions(roles, user_pubkey)
+			}
+			None => c.everyone_permissions(),
+		};
+		if permissions & CHANNEL_PERMISSION_VIEW == 0 {
+			return Ok(false);
+		}
+		batch.put_ser(&follow_key(FOLLOWER_PREFIX, channel, Some(follower))?, &0u8)?;
+		batch.commit()?;
+		Ok(true)
+	}
+
+	pub fn remove_follower(
+		&self,
+		channel: &ChannelKey,
+		follower: &ChannelKey,
+	) -> Result<(), Error> {
+		let batch = self.store.batch()?;
+		let _ = batch.delete(&follow_key(FOLLOWER_PREFIX, channel, Some(follower))?);
 		batch.commit()?;
 		Ok(())
 	}
 
-	fn delete_channel_impl(&self, channel_key: ChannelKey, batch: &Batch) -> Result<(), Error> {
+	// returns the channels that follow a local announcement channel.
+	pub fn get_followers(&self, channel: &ChannelKey) -> Result<Vec<ChannelKey>, Error> {
+		self.get_follow_links(FOLLOWER_PREFIX, channel)
+	}
+
+	// record that a local channel republishes the announcements of the source channel.
+	pub fn add_following(&self, channel: &ChannelKey, source: &ChannelKey) -> Result<(), Error> {
+		let batch = self.store.batch()?;
+		batch.put_ser(&follow_key(FOLLOWING_PREFIX, channel, Some(source))?, &0u8)?;
+		batch.commit()?;
+		Ok(())
+	}
+
+	pub fn remove_following(&self, channel: &ChannelKey, source: &ChannelKey) -> Result<(), Error> {
+		let batch = self.store.batch()?;
+		let _ = batch.delete(&follow_key(FOLLOWING_PREFIX, channel, Some(source))?);
+		batch.commit()?;
+		Ok(())
+	}
+
+	pub fn is_following(&self, channel: &ChannelKey, source: &ChannelKey) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let following: Option<u8> =
+			batch.get_ser(&follow_key(FOLLOWING_PREFIX, channel, Some(source))?)?;
+		Ok(following.is_some())
+	}
+
+	fn get_follow_links(&self, prefix: u8, channel: &ChannelKey) -> Result<Vec<ChannelKey>, Error> {
+		let batch = self.store.batch()?;
+		let key = follow_key(prefix, channel, None)?;
+		let len = key.len();
+		let mut itt = batch.iter(&key, |k, _| {
+			let mut cursor = Cursor::new(k[len..].to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			ChannelKey::read(&mut reader)
+		})?;
+
+		let mut ret = vec![];
+		loop {
+			match itt.next() {
+				Some(other) => ret.push(other),
+				None => break,
+			}
+		}
+		Ok(ret)
+	}
+
+	fn get_channel_impl(
+		&self,
+		channel_key: &ChannelKey,
+		batch: &Batch,
+	) -> Result<Option<Channel>, Error> {
+		let mut buffer = vec![];
+		serialize_default(&mut buffer, channel_key)?;
+		let mut buffer2 = vec![CHANNEL_PREFIX];
+		buffer2.append(&mut buffer);
+		batch.get_ser(&buffer2)
+	}
+
+	fn set_channel_impl(
+		&self,
+		channel_key: ChannelKey,
+		channel: Channel,
+		batch: &Batch,
+	) -> Result<(), Error> {
 		let mut buffer = vec![];
 		serialize_default(&mut buffer, &channel_key)?;
 		let mut buffer2 = vec![CHANNEL_PREFIX];
 		buffer2.append(&mut buffer);
-		let _ = batch.delete(&buffer2);
+		batch.put_ser(&buffer2, &channel)?;
 		Ok(())
 	}
 
-	pub fn create_invite(
+	pub fn delete_channel(
 		&self,
-		inviter: [u8; 32],
 		server_id: [u8; 8],
-		expiry: u128,
-		count: u64,
-	) -> Result<u128, Error> {
+		server_pubkey: [u8; 32],
+		channel_id: u64,
+	) -> Result<(), Error> {
 		let batch = self.store.batch()?;
-
-		let id: u128 = rand::random();
-
-		let invite = Invite {
-			inviter,
+		let channel_key = ChannelKey {
+			channel_id,
 			server_id,
-			expiry,
-			cur: 0,
-			max: count,
-			id,
+			server_pubkey,
 		};
 
-		let invite_key = InviteKey {
+		let message_metadata_key = MessageMetaDataKey {
+			server_pubkey,
 			server_id,
-			inviter,
-			id,
+			channel_id,
 		};
-
 		let mut buffer = vec![];
-		serialize_default(&mut buffer, &invite_key)?;
-		let mut invite_key_buf = vec![INVITE_PREFIX];
-		invite_key_buf.append(&mut buffer);
+		serialize_default(&mut buffer, &message_metadata_key)?;
+		let _ = batch.delete(&buffer);
+
+		for prefix in &[FOLLOWER_PREFIX, FOLLOWING_PREFIX] {
+			let mut itt = batch.iter(&follow_key(*prefix, &channel_key, None)?, |k, _| {
+				Ok(k.to_vec())
+			})?;
+			loop {
+				match itt.next() {
+					Some(key) => batch.delete(&key)?,
+					None => break,
+				}
+			}
+		}
 
-		serialize_default(&mut buffer, &invite)?;
-		batch.put_ser(&invite_key_buf, &buffer)?;
+		self.delete_channel_impl(channel_key, &batch)?;
 
-		// create second index for invite id.
-		let mut invite_id_key = vec![INVITE_ID_PREFIX];
-		invite_id_key.append(&mut id.to_be_bytes().to_vec());
-		batch.put_ser(&invite_id_key, &buffer)?;
+		// the messages are purged in chunks after this commit. If we are interrupted,
+		// resume_channel_deletes finishes the job.
+		batch.put_ser(
+			&channel_delete_key(server_pubkey, server_id, channel_id),
+			&0u8,
+		)?;
 
 		batch.commit()?;
 
-		Ok(id)
+		self.purge_channel_messages(server_pubkey, server_id, channel_id)
 	}
 
-	pub fn get_invites(
+	// finish any channel deletes that were interrupted.
+	pub fn resume_channel_deletes(&self) -> Result<(), Error> {
+		// the batch must be released before purging, which opens its own
+		let channels: Vec<([u8; 32], [u8; 8], u64)> = {
+			let batch = self.store.batch()?;
+			let itt = batch.iter(&[CHANNEL_DELETE_PREFIX], |k, _| {
+				let server_pubkey: [u8; 32] = k[1..33].try_into()?;
+				let server_id: [u8; 8] = k[33..41].try_into()?;
+				let channel_id = u64::from_be_bytes(k[41..49].try_into()?);
+				Ok((server_pubkey, server_id, channel_id))
+			})?;
+			itt.collect()
+		};
+
+		for (server_pubkey, server_id, channel_id) in channels {
+			warn!("resuming delete of channel {}", channel_id);
+			self.purge_channel_messages(server_pubkey, server_id, channel_id)?;
+		}
+
+		Ok(())
+	}
+
+	fn purge_channel_messages(
 		&self,
-		inviter: Option<[u8; 32]>,
+		server_pubkey: [u8; 32],
 		server_id: [u8; 8],
-	) -> Result<Vec<Invite>, Error> {
+		channel_id: u64,
+	) -> Result<(), Error> {
+		while !self.purge_channel_messages_chunk(server_pubkey, server_id, channel_id)? {}
+
 		let batch = self.store.batch()?;
-		// get the iterator for each invite
-		let mut key_vec = vec![INVITE_PREFIX];
-		key_vec.append(&mut server_id.to_vec());
-		// if inviter is specfied
-		match inviter {
-			Some(inviter) => {
-				key_vec.append(&mut inviter.to_vec());
+		let _ = batch.delete(&channel_delete_key(server_pubkey, server_id, channel_id));
+		batch.commit()?;
+		Ok(())
+	}
+
+	// delete up to SERVER_DELETE_CHUNK_SIZE messages of a channel in one transaction.
+	// returns true when nothing is left to delete.
+	fn purge_channel_messages_chunk(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let prefix = message_prefix(server_pubkey, server_id, channel_id);
+		let mut itt = batch.iter(&prefix, |k, _| Ok(k.to_vec()))?;
+		let mut count = 0;
+		loop {
+			match itt.next() {
+				Some(key) => {
+					batch.delete(&key)?;
+					count += 1;
+					if count >= SERVER_DELETE_CHUNK_SIZE {
+						batch.commit()?;
+						return Ok(false);
+					}
+				}
+				None => break,
 			}
-			None => {}
 		}
+		batch.commit()?;
+		Ok(true)
+	}
 
-		let mut itt = batch.iter(&(key_vec[..]), |_, v| {
+	fn delete_channel_impl(&self, channel_key: ChannelKey, batch: &Batch) -> Result<(), Error> {
+		let mut buffer = vec![];
+		serialize_default(&mut buffer, &channel_key)?;
+		let mut buffer2 = vec![CHANNEL_PREFIX];
+		buffer2.append(&mut buffer);
+		let _ = batch.delete(&buffer2);
+		Ok(())
+	}
+
+	// returns the categories of a server ordered by position.
+	pub fn get_categories(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+	) -> Result<Vec<Category>, Error> {
+		let batch = self.store.batch()?;
+		self.get_categories_impl(server_pubkey, server_id, &batch)
+	}
+
+	fn get_categories_impl(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		batch: &Batch,
+	) -> Result<Vec<Category>, Error> {
+		let mut itt = batch.iter(&category_key(server_pubkey, server_id, None), |_, v| {
 			let mut cursor = Cursor::new(v.to_vec());
 			cursor.set_position(0);
 			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
-			let invite = Invite::read(&mut reader)?;
-			Ok(invite)
+			Category::read(&mut reader)
 		})?;
 
 		let mut ret = vec![];
 		loop {
-			let next = itt.next();
-			match next {
-				Some(invite) => {
-					ret.push(invite);
-				}
-				None => {
-					break;
-				}
+			match itt.next() {
+				Some(category) => ret.push(category),
+				None => break,
 			}
 		}
 
+		ret.sort_by_key(|c| (c.position, c.category_id));
+
 		Ok(ret)
 	}
 
-	pub fn delete_invite(&self, invite_id: u128) -> Result<(), Error> {
+	pub fn add_category(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		name: String,
+	) -> Result<u64, Error> {
 		let batch = self.store.batch()?;
-		let mut key = vec![INVITE_ID_PREFIX];
-		key.append(&mut invite_id.to_be_bytes().to_vec());
-		let invite: Option<Invite> = batch.get_ser(&key)?;
-
-		match invite {
-			Some(invite) => {
-				batch.delete(&key)?;
-				let invite_key = InviteKey {
-					server_id: invite.server_id,
-					inviter: invite.inviter,
-					id: invite_id,
-				};
+		// 0 means no category so it is never used as an id
+		let category_id = match rand::random::<u64>() {
+			0 => 1,
+			category_id => category_id,
+		};
+		let position = self
+			.get_categories_impl(server_pubkey, server_id, &batch)?
+			.iter()
+			.map(|c| c.position + 1)
+			.max()
+			.unwrap_or(0);
 
-				let mut buffer = vec![];
-				serialize_default(&mut buffer, &invite_key)?;
-				let mut invite_key_buf = vec![INVITE_PREFIX];
-				invite_key_buf.append(&mut buffer);
-				batch.delete(&invite_key_buf)?;
+		batch.put_ser(
+			&category_key(server_pubkey, server_id, Some(category_id)),
+			&Category {
+				name,
+				category_id,
+				position,
+			},
+		)?;
+		batch.commit()?;
+		Ok(category_id)
+	}
 
+	// rename a category. Returns false if the category does not exist.
+	pub fn modify_category(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		category_id: u64,
+		name: String,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let key = category_key(server_pubkey, server_id, Some(category_id));
+		let category: Option<Category> = batch.get_ser(&key)?;
+		match category {
+			Some(mut category) => {
+				category.name = name;
+				batch.put_ser(&key, &category)?;
 				batch.commit()?;
+				Ok(true)
 			}
-			None => {}
+			None => Ok(false),
 		}
-		Ok(())
 	}
 
-	pub fn check_invite(
+	// delete a category. The channels in it are kept and no longer have a category.
+	pub fn delete_category(
 		&self,
-		invite_id: u128,
 		server_pubkey: [u8; 32],
-	) -> Result<Option<JoinInfoReply>, Error> {
-		error!(
-			"checking id={}, be={:?}",
-			invite_id,
-			invite_id.to_be_bytes().to_vec()
-		);
-
+		server_id: [u8; 8],
+		category_id: u64,
+	) -> Result<(), Error> {
 		let batch = self.store.batch()?;
-		let mut key = vec![INVITE_ID_PREFIX];
-		key.append(&mut invite_id.to_be_bytes().to_vec());
-		let invite: Option<Invite> = batch.get_ser(&key)?;
-		match invite {
-			Some(invite) => {
-				info!("found a match = {:?}", invite);
-				match invite.cur >= invite.max {
-					true => Ok(None), // accepted too many times
-					false => {
-						let mut key = vec![SERVER_PREFIX];
-						key.append(&mut invite.server_id.to_vec());
-						key.append(&mut server_pubkey.to_vec());
-						let ret: Option<ServerInfo> = batch.get_ser(&key)?;
+		for mut channel in self.get_channels_impl(server_pubkey, server_id, &batch)? {
+			if channel.category_id == category_id {
+				channel.category_id = 0;
+				let channel_key = ChannelKey {
+					channel_id: channel.channel_id,
+					server_id,
+					server_pubkey,
+				};
+				self.set_channel_impl(channel_key, channel, &batch)?;
+			}
+		}
+		let _ = batch.delete(&category_key(server_pubkey, server_id, Some(category_id)));
+		batch.commit()?;
+		Ok(())
+	}
+
+	// apply a new ordering in a single transaction. `categories` lists category ids in their
+	// new order and `channels` lists (channel_id, category_id) in their new order. Channels
+	// and categories that are not listed keep their relative order after the listed ones.
+	// Returns false and changes nothing if an unknown channel or category is referenced.
+	pub fn reorder_channels(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		categories: Vec<u64>,
+		channels: Vec<(u64, u64)>,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let mut cur_categories = self.get_categories_impl(server_pubkey, server_id, &batch)?;
+		let mut cur_channels = self.get_channels_impl(server_pubkey, server_id, &batch)?;
+
+		for category_id in &categories {
+			if !cur_categories.iter().any(|c| c.category_id == *category_id) {
+				return Ok(false);
+			}
+		}
+		for (channel_id, category_id) in &channels {
+			if !cur_channels.iter().any(|c| c.channel_id == *channel_id) {
+				return Ok(false);
+			}
+			if *category_id != 0 && !cur_categories.iter().any(|c| c.category_id == *category_id) {
+				return Ok(false);
+			}
+		}
+
+		// listed entries sort by their index, the rest after them in their current order
+		let listed = categories.len();
+		cur_categories.sort_by_key(|c| {
+			match categories.iter().position(|id| *id == c.category_id) {
+				Some(i) => i,
+				None => listed,
+			}
+		});
+		for (i, mut category) in cur_categories.into_iter().enumerate() {
+			category.position = i.try_into()?;
+			batch.put_ser(
+				&category_key(server_pubkey, server_id, Some(category.category_id)),
+				&category,
+			)?;
+		}
+
+		let listed = channels.len();
+		cur_channels.sort_by_key(|c| {
+			match channels.iter().position(|(id, _)| *id == c.channel_id) {
+				Some(i) => i,
+				None => listed,
+			}
+		});
+		for (i, mut channel) in cur_channels.into_iter().enumerate() {
+			channel.position = i.try_into()?;
+			if let Some((_, category_id)) =
+				channels.iter().find(|(id, _)| *id == channel.channel_id)
+			{
+				channel.category_id = *category_id;
+			}
+			let channel_key = ChannelKey {
+				channel_id: channel.channel_id,
+				server_id,
+				server_pubkey,
+			};
+			self.set_channel_impl(channel_key, channel, &batch)?;
+		}
+
+		batch.commit()?;
+		Ok(true)
+	}
+
+	pub fn create_invite(
+		&self,
+		inviter: [u8; 32],
+		server_id: [u8; 8],
+		expiry: u128,
+		count: u64,
+		roles: u128,
+		landing_channel: u64,
+	) -> Result<u128, Error> {
+		let batch = self.store.batch()?;
+
+		let id: u128 = rand::random();
+
+		let invite = Invite {
+			inviter,
+			server_id,
+			expiry,
+			cur: 0,
+			max: count,
+			id,
+			roles,
+			landing_channel,
+		};
+
+		self.put_invite(&invite, &batch)?;
+
+		batch.commit()?;
+
+		Ok(id)
+	}
+
+	// write an invite to both the server index and the invite id index.
+	fn put_invite(&self, invite: &Invite, batch: &Batch) -> Result<(), Error> {
+		let mut buffer = vec![];
+		serialize_default(&mut buffer, invite)?;
+		batch.put_ser(&invite_key(invite)?, &buffer)?;
+		batch.put_ser(&invite_id_key(invite.id), &buffer)?;
+		Ok(())
+	}
+
+	fn get_invite_impl(&self, invite_id: u128, batch: &Batch) -> Result<Option<Invite>, Error> {
+		batch.get_ser(&invite_id_key(invite_id))
+	}
+
+	// returns the recorded uses of an invite, oldest first. Uses are kept after the invite
+	// itself is deleted.
+	pub fn get_invite_uses(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		invite_id: u128,
+	) -> Result<Vec<InviteUse>, Error> {
+		let batch = self.store.batch()?;
+		let mut prefix = invite_use_key(server_pubkey, server_id, None);
+		prefix.append(&mut invite_id.to_be_bytes().to_vec());
+		let mut itt = batch.iter(&prefix, |_, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			InviteUse::read(&mut reader)
+		})?;
+
+		let mut ret = vec![];
+		loop {
+			match itt.next() {
+				Some(invite_use) => ret.push(invite_use),
+				None => break,
+			}
+		}
+		Ok(ret)
+	}
+
+	pub fn get_invite(&self, invite_id: u128) -> Result<Option<Invite>, Error> {
+		let batch = self.store.batch()?;
+		self.get_invite_impl(invite_id, &batch)
+	}
+
+	// change the maximum number of uses and the expiry of an invite. Returns false if the
+	// invite does not exist.
+	pub fn modify_invite(&self, invite_id: u128, max: u64, expiry: u128) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		match self.get_invite_impl(invite_id, &batch)? {
+			Some(mut invite) => {
+				invite.max = max;
+				invite.expiry = expiry;
+				self.put_invite(&invite, &batch)?;
+				batch.commit()?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	// delete invites that have expired or have been used up.
+	pub fn purge_invites(&self) -> Result<(), Error> {
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+
+		let batch = self.store.batch()?;
+		let mut itt = batch.iter(&(vec![INVITE_ID_PREFIX])[..], |_, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			Invite::read(&mut reader)
+		})?;
+
+		loop {
+			match itt.next() {
+				Some(invite) => {
+					if !invite.is_live(time_now) {
+						debug!("purging invite {}", invite.id);
+						batch.delete(&invite_id_key(invite.id))?;
+						batch.delete(&invite_key(&invite)?)?;
+					}
+				}
+				None => break,
+			}
+		}
+
+		batch.commit()?;
+
+		Ok(())
+	}
+
+	pub fn get_invites(
+		&self,
+		inviter: Option<[u8; 32]>,
+		server_id: [u8; 8],
+	) -> Result<Vec<Invite>, Error> {
+		let batch = self.store.batch()?;
+		// get the iterator for each invite
+		let mut key_vec = vec![INVITE_PREFIX];
+		key_vec.append(&mut server_id.to_vec());
+		// if inviter is specfied
+		match inviter {
+			Some(inviter) => {
+				key_vec.append(&mut inviter.to_vec());
+			}
+			None => {}
+		}
+
+		let mut itt = batch.iter(&(key_vec[..]), |_, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			let invite = Invite::read(&mut reader)?;
+			Ok(invite)
+		})?;
+
+		let mut ret = vec![];
+		loop {
+			let next = itt.next();
+			match next {
+				Some(invite) => {
+					ret.push(invite);
+				}
+				None => {
+					break;
+				}
+			}
+		}
+
+		Ok(ret)
+	}
+
+	pub fn delete_invite(&self, invite_id: u128) -> Result<(), Error> {
+		let batch = self.store.batch()?;
+
+		if let Some(invite) = self.get_invite_impl(invite_id, &batch)? {
+			batch.delete(&invite_id_key(invite_id))?;
+			batch.delete(&invite_key(&invite)?)?;
+
+			batch.commit()?;
+		}
+		Ok(())
+	}
+
+	pub fn check_invite(
+		&self,
+		invite_id: u128,
+		server_pubkey: [u8; 32],
+	) -> Result<Option<JoinInfoReply>, Error> {
+		error!(
+			"checking id={}, be={:?}",
+			invite_id,
+			invite_id.to_be_bytes().to_vec()
+		);
+
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+
+		let batch = self.store.batch()?;
+		match self.get_invite_impl(invite_id, &batch)? {
+			Some(invite) => {
+				info!("found a match = {:?}", invite);
+				match !invite.is_live(time_now) {
+					true => Ok(None), // expired or accepted too many times
+					false => {
+						let mut key = vec![SERVER_PREFIX];
+						key.append(&mut invite.server_id.to_vec());
+						key.append(&mut server_pubkey.to_vec());
+						let ret: Option<ServerInfo> = batch.get_ser(&key)?;
 						info!(
 							"ret on server info was {:?}, server_id={:?}",
 							ret, invite.server_id
@@ -1691,6 +3352,12 @@ impl DSContext {
 								name: ret.name,
 								server_id: invite.server_id,
 								inviter_pubkey: invite.inviter,
+								description: ret.description,
+								topic: ret.topic,
+								creation_time: ret.creation_time,
+								rules: ret.rules,
+								verification_level: ret.verification_level,
+								pow_difficulty: ret.pow_difficulty,
 							})),
 							None => Ok(None),
 						}
@@ -1701,6 +3368,8 @@ impl DSContext {
 		}
 	}
 
+	// accept an invite. Users that are not already members must solve the server's proof of
+	// work. If the server requires approval, a join request is queued instead of adding them.
 	pub fn accept_invite(
 		&self,
 		invite_id: u128,
@@ -1709,75 +3378,273 @@ impl DSContext {
 		user_name: String,
 		user_bio: String,
 		_avatar: Vec<u8>,
-	) -> Result<Option<ServerInfoReply>, Error> {
-		let batch = self.store.batch()?;
-		let mut key = vec![INVITE_ID_PREFIX];
-		key.append(&mut invite_id.to_be_bytes().to_vec());
-		let invite: Option<Invite> = batch.get_ser(&key)?;
-		match invite {
-			Some(mut invite) => {
-				if invite.cur >= invite.max {
-					// this invite has been accepted too many times
-					Ok(None)
-				} else {
-					// success, increment accept counter and write back
-					invite.cur += 1;
-					let mut buffer = vec![];
-					serialize_default(&mut buffer, &invite)?;
-					batch.put_ser(&key, &buffer)?;
+		pow_nonce: u64,
+	) -> Result<AcceptInviteResult, Error> {
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
 
-					let user_name = user_name.into();
-					let user_bio = user_bio.into();
-					// build the profile
-					self.set_profile_impl(
-						Pubkey::from_bytes(user_pubkey),
-						Pubkey::from_bytes(server_pubkey),
-						ServerId::from_bytes(invite.server_id),
-						ProfileData {
-							user_name,
-							user_bio,
-						},
-						&batch,
-					)?;
+		let batch = self.store.batch()?;
+		let mut invite = match self.get_invite_impl(invite_id, &batch)? {
+			Some(invite) => invite,
+			// this is not a valid invite, reject
+			None => return Ok(AcceptInviteResult::Rejected),
+		};
 
-					// add to member table
-					self.set_member(
-						Pubkey::from_bytes(user_pubkey),
-						ServerId::from_bytes(invite.server_id),
-						Pubkey::from_bytes(server_pubkey),
-						AUTH_FLAG_MEMBER,
-						None,
-						None,
-						&batch,
-					)?;
+		if !invite.is_live(time_now) {
+			// this invite has expired or has been accepted too many times
+			return Ok(AcceptInviteResult::Rejected);
+		}
 
-					let mut key = vec![SERVER_PREFIX];
-					key.append(&mut invite.server_id.to_vec());
-					let ret: Option<ServerInfo> = batch.get_ser(&key)?;
+		let mut key = vec![SERVER_PREFIX];
+		key.append(&mut invite.server_id.to_vec());
+		key.append(&mut server_pubkey.to_vec());
+		let server_info: ServerInfo = match batch.get_ser(&key)? {
+			Some(server_info) => server_info,
+			None => return Ok(AcceptInviteResult::Rejected),
+		};
 
-					batch.commit()?;
+		let is_member = self
+			.get_member(
+				Pubkey::from_bytes(user_pubkey),
+				ServerId::from_bytes(invite.server_id),
+				Pubkey::from_bytes(server_pubkey),
+				&batch,
+			)?
+			.is_some();
+
+		if !is_member
+			&& !pow::verify(
+				server_pubkey,
+				invite_id,
+				user_pubkey,
+				pow_nonce,
+				server_info.pow_difficulty,
+			) {
+			return Ok(AcceptInviteResult::ProofOfWorkRequired(
+				server_info.pow_difficulty,
+			));
+		}
 
-					match ret {
-						Some(ret) => Ok(Some(ServerInfoReply {
-							pubkey: ret.pubkey,
-							name: ret.name,
-							server_id: invite.server_id,
-							seqno: ret.seqno,
-						})),
-						None => Ok(None),
-					}
-				}
+		let join_request_key = join_request_key(server_pubkey, invite.server_id, Some(user_pubkey));
+		if server_info.require_approval && !is_member {
+			// a repeated request replaces the pending one without using the invite again
+			let pending: Option<JoinRequest> = batch.get_ser(&join_request_key)?;
+			if pending.is_none() {
+				invite.cur += 1;
+				self.put_invite(&invite, &batch)?;
 			}
-			None => {
-				// this is not a valid invite, reject
-				Ok(None)
+
+			let join_request = JoinRequest {
+				user_pubkey,
+				invite_id,
+				inviter: invite.inviter,
+				roles: invite.roles,
+				landing_channel: invite.landing_channel,
+				user_name,
+				user_bio,
+				request_time: time_now.try_into()?,
+			};
+			batch.put_ser(&join_request_key, &join_request)?;
+			batch.commit()?;
+			return Ok(AcceptInviteResult::Pending);
+		}
+
+		// success, increment accept counter and write back. A member accepting again doesn't
+		// use up the invite.
+		if !is_member {
+			invite.cur += 1;
+			self.put_invite(&invite, &batch)?;
+		}
+
+		let landing_channel = self.admit_member(
+			server_pubkey,
+			invite.server_id,
+			JoinRequest {
+				user_pubkey,
+				invite_id,
+				inviter: invite.inviter,
+				roles: invite.roles,
+				landing_channel: invite.landing_channel,
+				user_name,
+				user_bio,
+				request_time: time_now.try_into()?,
+			},
+			&batch,
+		)?;
+
+		batch.commit()?;
+
+		Ok(AcceptInviteResult::Joined(
+			server_info.reply(invite.server_id),
+			landing_channel,
+		))
+	}
+
+	// returns the pending join requests of a server, oldest first.
+	pub fn list_join_requests(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+	) -> Result<Vec<JoinRequest>, Error> {
+		let batch = self.store.batch()?;
+		let prefix = join_request_key(server_pubkey, server_id, None);
+		let mut itt = batch.iter(&prefix, |_, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			JoinRequest::read(&mut reader)
+		})?;
+
+		let mut ret = vec![];
+		loop {
+			match itt.next() {
+				Some(join_request) => ret.push(join_request),
+				None => break,
 			}
 		}
+		ret.sort_by_key(|join_request| join_request.request_time);
+		Ok(ret)
 	}
 
-	fn get_member(
+	// add the applicant of a pending join request as a member. Returns the landing channel
+	// of the request, or None if there is no such request.
+	pub fn approve_join_request(
 		&self,
-		user_pubkey: Pubkey,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		user_pubkey: [u8; 32],
+	) -> Result<Option<u64>, Error> {
+		let batch = self.store.batch()?;
+		let key = join_request_key(server_pubkey, server_id, Some(user_pubkey));
+		let join_request: Option<JoinRequest> = batch.get_ser(&key)?;
+		match join_request {
+			Some(join_request) => {
+				batch.delete(&key)?;
+				let landing_channel =
+					self.admit_member(server_pubkey, server_id, join_request, &batch)?;
+				batch.commit()?;
+				Ok(Some(landing_channel))
+			}
+			None => Ok(None),
+		}
+	}
+
+	// drop a pending join request and give back the invite use it consumed. Returns false if
+	// there is no such request.
+	pub fn reject_join_request(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		user_pubkey: [u8; 32],
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let key = join_request_key(server_pubkey, server_id, Some(user_pubkey));
+		let join_request: Option<JoinRequest> = batch.get_ser(&key)?;
+		match join_request {
+			Some(join_request) => {
+				batch.delete(&key)?;
+				// the invite may have been deleted in the meantime
+				match self.get_invite_impl(join_request.invite_id, &batch)? {
+					Some(mut invite) => {
+						invite.cur = invite.cur.saturating_sub(1);
+						self.put_invite(&invite, &batch)?;
+					}
+					None => {}
+				}
+				batch.commit()?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	// build the profile and member entries for a user joining through an invite, and record
+	// the invite use. Returns the landing channel, 0 if there is none.
+	fn admit_member(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		join_request: JoinRequest,
+		batch: &Batch,
+	) -> Result<u64, Error> {
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+		let user_pubkey = join_request.user_pubkey;
+
+		// build the profile
+		self.set_profile_impl(
+			Pubkey::from_bytes(user_pubkey),
+			Pubkey::from_bytes(server_pubkey),
+			ServerId::from_bytes(server_id),
+			ProfileData {
+				user_name: join_request.user_name.into(),
+				user_bio: join_request.user_bio.into(),
+			},
+			batch,
+		)?;
+
+		// add to member table
+		let (member, new_member) = self.set_member(
+			Pubkey::from_bytes(user_pubkey),
+			ServerId::from_bytes(server_id),
+			Pubkey::from_bytes(server_pubkey),
+			join_request.roles | AUTH_FLAG_MEMBER,
+			None,
+			None,
+			Some(Pubkey::from_bytes(join_request.inviter)),
+			batch,
+		)?;
+
+		// give the member access to the landing channel
+		let landing_channel = match join_request.landing_channel {
+			0 => 0,
+			channel_id => {
+				let channel_key = ChannelKey {
+					server_pubkey,
+					server_id,
+					channel_id,
+				};
+				match self.get_channel_impl(&channel_key, batch)? {
+					Some(mut channel) => {
+						channel.overwrites.retain(|o| match o.target {
+							OverwriteTarget::User(pubkey) => pubkey != user_pubkey,
+							OverwriteTarget::Role(_) => true,
+						});
+						channel.overwrites.push(PermissionOverwrite {
+							target: OverwriteTarget::User(user_pubkey),
+							allow: CHANNEL_PERMISSION_VIEW | CHANNEL_PERMISSION_SEND,
+							deny: 0,
+						});
+						self.set_channel_impl(channel_key, channel, batch)?;
+						channel_id
+					}
+					None => 0,
+				}
+			}
+		};
+
+		// keep a record of who joined through this invite
+		let invite_use = InviteUse {
+			invite_id: join_request.invite_id,
+			user_pubkey,
+			time: time_now.try_into()?,
+			roles: member.roles,
+			join_time: member.join_time,
+			new_member,
+		};
+		batch.put_ser(
+			&invite_use_key(server_pubkey, server_id, Some(&invite_use)),
+			&invite_use,
+		)?;
+
+		Ok(landing_channel)
+	}
+
+	fn get_member(
+		&self,
+		user_pubkey: Pubkey,
 		server_id: ServerId,
 		server_pubkey: Pubkey,
 		batch: &Batch,
@@ -1800,11 +3667,45 @@ impl DSContext {
 				join_time: m.join_time,
 				modified_time: m.modified_time,
 				batch_num: m.batch_num,
+				inviter: m.inviter,
 			})),
 			None => Ok(None),
 		}
 	}
 
+	// returns the number of members that have joined the server.
+	pub fn member_count(&self, server_pubkey: [u8; 32], server_id: [u8; 8]) -> Result<u64, Error> {
+		let batch = self.store.batch()?;
+		let mut member_meta_data_key = vec![];
+		serialize_default(
+			&mut member_meta_data_key,
+			&MemberMetaDataKey {
+				server_pubkey: Pubkey::from_bytes(server_pubkey),
+				server_id: ServerId::from_bytes(server_id),
+			},
+		)?;
+		let member_meta_data_value: Option<MemberMetaDataValue> =
+			batch.get_ser(&member_meta_data_key)?;
+		Ok(member_meta_data_value.map(|m| m.member_count).unwrap_or(0))
+	}
+
+	// look up a single member of a server. Profile data is not included.
+	pub fn get_server_member(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		user_pubkey: [u8; 32],
+	) -> Result<Option<Member>, Error> {
+		let batch = self.store.batch()?;
+		let member = self.get_member(
+			Pubkey::from_bytes(user_pubkey),
+			ServerId::from_bytes(server_id),
+			Pubkey::from_bytes(server_pubkey),
+			&batch,
+		)?;
+		Ok(member.map(|m| m.into()))
+	}
+
 	/*
 		fn update_roles(
 			&self,
@@ -1851,8 +3752,9 @@ impl DSContext {
 		roles: u128,
 		modified_time: Option<u64>,
 		join_time: Option<u64>,
+		inviter: Option<Pubkey>,
 		batch: &Batch,
-	) -> Result<Member, Error> {
+	) -> Result<(Member, bool), Error> {
 		let time_now = std::time::SystemTime::now()
 			.duration_since(std::time::UNIX_EPOCH)?
 			.as_millis()
@@ -1861,7 +3763,7 @@ impl DSContext {
 		match self.get_member(user_pubkey, server_id, server_pubkey, batch)? {
 			Some(member) => {
 				warn!("member already joined the server");
-				Ok(member.into())
+				Ok((member.into(), false))
 			}
 			None => {
 				let member = Member {
@@ -1870,10 +3772,11 @@ impl DSContext {
 					roles,
 					join_time: join_time.unwrap_or(time_now),
 					modified_time: modified_time.unwrap_or(time_now),
+					inviter,
 				};
 
 				self.save_member(server_pubkey, server_id, &member, batch)?;
-				Ok(member.into())
+				Ok((member, true))
 			}
 		}
 	}
@@ -1938,8 +3841,177 @@ impl DSContext {
 			batch_num,
 			server_id,
 			server_pubkey,
+			inviter: member.inviter,
+		};
+
+		self.write_member(member_impl, batch)
+	}
+
+	// update the roles of an existing member. AUTH_FLAG_MEMBER is always kept. Returns false
+	// if the user is not a member or if the change would leave the server without an owner.
+	pub fn set_member_roles(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		user_pubkey: [u8; 32],
+		roles: u128,
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let member = self.get_member(
+			Pubkey::from_bytes(user_pubkey),
+			ServerId::from_bytes(server_id),
+			Pubkey::from_bytes(server_pubkey),
+			&batch,
+		)?;
+
+		let roles = roles | AUTH_FLAG_MEMBER;
+		match member {
+			Some(mut member) => {
+				if member.roles & AUTH_FLAG_OWNER != 0
+					&& roles & AUTH_FLAG_OWNER == 0
+					&& self.owner_count(server_pubkey, server_id, &batch)? <= 1
+				{
+					return Ok(false);
+				}
+
+				// roles are part of the auth key so the old keys must be removed
+				let member_key_itt: MemberKeyIttImpl = (&member).into();
+				let member_key_auth: MemberKeyAuthImpl = (&member).into();
+				let mut member_key_itt_buffer = vec![];
+				let mut member_key_auth_buffer = vec![];
+				serialize_default(&mut member_key_itt_buffer, &member_key_itt)?;
+				serialize_default(&mut member_key_auth_buffer, &member_key_auth)?;
+				let _ = batch.delete(&member_key_itt_buffer);
+				let _ = batch.delete(&member_key_auth_buffer);
+
+				member.roles = roles;
+				member.modified_time = std::time::SystemTime::now()
+					.duration_since(std::time::UNIX_EPOCH)?
+					.as_millis()
+					.try_into()?;
+				self.write_member(&member, &batch)?;
+				batch.commit()?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	// owners always have roles beyond AUTH_FLAG_MEMBER so only the auth table is checked.
+	fn owner_count(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		batch: &Batch,
+	) -> Result<u64, Error> {
+		let mut prefix = vec![MEMBER_AUTH_PREFIX];
+		prefix.append(&mut server_pubkey.to_vec());
+		prefix.append(&mut server_id.to_vec());
+
+		let mut itt = batch.iter(&prefix, |k, _v| {
+			let mut cursor = Cursor::new(k.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			Ok(MemberKeyAuthImpl::read(&mut reader)?.roles)
+		})?;
+
+		let mut count = 0;
+		loop {
+			match itt.next() {
+				Some(roles) => {
+					if roles & AUTH_FLAG_OWNER != 0 {
+						count += 1;
+					}
+				}
+				None => break,
+			}
+		}
+
+		Ok(count)
+	}
+
+	// remove a member from the server along with its profile and name index entry. Returns
+	// false if the user is not a member or is the last owner of the server.
+	pub fn remove_member(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		user_pubkey: [u8; 32],
+	) -> Result<bool, Error> {
+		let batch = self.store.batch()?;
+		let member = match self.get_member(
+			Pubkey::from_bytes(user_pubkey),
+			ServerId::from_bytes(server_id),
+			Pubkey::from_bytes(server_pubkey),
+			&batch,
+		)? {
+			Some(member) => member,
+			None => return Ok(false),
+		};
+
+		if member.roles & AUTH_FLAG_OWNER != 0
+			&& self.owner_count(server_pubkey, server_id, &batch)? <= 1
+		{
+			return Ok(false);
+		}
+
+		let member_key_hash: MemberKeyHashImpl = (&member).into();
+		let member_key_itt: MemberKeyIttImpl = (&member).into();
+		let member_key_auth: MemberKeyAuthImpl = (&member).into();
+		let mut member_key_hash_buffer = vec![];
+		let mut member_key_itt_buffer = vec![];
+		let mut member_key_auth_buffer = vec![];
+		serialize_default(&mut member_key_hash_buffer, &member_key_hash)?;
+		serialize_default(&mut member_key_itt_buffer, &member_key_itt)?;
+		serialize_default(&mut member_key_auth_buffer, &member_key_auth)?;
+		batch.delete(&member_key_hash_buffer)?;
+		let _ = batch.delete(&member_key_itt_buffer);
+		let _ = batch.delete(&member_key_auth_buffer);
+
+		let profile_key = ProfileKey {
+			user_pubkey: member.user_pubkey,
+			server_pubkey: member.server_pubkey,
+			server_id: member.server_id,
 		};
+		let mut profile_key_buffer = vec![];
+		serialize_default(&mut profile_key_buffer, &profile_key)?;
+		let profile: Option<ProfileData> = batch.get_ser(&profile_key_buffer)?;
+		match profile {
+			Some(profile) => {
+				let _ = batch.delete(&member_name_key(
+					&profile_key,
+					&profile.user_name.to_string(),
+				)?);
+				batch.delete(&profile_key_buffer)?;
+			}
+			None => {}
+		}
+
+		let mut member_meta_data_key = vec![];
+		serialize_default(
+			&mut member_meta_data_key,
+			&MemberMetaDataKey {
+				server_pubkey: member.server_pubkey,
+				server_id: member.server_id,
+			},
+		)?;
+		let member_meta_data_value: Option<MemberMetaDataValue> =
+			batch.get_ser(&member_meta_data_key)?;
+		match member_meta_data_value {
+			Some(meta_data) => batch.put_ser(
+				&member_meta_data_key,
+				&MemberMetaDataValue {
+					member_count: meta_data.member_count.saturating_sub(1),
+				},
+			)?,
+			None => {}
+		}
 
+		batch.commit()?;
+		Ok(true)
+	}
+
+	fn write_member(&self, member_impl: &MemberImpl, batch: &Batch) -> Result<(), Error> {
 		// create key/value structs
 		let member_key_hash: MemberKeyHashImpl = member_impl.into();
 		let member_key_itt: MemberKeyIttImpl = member_impl.into();
@@ -1960,9 +4032,9 @@ impl DSContext {
 		batch.put_ser(&member_key_hash_buffer, &member_value)?;
 
 		// only want to add the user to one of the two tables
-		// for now roles == 0 is member, anything else is auth table
-		// meaning it will be listed first
-		match member.roles == 0 {
+		// members with no roles other than AUTH_FLAG_MEMBER go in the member
+		// table, anything else is auth table meaning it will be listed first
+		match member_impl.roles & !AUTH_FLAG_MEMBER == 0 {
 			true => {
 				batch.put_ser(&member_key_itt_buffer, &member_value)?;
 				// have to remove incase of auth changes
@@ -1978,113 +4050,205 @@ impl DSContext {
 		Ok(())
 	}
 
+	// returns a page of at most `limit` members of the server, with members that have roles
+	// beyond AUTH_FLAG_MEMBER (moderators) listed first. The returned cursor is passed back in
+	// to get the next page. Since members are ordered by join order, people joining while a
+	// client pages through the list do not shift the pages that were already returned.
 	pub fn get_members(
 		&self,
 		server_pubkey: [u8; 32],
 		server_id: [u8; 8],
-		batch_num: u64,
-		auth: bool, // if true send back users with auth privileges (moderators), otherwise members.
-	) -> Result<Vec<Member>, Error> {
+		cursor: Option<PageCursor>,
+		limit: u64,
+	) -> Result<(Vec<Member>, Option<PageCursor>), Error> {
 		let batch = self.store.batch()?;
-		let mut ret = vec![];
-		match auth {
-			true => {
-				let ablki = AuthBatchLookupKeyImpl {
+		let limit: usize = match limit == 0 || limit > MEMBER_BATCH_SIZE {
+			true => MEMBER_BATCH_SIZE,
+			false => limit,
+		}
+		.try_into()?;
+
+		// the auth table is listed before the member table
+		let mut tables = vec![];
+		for table_prefix in &[MEMBER_AUTH_PREFIX, MEMBER_ITT_PREFIX] {
+			let mut prefix = vec![*table_prefix];
+			prefix.append(&mut server_pubkey.to_vec());
+			prefix.append(&mut server_id.to_vec());
+			tables.push(prefix);
+		}
+
+		// the cursor is the key of the last member of the previous page
+		let (first_table, cursor) = match cursor {
+			Some(cursor) => match tables.iter().position(|p| cursor.data.starts_with(p)) {
+				Some(i) => (i, Some(cursor.data)),
+				None => {
+					return Err(ErrorKind::IllegalArgument(format!(
+						"invalid member cursor: {:?}",
+						cursor
+					))
+					.into());
+				}
+			},
+			None => (0, None),
+		};
+
+		let mut entries: Vec<(Vec<u8>, MemberImpl)> = vec![];
+		for (i, prefix) in tables.iter().enumerate().skip(first_table) {
+			let start = match (i == first_table, &cursor) {
+				(true, Some(cursor)) => cursor.clone(),
+				_ => prefix.clone(),
+			};
+			let auth = prefix[0] == MEMBER_AUTH_PREFIX;
+
+			let mut itt = batch.iter_from(prefix, &start, |k, v| {
+				let mut cursor = Cursor::new(k.to_vec());
+				cursor.set_position(0);
+				let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+				let (user_pubkey, batch_num) = match auth {
+					true => {
+						let member_key = MemberKeyAuthImpl::read(&mut reader)?;
+						(member_key.user_pubkey, member_key.batch_num)
+					}
+					false => {
+						let member_key = MemberKeyIttImpl::read(&mut reader)?;
+						(member_key.user_pubkey, member_key.batch_num)
+					}
+				};
+
+				let mut cursor = Cursor::new(v.to_vec());
+				cursor.set_position(0);
+				let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+				let member_value = MemberValueImpl::read(&mut reader)?;
+
+				let member = MemberImpl {
+					user_pubkey,
 					server_pubkey: Pubkey::from_bytes(server_pubkey),
 					server_id: ServerId::from_bytes(server_id),
 					batch_num,
+					roles: member_value.roles,
+					join_time: member_value.join_time,
+					modified_time: member_value.modified_time,
+					inviter: member_value.inviter,
 				};
-				let mut ablki_buffer = vec![];
-				serialize_default(&mut ablki_buffer, &ablki)?;
-				let mut itt = batch.iter(&(ablki_buffer[..]), |k, v| {
-					let mut cursor = Cursor::new(k.to_vec());
-					cursor.set_position(0);
-					let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
-					let member_key = MemberKeyAuthImpl::read(&mut reader)?;
-
-					let mut cursor = Cursor::new(v.to_vec());
-					cursor.set_position(0);
-					let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
-					let member_value = MemberValueImpl::read(&mut reader)?;
-
-					let member = MemberImpl {
-						user_pubkey: member_key.user_pubkey,
-						server_pubkey: member_key.server_pubkey,
-						server_id: member_key.server_id,
-						batch_num: member_key.batch_num,
-						roles: member_value.roles,
-						join_time: member_value.join_time,
-						modified_time: member_value.modified_time,
-					};
 
-					Ok(member)
-				})?;
+				Ok((k.to_vec(), member))
+			})?;
 
-				loop {
-					let next = itt.next();
-					match next {
-						Some(member) => ret.push(member),
-						None => break,
+			// read one past the limit so we know whether there is another page
+			loop {
+				match itt.next() {
+					Some((key, member)) => {
+						if Some(&key) == cursor.as_ref() {
+							continue;
+						}
+						entries.push((key, member));
+						if entries.len() > limit {
+							break;
+						}
 					}
+					None => break,
 				}
 			}
-			false => {
-				let mblki = MemberBatchLookupKeyImpl {
-					server_pubkey: Pubkey::from_bytes(server_pubkey),
-					server_id: ServerId::from_bytes(server_id),
-					batch_num,
-				};
 
-				let mut mblki_buffer = vec![];
-				serialize_default(&mut mblki_buffer, &mblki)?;
-				let mut itt = batch.iter(&(mblki_buffer[..]), |k, v| {
-					let mut cursor = Cursor::new(k.to_vec());
-					cursor.set_position(0);
-					let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
-					let member_key = MemberKeyIttImpl::read(&mut reader)?;
-
-					let mut cursor = Cursor::new(v.to_vec());
-					cursor.set_position(0);
-					let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
-					let member_value = MemberValueImpl::read(&mut reader)?;
-
-					let member = MemberImpl {
-						user_pubkey: member_key.user_pubkey,
-						server_pubkey: member_key.server_pubkey,
-						server_id: member_key.server_id,
-						batch_num: member_key.batch_num,
-						roles: member_value.roles,
-						join_time: member_value.join_time,
-						modified_time: member_value.modified_time,
-					};
+			if entries.len() > limit {
+				break;
+			}
+		}
+
+		let next_cursor = match entries.len() > limit {
+			true => {
+				entries.truncate(limit);
+				entries
+					.last()
+					.map(|(key, _)| PageCursor { data: key.clone() })
+			}
+			false => None,
+		};
+
+		let members = entries.into_iter().map(|(_, member)| member).collect();
+		let members = self.add_profile_data(
+			members,
+			Pubkey::from_bytes(server_pubkey),
+			ServerId::from_bytes(server_id),
+			&batch,
+		)?;
+
+		Ok((members, next_cursor))
+	}
+
+	// find members of a server whose user_name starts with `query` (case insensitive).
+	pub fn search_members(
+		&self,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		query: &str,
+		limit: u64,
+	) -> Result<Vec<Member>, Error> {
+		let batch = self.store.batch()?;
+		let limit: usize = match limit == 0 || limit > MEMBER_BATCH_SIZE {
+			true => MEMBER_BATCH_SIZE,
+			false => limit,
+		}
+		.try_into()?;
+		let server_pubkey = Pubkey::from_bytes(server_pubkey);
+		let server_id = ServerId::from_bytes(server_id);
+
+		let mut prefix = vec![];
+		serialize_default(
+			&mut prefix,
+			&MemberNameKeyImpl {
+				server_pubkey: server_pubkey.clone(),
+				server_id: server_id.clone(),
+				user_name: query.to_lowercase(),
+				user_pubkey: None,
+			},
+		)?;
 
-					Ok(member)
-				})?;
+		// the user_pubkey is always the last 32 bytes of the name key
+		let mut itt = batch.iter(&prefix, |k, _| {
+			let user_pubkey: [u8; 32] = k[k.len().saturating_sub(32)..].try_into()?;
+			Ok(Pubkey::from_bytes(user_pubkey))
+		})?;
 
-				loop {
-					let next = itt.next();
-					match next {
-						Some(member) => ret.push(member),
-						None => break,
+		let mut members = vec![];
+		loop {
+			match itt.next() {
+				Some(user_pubkey) => {
+					match self.get_member(
+						user_pubkey,
+						server_id.clone(),
+						server_pubkey.clone(),
+						&batch,
+					)? {
+						Some(member) => members.push(member),
+						None => {}
+					}
+					if members.len() >= limit {
+						break;
 					}
 				}
+				None => break,
 			}
 		}
 
+		self.add_profile_data(members, server_pubkey, server_id, &batch)
+	}
+
+	fn add_profile_data(
+		&self,
+		members_impl: Vec<MemberImpl>,
+		server_pubkey: Pubkey,
+		server_id: ServerId,
+		batch: &Batch,
+	) -> Result<Vec<Member>, Error> {
 		let mut members: Vec<Member> = vec![];
 		let mut pubkeys = vec![];
-		for member in ret {
+		for member in members_impl {
 			pubkeys.push(member.user_pubkey);
 			members.push(member.into());
-			// handle including profile here.
 		}
 
-		let profiles = self.get_profiles_impl(
-			pubkeys,
-			Pubkey::from_bytes(server_pubkey),
-			ServerId::from_bytes(server_id),
-			&batch,
-		)?;
+		let profiles = self.get_profiles_impl(pubkeys, server_pubkey, server_id, batch)?;
 
 		let mut i = 0;
 		for profile in profiles {
@@ -2098,49 +4262,71 @@ impl DSContext {
 		Ok(members)
 	}
 
-	pub fn create_auth_challenge(&self, user_pubkey: [u8; 32]) -> Result<[u8; 8], Error> {
+	// issue a challenge for the server. It must be answered with consume_challenge or
+	// validate_challenge within CHALLENGE_EXPIRATION.
+	pub fn create_auth_challenge(&self, server_pubkey: [u8; 32]) -> Result<Challenge, Error> {
+		let challenge = Challenge::new(server_pubkey)?;
 		let batch = self.store.batch()?;
-		let challenge: [u8; 8] = rand::random();
 		let mut key = vec![CHALLENGE_PREFIX];
-		key.append(&mut user_pubkey.to_vec());
-		let challenge_value = Challenge { challenge };
-		batch.put_ser(&key, &challenge_value)?;
+		key.append(&mut challenge.challenge.to_vec());
+		batch.put_ser(&key, &challenge)?;
 		batch.commit()?;
 		Ok(challenge)
 	}
 
-	// validate the challenge and generate a token, store it, and return it.
+	// take a challenge issued by create_auth_challenge. A challenge can only be taken once.
+	// Returns None if it was not issued for the server or has expired.
+	pub fn consume_challenge(
+		&self,
+		challenge: [u8; 32],
+		server_pubkey: [u8; 32],
+	) -> Result<Option<Challenge>, Error> {
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+		let batch = self.store.batch()?;
+		let mut key = vec![CHALLENGE_PREFIX];
+		key.append(&mut challenge.to_vec());
+		let stored: Option<Challenge> = batch.get_ser(&key)?;
+		let stored = match stored {
+			Some(stored) => stored,
+			None => return Ok(None),
+		};
+		batch.delete(&key)?;
+		batch.commit()?;
+
+		if stored.server_pubkey != server_pubkey || stored.expired(time_now) {
+			Ok(None)
+		} else {
+			Ok(Some(stored))
+		}
+	}
+
+	// take the challenge and generate a token for the user, store it, and return it.
 	pub fn validate_challenge(
 		&self,
 		user_pubkey: [u8; 32],
-		_server_pubkey: [u8; 32],
-		challenge: [u8; 8],
+		server_pubkey: [u8; 32],
+		challenge: [u8; 32],
 		expiration_millis: u128,
 		roles: u128,
+		device_label: String,
 	) -> Result<Option<String>, Error> {
-		let batch = self.store.batch()?;
-		let mut key = vec![CHALLENGE_PREFIX];
-		key.append(&mut user_pubkey.to_vec());
-		let challenge_stored: Option<Challenge> = batch.get_ser(&key)?;
-
-		match challenge_stored {
-			Some(challenge_stored) => {
-				let challenge_stored = challenge_stored.challenge;
-				for i in 0..challenge_stored.len() {
-					if challenge_stored[i] != challenge[i] {
-						return Ok(None);
-					}
-				}
-
-				// generate and store token
-				let token: u128 = rand::random();
+		match self.consume_challenge(challenge, server_pubkey)? {
+			Some(_) => {
 				let creation_time = std::time::SystemTime::now()
 					.duration_since(std::time::UNIX_EPOCH)?
 					.as_millis();
+				let batch = self.store.batch()?;
+
+				// generate and store token
+				let token: u128 = rand::random();
 				let auth_info = AuthInfo {
 					creation_time,
 					last_access_time: creation_time,
 					expiration_millis,
+					device_label,
+					roles,
 				};
 				let mut auth_key = vec![TOKEN_PREFIX];
 				auth_key.append(&mut user_pubkey.to_vec());
@@ -2182,11 +4368,12 @@ impl DSContext {
 		let auth_info: Option<AuthInfo> = batch.get_ser(&auth_key)?;
 
 		match auth_info {
-			Some(auth_info) => {
+			Some(mut auth_info) => {
 				let time_now = std::time::SystemTime::now()
 					.duration_since(std::time::UNIX_EPOCH)?
 					.as_millis();
-				if time_now - auth_info.last_access_time > auth_info.expiration_millis {
+				if time_now.saturating_sub(auth_info.last_access_time) > auth_info.expiration_millis
+				{
 					Err(ErrorKind::NotAuthorized("invalid token - expired".to_string()).into())
 				} else {
 					let member = self.get_member(user_pubkey, server_id, server_pubkey, &batch)?;
@@ -2194,6 +4381,10 @@ impl DSContext {
 					match member {
 						Some(member) => {
 							if (member.roles & requested_roles) != 0 {
+								// sliding expiry, every use extends the session
+								auth_info.last_access_time = time_now;
+								batch.put_ser(&auth_key, &auth_info)?;
+								batch.commit()?;
 								Ok(())
 							} else {
 								Err(ErrorKind::NotAuthorized(format!(
@@ -2215,6 +4406,198 @@ impl DSContext {
 		}
 	}
 
+	// check a session token of the user and extend the session. Returns false if the token
+	// does not exist or has expired.
+	pub fn touch_session(&self, user_pubkey: [u8; 32], token: u128) -> Result<bool, Error> {
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+		let batch = self.store.batch()?;
+		let mut auth_key = vec![TOKEN_PREFIX];
+		auth_key.append(&mut user_pubkey.to_vec());
+		auth_key.append(&mut token.to_be_bytes().to_vec());
+
+		let auth_info: Option<AuthInfo> = batch.get_ser(&auth_key)?;
+		match auth_info {
+			Some(mut auth_info) => {
+				if time_now.saturating_sub(auth_info.last_access_time) > auth_info.expiration_millis
+				{
+					return Ok(false);
+				}
+				auth_info.last_access_time = time_now;
+				batch.put_ser(&auth_key, &auth_info)?;
+				batch.commit()?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	// returns the unexpired sessions of the user, most recently used first.
+	pub fn list_sessions(&self, user_pubkey: [u8; 32]) -> Result<Vec<SessionInfo>, Error> {
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+		let batch = self.store.batch()?;
+		let mut prefix = vec![TOKEN_PREFIX];
+		prefix.append(&mut user_pubkey.to_vec());
+		let mut itt = batch.iter(&prefix, |k, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			Ok((k.to_vec(), AuthInfo::read(&mut reader)?))
+		})?;
+
+		let mut ret = vec![];
+		loop {
+			match itt.next() {
+				Some((k, auth_info)) => {
+					if time_now.saturating_sub(auth_info.last_access_time)
+						> auth_info.expiration_millis
+					{
+						continue;
+					}
+					let token = u128::from_be_bytes(k[k.len() - 16..].try_into()?);
+					ret.push(SessionInfo {
+						session_id: session_id(token),
+						creation_time: auth_info.creation_time,
+						last_access_time: auth_info.last_access_time,
+						expiration_millis: auth_info.expiration_millis,
+						device_label: auth_info.device_label,
+					});
+				}
+				None => break,
+			}
+		}
+		ret.sort_by_key(|s| std::cmp::Reverse(s.last_access_time));
+		Ok(ret)
+	}
+
+	// delete a session of the user. Returns the revoked token so that connections using it
+	// can be closed, or None if the user has no such session.
+	pub fn revoke_session(
+		&self,
+		user_pubkey: [u8; 32],
+		session_id_to_revoke: u64,
+	) -> Result<Option<u128>, Error> {
+		let batch = self.store.batch()?;
+		let mut prefix = vec![TOKEN_PREFIX];
+		prefix.append(&mut user_pubkey.to_vec());
+		let mut itt = batch.iter(&prefix, |k, _| Ok(k.to_vec()))?;
+
+		let mut revoked = None;
+		loop {
+			match itt.next() {
+				Some(k) => {
+					let token = u128::from_be_bytes(k[k.len() - 16..].try_into()?);
+					if session_id(token) == session_id_to_revoke {
+						revoked = Some((k, token));
+						break;
+					}
+				}
+				None => break,
+			}
+		}
+
+		match revoked {
+			Some((k, token)) => {
+				batch.delete(&k)?;
+				batch.commit()?;
+				Ok(Some(token))
+			}
+			None => Ok(None),
+		}
+	}
+
+	// record that a device key may no longer act as its user. The revocation must be signed
+	// by the primary key of the user. Only certificates issued at or before the time of the
+	// revocation are revoked, so the device can be linked again with a newer certificate.
+	pub fn revoke_device(&self, revocation: &DeviceRevocation) -> Result<(), Error> {
+		revocation.verify()?;
+		let batch = self.store.batch()?;
+		let key = device_revocation_key(
+			revocation.user_pubkey.to_bytes(),
+			revocation.device_pubkey.to_bytes(),
+		);
+
+		// an older revocation never narrows a newer one
+		let existing: Option<DeviceRevocation> = batch.get_ser(&key)?;
+		if let Some(existing) = existing {
+			if existing.timestamp >= revocation.timestamp {
+				return Ok(());
+			}
+		}
+
+		batch.put_ser(&key, revocation)?;
+		batch.commit()?;
+		Ok(())
+	}
+
+	// returns true if a certificate issued for the device at certificate_timestamp has been
+	// revoked. A certificate dated further in the future than CERTIFICATE_MAX_SKEW would
+	// outlive any revocation issued before that time, so it is treated as revoked.
+	pub fn is_device_revoked(
+		&self,
+		user_pubkey: [u8; 32],
+		device_pubkey: [u8; 32],
+		certificate_timestamp: u128,
+	) -> Result<bool, Error> {
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+		if certificate_timestamp > time_now.saturating_add(CERTIFICATE_MAX_SKEW) {
+			return Ok(true);
+		}
+
+		let batch = self.store.batch()?;
+		let revocation: Option<DeviceRevocation> =
+			batch.get_ser(&device_revocation_key(user_pubkey, device_pubkey))?;
+		Ok(match revocation {
+			Some(revocation) => certificate_timestamp <= revocation.timestamp,
+			None => false,
+		})
+	}
+
+	// returns the revocations recorded for the devices of a user.
+	pub fn list_device_revocations(
+		&self,
+		user_pubkey: [u8; 32],
+	) -> Result<Vec<DeviceRevocation>, Error> {
+		let batch = self.store.batch()?;
+		let mut prefix = vec![DEVICE_REVOCATION_PREFIX];
+		prefix.append(&mut user_pubkey.to_vec());
+		let mut itt = batch.iter(&prefix, |_, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			DeviceRevocation::read(&mut reader)
+		})?;
+
+		let mut ret = vec![];
+		loop {
+			match itt.next() {
+				Some(revocation) => ret.push(revocation),
+				None => break,
+			}
+		}
+		Ok(ret)
+	}
+
+	// check that a device certificate is validly signed and that it has not been revoked.
+	pub fn check_device_certificate(
+		&self,
+		device_certificate: &DeviceCertificate,
+	) -> Result<bool, Error> {
+		if device_certificate.verify().is_err() {
+			return Ok(false);
+		}
+		Ok(!self.is_device_revoked(
+			device_certificate.user_pubkey.to_bytes(),
+			device_certificate.device_pubkey.to_bytes(),
+			device_certificate.timestamp,
+		)?)
+	}
+
 	// purge any expired tokens
 	pub fn purge_tokens(&self) -> Result<(), Error> {
 		let time_now = std::time::SystemTime::now()
@@ -2233,7 +4616,9 @@ impl DSContext {
 		loop {
 			match itt.next() {
 				Some((k, auth_info)) => {
-					if time_now - auth_info.last_access_time > auth_info.expiration_millis {
+					if time_now.saturating_sub(auth_info.last_access_time)
+						> auth_info.expiration_millis
+					{
 						batch.delete(&k)?;
 					}
 				}
@@ -2241,6 +4626,25 @@ impl DSContext {
 			}
 		}
 
+		// challenges that were never answered. Challenges stored in an older format can't be
+		// answered anymore either.
+		let mut itt = batch.iter(&(vec![CHALLENGE_PREFIX])[..], |k, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			Ok((k.to_vec(), Challenge::read(&mut reader).ok()))
+		})?;
+
+		loop {
+			match itt.next() {
+				Some((k, challenge)) => match challenge {
+					Some(challenge) if !challenge.expired(time_now) => {}
+					_ => batch.delete(&k)?,
+				},
+				None => break,
+			}
+		}
+
 		batch.commit()?;
 
 		Ok(())
@@ -2347,12 +4751,77 @@ impl DSContext {
 		};
 		let mut profile_key_buffer = vec![];
 		serialize_default(&mut profile_key_buffer, &profile_key)?;
+
+		// update the name index, removing the entry for the previous name
+		let prev: Option<ProfileData> = batch.get_ser(&profile_key_buffer)?;
+		match prev {
+			Some(prev) => {
+				let _ = batch.delete(&member_name_key(&profile_key, &prev.user_name.to_string())?);
+			}
+			None => {}
+		}
+		batch.put_ser(
+			&member_name_key(&profile_key, &profile_data.user_name.to_string())?,
+			&0u8,
+		)?;
+
 		let mut profile_data_buffer = vec![];
 		serialize_default(&mut profile_data_buffer, &profile_data)?;
 		batch.put_ser(&profile_key_buffer, &profile_data_buffer)?;
 		Ok(())
 	}
 
+	// add the profiles saved before the member name index existed to the index. This only
+	// runs once, later profile changes keep the index up to date.
+	pub fn index_member_names(&self) -> Result<(), Error> {
+		let batch = self.store.batch()?;
+		let done: Option<u8> = batch.get_ser(&[MEMBER_NAME_MIGRATION])?;
+		if done.is_some() {
+			return Ok(());
+		}
+
+		let profiles: Vec<(ProfileKey, ProfileData)> = {
+			let mut itt = batch.iter(&[PROFILE_PREFIX], |k, v| {
+				let mut cursor = Cursor::new(k.to_vec());
+				cursor.set_position(0);
+				let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+				let profile_key = ProfileKey::read(&mut reader);
+
+				let mut cursor = Cursor::new(v.to_vec());
+				cursor.set_position(0);
+				let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+				let profile_data = ProfileData::read(&mut reader);
+
+				match (profile_key, profile_data) {
+					(Ok(profile_key), Ok(profile_data)) => Ok(Some((profile_key, profile_data))),
+					_ => Ok(None),
+				}
+			})?;
+
+			let mut profiles = vec![];
+			loop {
+				match itt.next() {
+					Some(Some(profile)) => profiles.push(profile),
+					Some(None) => warn!("skipping unreadable profile while indexing names"),
+					None => break,
+				}
+			}
+			profiles
+		};
+
+		info!("indexing the names of {} profiles", profiles.len());
+		for (profile_key, profile_data) in profiles {
+			batch.put_ser(
+				&member_name_key(&profile_key, &profile_data.user_name.to_string())?,
+				&0u8,
+			)?;
+		}
+
+		batch.put_ser(&[MEMBER_NAME_MIGRATION], &0u8)?;
+		batch.commit()?;
+		Ok(())
+	}
+
 	/*pub fn get_profile(
 		&self,
 		user_pubkey: [u8; 32],
@@ -2551,20 +5020,64 @@ impl DSContext {
 		}
 	}*/
 
-	pub fn save_ws_auth_token(&self, token: u128) -> Result<(), Error> {
+	// delete the sessions of the user that were created with the instance owner auth token,
+	// and those saved before sessions had roles, which never expired. Returns the revoked
+	// tokens so that connections using them can be closed.
+	pub fn revoke_owner_sessions(&self, user_pubkey: [u8; 32]) -> Result<Vec<u128>, Error> {
+		let batch = self.store.batch()?;
+		let mut prefix = vec![TOKEN_PREFIX];
+		prefix.append(&mut user_pubkey.to_vec());
+		let mut itt = batch.iter(&prefix, |k, v| {
+			let mut cursor = Cursor::new(v.to_vec());
+			cursor.set_position(0);
+			let mut reader = BinReader::new(&mut cursor, ProtocolVersion::local());
+			Ok((k.to_vec(), AuthInfo::read(&mut reader).ok()))
+		})?;
+
+		let mut revoked = vec![];
+		loop {
+			match itt.next() {
+				Some((k, Some(auth_info))) => {
+					if (auth_info.roles & AUTH_FLAG_OWNER) != 0
+						|| auth_info.expiration_millis == u128::MAX
+					{
+						batch.delete(&k)?;
+						revoked.push(u128::from_be_bytes(k[k.len() - 16..].try_into()?));
+					}
+				}
+				Some((_, None)) => {}
+				None => break,
+			}
+		}
+		batch.commit()?;
+		Ok(revoked)
+	}
+
+	// save the instance owner auth token. Any previously saved token is invalidated. If
+	// hashed is true, only the hash of the token is stored.
+	pub fn save_ws_auth_token(&self, token: u128, hashed: bool) -> Result<(), Error> {
 		let batch = self.store.batch()?;
-		let mut key_buffer = vec![];
-		serialize_default(&mut key_buffer, &WSAuthToken { token })?;
-		batch.put_ser(&key_buffer, &0u8)?;
+		let mut itt = batch.iter(&(vec![WS_AUTH_TOKEN])[..], |k, _v| Ok(k.to_vec()))?;
+
+		loop {
+			match itt.next() {
+				Some(k) => batch.delete(&k)?,
+				None => break,
+			}
+		}
+
+		batch.put_ser(&ws_auth_token_key(token, hashed)?, &0u8)?;
 		batch.commit()?;
 		Ok(())
 	}
 
 	pub fn check_ws_auth_token(&self, token: u128) -> Result<bool, Error> {
 		let batch = self.store.batch()?;
-		let mut key_buffer = vec![];
-		serialize_default(&mut key_buffer, &WSAuthToken { token })?;
-		let v: Option<u8> = batch.get_ser(&key_buffer)?;
+		let v: Option<u8> = batch.get_ser(&ws_auth_token_key(token, false)?)?;
+		if v.is_some() {
+			return Ok(true);
+		}
+		let v: Option<u8> = batch.get_ser(&ws_auth_token_key(token, true)?)?;
 		Ok(v.is_some())
 	}
 
@@ -2583,3 +5096,1228 @@ impl DSContext {
 		Ok(DSContext { store })
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::ser::deserialize_default;
+	use ed25519_dalek::{ExpandedSecretKey, SecretKey};
+
+	fn test_context() -> Result<(tempfile::TempDir, DSContext), Error> {
+		let dir = tempfile::tempdir()?;
+		let ds_context = DSContext::new(dir.path().display().to_string())?;
+		Ok((dir, ds_context))
+	}
+
+	fn test_server_info() -> ServerInfo {
+		ServerInfo {
+			pubkey: [1u8; 32],
+			name: "test".to_string(),
+			joined: true,
+			seqno: 5,
+			description: "description".to_string(),
+			topic: "topic".to_string(),
+			creation_time: 1234,
+			default_channel: 9,
+			rules: "rules".to_string(),
+			verification_level: 2,
+			require_approval: true,
+			pow_difficulty: 7,
+		}
+	}
+
+	// add a server owned by its host, returns (server_pubkey, server_id)
+	fn test_server(ds_context: &DSContext) -> Result<([u8; 32], [u8; 8]), Error> {
+		let mut server_info = test_server_info();
+		// anyone with a live invite joins right away
+		server_info.require_approval = false;
+		server_info.pow_difficulty = 0;
+		let server_pubkey = server_info.pubkey;
+		let server_id = ds_context.add_server(server_info, None, None, false)?;
+		Ok((server_pubkey, server_id))
+	}
+
+	#[test]
+	fn test_server_info_versions() -> Result<(), Error> {
+		let mut buf = vec![];
+		serialize_default(&mut buf, &test_server_info())?;
+		// the version byte follows pubkey, name, joined and seqno
+		let version_offset = 32 + 4 + "test".len() + 1 + 8;
+		assert_eq!(buf[version_offset], 3);
+
+		let info: ServerInfo = deserialize_default(&mut &buf[..])?;
+		assert_eq!(info.seqno, 5);
+		assert_eq!(info.description, "description");
+		assert_eq!(info.topic, "topic");
+		assert_eq!(info.creation_time, 1234);
+		assert_eq!(info.default_channel, 9);
+		assert_eq!(info.rules, "rules");
+		assert_eq!(info.verification_level, 2);
+		assert!(info.require_approval);
+		assert_eq!(info.pow_difficulty, 7);
+
+		// version 2 has no proof of work
+		let mut v2 = buf[..buf.len() - 1].to_vec();
+		v2[version_offset] = 2;
+		let info: ServerInfo = deserialize_default(&mut &v2[..])?;
+		assert!(info.require_approval);
+		assert_eq!(info.pow_difficulty, 0);
+
+		// version 1 has no approval setting either
+		let mut v1 = buf[..buf.len() - 2].to_vec();
+		v1[version_offset] = 1;
+		let info: ServerInfo = deserialize_default(&mut &v1[..])?;
+		assert_eq!(info.rules, "rules");
+		assert_eq!(info.verification_level, 2);
+		assert!(!info.require_approval);
+		assert_eq!(info.pow_difficulty, 0);
+
+		// servers saved before the version byte existed
+		let legacy = buf[..version_offset].to_vec();
+		let info: ServerInfo = deserialize_default(&mut &legacy[..])?;
+		assert_eq!(info.name, "test");
+		assert!(info.joined);
+		assert_eq!(info.seqno, 5);
+		assert_eq!(info.description, "");
+		assert_eq!(info.creation_time, 0);
+		assert_eq!(info.default_channel, 0);
+		assert!(!info.require_approval);
+
+		let mut unknown = buf.clone();
+		unknown[version_offset] = 9;
+		assert!(deserialize_default::<ServerInfo, _>(&mut &unknown[..]).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_follow_requires_view() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		let channel_id =
+			ds_context.add_channel(server_id, server_pubkey, "news".to_string(), "".to_string())?;
+		assert!(ds_context.set_channel_kind(
+			server_pubkey,
+			server_id,
+			channel_id,
+			CHANNEL_KIND_ANNOUNCEMENT,
+			0
+		)?);
+		let source = ChannelKey {
+			server_pubkey,
+			server_id,
+			channel_id,
+		};
+		let follower = ChannelKey {
+			server_pubkey: [2u8; 32],
+			server_id: [3u8; 8],
+			channel_id: 4,
+		};
+
+		// hide the channel from everyone but the owner
+		assert!(ds_context.set_channel_overwrites(
+			server_pubkey,
+			server_id,
+			channel_id,
+			vec![PermissionOverwrite {
+				target: OverwriteTarget::Role(AUTH_FLAG_MEMBER),
+				allow: 0,
+				deny: CHANNEL_PERMISSION_VIEW,
+			}],
+		)?);
+
+		// another host and a user that is not a member are denied
+		assert!(!ds_context.add_follower(&source, &follower, None)?);
+		assert!(!ds_context.add_follower(&source, &follower, Some([5u8; 32]))?);
+		assert!(ds_context.get_followers(&source)?.is_empty());
+
+		// the owner can still view it
+		assert!(ds_context.add_follower(&source, &follower, Some(server_pubkey))?);
+		assert_eq!(ds_context.get_followers(&source)?.len(), 1);
+
+		// once visible to everyone, another host may follow it
+		ds_context.remove_follower(&source, &follower)?;
+		assert!(ds_context.set_channel_overwrites(server_pubkey, server_id, channel_id, vec![])?);
+		assert!(ds_context.add_follower(&source, &follower, None)?);
+		assert_eq!(ds_context.get_followers(&source)?.len(), 1);
+
+		Ok(())
+	}
+
+	fn accept_test_invite(
+		ds_context: &DSContext,
+		invite_id: u128,
+		user_pubkey: [u8; 32],
+		server_pubkey: [u8; 32],
+	) -> Result<AcceptInviteResult, Error> {
+		ds_context.accept_invite(
+			invite_id,
+			user_pubkey,
+			server_pubkey,
+			"user".to_string(),
+			"".to_string(),
+			vec![],
+			0,
+		)
+	}
+
+	#[test]
+	fn test_invite_expiry() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+
+		// an expired invite is rejected
+		let invite_id =
+			ds_context.create_invite(server_pubkey, server_id, time_now - 1000, 10, 0, 0)?;
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [20u8; 32], server_pubkey)?,
+			AcceptInviteResult::Rejected
+		));
+
+		// a live invite can only be accepted max times
+		let invite_id =
+			ds_context.create_invite(server_pubkey, server_id, time_now + 60_000, 1, 0, 0)?;
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [21u8; 32], server_pubkey)?,
+			AcceptInviteResult::Joined(_, 0)
+		));
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [22u8; 32], server_pubkey)?,
+			AcceptInviteResult::Rejected
+		));
+
+		// an expiry of 0 never expires
+		let invite_id = ds_context.create_invite(server_pubkey, server_id, 0, 10, 0, 0)?;
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [23u8; 32], server_pubkey)?,
+			AcceptInviteResult::Joined(_, 0)
+		));
+
+		let invite = Invite {
+			inviter: server_pubkey,
+			server_id,
+			expiry: 1000,
+			cur: 0,
+			max: 2,
+			id: 1,
+			roles: 0,
+			landing_channel: 0,
+		};
+		assert!(invite.is_live(999));
+		assert!(!invite.is_live(1000));
+		Ok(())
+	}
+
+	#[test]
+	fn test_reject_join_request() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let mut server_info = test_server_info();
+		server_info.pow_difficulty = 0;
+		let server_pubkey = server_info.pubkey;
+		let server_id = ds_context.add_server(server_info, None, None, false)?;
+		let invite_id = ds_context.create_invite(server_pubkey, server_id, 0, 1, 0, 0)?;
+
+		// the pending request holds the only use of the invite
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [20u8; 32], server_pubkey)?,
+			AcceptInviteResult::Pending
+		));
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [21u8; 32], server_pubkey)?,
+			AcceptInviteResult::Rejected
+		));
+
+		// rejecting the request gives the use back
+		assert!(ds_context.reject_join_request(server_pubkey, server_id, [20u8; 32])?);
+		assert!(!ds_context.reject_join_request(server_pubkey, server_id, [20u8; 32])?);
+		assert_eq!(ds_context.get_invite(invite_id)?.unwrap().cur, 0);
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [21u8; 32], server_pubkey)?,
+			AcceptInviteResult::Pending
+		));
+
+		// an approved request keeps it
+		assert!(ds_context
+			.approve_join_request(server_pubkey, server_id, [21u8; 32])?
+			.is_some());
+		assert_eq!(ds_context.get_invite(invite_id)?.unwrap().cur, 1);
+		assert!(ds_context
+			.list_join_requests(server_pubkey, server_id)?
+			.is_empty());
+		Ok(())
+	}
+
+	#[test]
+	fn test_member_accepts_invite() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		let invite_id = ds_context.create_invite(server_pubkey, server_id, 0, 2, 0, 0)?;
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [20u8; 32], server_pubkey)?,
+			AcceptInviteResult::Joined(_, 0)
+		));
+		assert_eq!(ds_context.get_invite(invite_id)?.unwrap().cur, 1);
+		let count = ds_context.member_count(server_pubkey, server_id)?;
+
+		// joining again is a no-op that doesn't use up the invite
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [20u8; 32], server_pubkey)?,
+			AcceptInviteResult::Joined(_, 0)
+		));
+		assert_eq!(ds_context.get_invite(invite_id)?.unwrap().cur, 1);
+		assert_eq!(ds_context.member_count(server_pubkey, server_id)?, count);
+
+		// so there is still a use left for someone new
+		assert!(matches!(
+			accept_test_invite(&ds_context, invite_id, [21u8; 32], server_pubkey)?,
+			AcceptInviteResult::Joined(_, 0)
+		));
+		assert_eq!(ds_context.get_invite(invite_id)?.unwrap().cur, 2);
+		Ok(())
+	}
+
+	#[test]
+	fn test_member_value_versions() -> Result<(), Error> {
+		let member_value = MemberValueImpl {
+			roles: AUTH_FLAG_MEMBER | AUTH_FLAG_MODERATOR,
+			join_time: 100,
+			modified_time: 200,
+			batch_num: 3,
+			inviter: Some(Pubkey::from_bytes([8u8; 32])),
+		};
+		let mut buf = vec![];
+		serialize_default(&mut buf, &member_value)?;
+		let read: MemberValueImpl = deserialize_default(&mut &buf[..])?;
+		assert_eq!(read.roles, AUTH_FLAG_MEMBER | AUTH_FLAG_MODERATOR);
+		assert_eq!(read.join_time, 100);
+		assert_eq!(read.modified_time, 200);
+		assert_eq!(read.batch_num, 3);
+		assert_eq!(read.inviter, Some(Pubkey::from_bytes([8u8; 32])));
+
+		// members saved before the inviter was recorded
+		let version_offset = 16 + 8 + 8 + 8;
+		assert_eq!(buf[version_offset], 1);
+		let legacy = buf[..version_offset].to_vec();
+		let read: MemberValueImpl = deserialize_default(&mut &legacy[..])?;
+		assert_eq!(read.roles, AUTH_FLAG_MEMBER | AUTH_FLAG_MODERATOR);
+		assert_eq!(read.batch_num, 3);
+		assert_eq!(read.inviter, None);
+
+		let mut unknown = buf.clone();
+		unknown[version_offset] = 9;
+		assert!(deserialize_default::<MemberValueImpl, _>(&mut &unknown[..]).is_err());
+		Ok(())
+	}
+
+	fn test_channel_value(overwrites: Vec<PermissionOverwrite>) -> Channel {
+		Channel {
+			name: "news".to_string(),
+			description: "description".to_string(),
+			channel_id: 11,
+			position: 3,
+			category_id: 4,
+			overwrites,
+			kind: CHANNEL_KIND_ANNOUNCEMENT,
+			post_roles: AUTH_FLAG_OWNER,
+			archived: true,
+		}
+	}
+
+	#[test]
+	fn test_channel_versions() -> Result<(), Error> {
+		let overwrites = vec![
+			PermissionOverwrite {
+				target: OverwriteTarget::Role(AUTH_FLAG_MEMBER),
+				allow: 0,
+				deny: CHANNEL_PERMISSION_SEND,
+			},
+			PermissionOverwrite {
+				target: OverwriteTarget::User([6u8; 32]),
+				allow: CHANNEL_PERMISSION_MANAGE,
+				deny: 0,
+			},
+		];
+		let mut buf = vec![];
+		serialize_default(&mut buf, &test_channel_value(overwrites.clone()))?;
+		let channel: Channel = deserialize_default(&mut &buf[..])?;
+		assert_eq!(
+			format!("{:?}", channel),
+			format!("{:?}", test_channel_value(overwrites))
+		);
+
+		// without overwrites the versioned fields have a fixed size
+		let mut buf = vec![];
+		serialize_default(&mut buf, &test_channel_value(vec![]))?;
+		// the version byte follows name, description and channel_id
+		let version_offset = 4 + "news".len() + 4 + "description".len() + 8;
+		assert_eq!(buf[version_offset], 4);
+
+		// version 3 can't be archived
+		let mut v3 = buf[..buf.len() - 1].to_vec();
+		v3[version_offset] = 3;
+		let channel: Channel = deserialize_default(&mut &v3[..])?;
+		assert_eq!(channel.kind, CHANNEL_KIND_ANNOUNCEMENT);
+		assert_eq!(channel.post_roles, AUTH_FLAG_OWNER);
+		assert!(!channel.archived);
+
+		// version 2 has no kind
+		let mut v2 = buf[..buf.len() - 18].to_vec();
+		v2[version_offset] = 2;
+		let channel: Channel = deserialize_default(&mut &v2[..])?;
+		assert_eq!(channel.position, 3);
+		assert_eq!(channel.kind, CHANNEL_KIND_TEXT);
+		assert_eq!(channel.post_roles, 0);
+
+		// version 1 has no overwrites
+		let mut v1 = buf[..version_offset + 17].to_vec();
+		v1[version_offset] = 1;
+		let channel: Channel = deserialize_default(&mut &v1[..])?;
+		assert_eq!(channel.position, 3);
+		assert_eq!(channel.category_id, 4);
+		assert!(channel.overwrites.is_empty());
+		assert_eq!(channel.kind, CHANNEL_KIND_TEXT);
+
+		// channels saved before the version byte existed
+		let legacy = buf[..version_offset].to_vec();
+		let channel: Channel = deserialize_default(&mut &legacy[..])?;
+		assert_eq!(channel.name, "news");
+		assert_eq!(channel.channel_id, 11);
+		assert_eq!(channel.position, 0);
+		assert_eq!(channel.category_id, 0);
+		assert!(!channel.archived);
+
+		let mut unknown = buf.clone();
+		unknown[version_offset] = 9;
+		assert!(deserialize_default::<Channel, _>(&mut &unknown[..]).is_err());
+		Ok(())
+	}
+
+	fn test_channel(ds_context: &DSContext) -> Result<([u8; 32], [u8; 8], u64), Error> {
+		let (server_pubkey, server_id) = test_server(ds_context)?;
+		let channel_id =
+			ds_context.add_channel(server_id, server_pubkey, "test".to_string(), "".to_string())?;
+		Ok((server_pubkey, server_id, channel_id))
+	}
+
+	// post count messages, the text of every other one contains "even"
+	fn post_test_messages(
+		ds_context: &DSContext,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		channel_id: u64,
+		count: u64,
+	) -> Result<(), Error> {
+		for i in 0..count {
+			let text = match i % 2 == 0 {
+				true => format!("EVEN message {}", i),
+				false => format!("odd message {}", i),
+			};
+			ds_context.post_message(Message {
+				payload: text.as_bytes().to_vec(),
+				signature: [0u8; 64],
+				message_type: MessageType::Text,
+				server_pubkey,
+				server_id,
+				channel_id,
+				timestamp: i,
+				user_pubkey: server_pubkey,
+				nonce: 0,
+				seqno: 0,
+				user_name: "".to_string(),
+				user_bio: "".to_string(),
+				device_certificate: None,
+			})?;
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn test_export_messages_pages() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id, channel_id) = test_channel(&ds_context)?;
+		post_test_messages(&ds_context, server_pubkey, server_id, channel_id, 250)?;
+
+		let mut exported = vec![];
+		let mut cursor = None;
+		let mut pages = 0;
+		loop {
+			let (messages, next_cursor) =
+				ds_context.export_messages(server_pubkey, server_id, channel_id, cursor)?;
+			assert!(messages.len() as u64 <= MESSAGE_BATCH_SIZE);
+			exported.extend(messages);
+			pages += 1;
+			cursor = match next_cursor {
+				Some(next_cursor) => Some(next_cursor),
+				None => break,
+			};
+		}
+
+		assert_eq!(pages, 3);
+		assert_eq!(exported.len(), 250);
+		for (i, message) in exported.iter().enumerate() {
+			assert_eq!(message.seqno, i as u64);
+			assert_eq!(message.timestamp, i as u64);
+		}
+
+		// a malformed cursor is rejected
+		let cursor = PageCursor {
+			data: vec![1, 2, 3],
+		};
+		assert!(ds_context
+			.export_messages(server_pubkey, server_id, channel_id, Some(cursor))
+			.is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_search_messages_pages() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id, channel_id) = test_channel(&ds_context)?;
+		post_test_messages(&ds_context, server_pubkey, server_id, channel_id, 250)?;
+
+		let (first, cursor) =
+			ds_context.search_messages(server_pubkey, server_id, channel_id, "even", None)?;
+		assert_eq!(first.len(), MAX_SEARCH_RESULTS);
+		assert!(cursor.is_some());
+		let (second, cursor) =
+			ds_context.search_messages(server_pubkey, server_id, channel_id, "even", cursor)?;
+		assert_eq!(second.len(), 25);
+		assert!(cursor.is_none());
+
+		// most recent first with no repeats or gaps across the pages
+		let timestamps: Vec<u64> = first
+			.iter()
+			.chain(second.iter())
+			.map(|m| m.timestamp)
+			.collect();
+		let expected: Vec<u64> = (0..250).rev().filter(|i| i % 2 == 0).collect();
+		assert_eq!(timestamps, expected);
+		Ok(())
+	}
+
+	#[test]
+	fn test_resume_channel_delete() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id, channel_id) = test_channel(&ds_context)?;
+		post_test_messages(&ds_context, server_pubkey, server_id, channel_id, 10)?;
+
+		// simulate being interrupted after the tombstone was committed
+		let tombstone_key = channel_delete_key(server_pubkey, server_id, channel_id);
+		let batch = ds_context.store.batch()?;
+		batch.put_ser(&tombstone_key, &0u8)?;
+		batch.commit()?;
+
+		ds_context.resume_channel_deletes()?;
+
+		{
+			let batch = ds_context.store.batch()?;
+			let prefix = message_prefix(server_pubkey, server_id, channel_id);
+			assert_eq!(batch.iter(&prefix, |k, _| Ok(k.to_vec()))?.count(), 0);
+			let tombstone: Option<u8> = batch.get_ser(&tombstone_key)?;
+			assert!(tombstone.is_none());
+		}
+
+		// a completed delete leaves no tombstone behind
+		let (server_pubkey, server_id, channel_id) = test_channel(&ds_context)?;
+		post_test_messages(&ds_context, server_pubkey, server_id, channel_id, 10)?;
+		ds_context.delete_channel(server_id, server_pubkey, channel_id)?;
+		let batch = ds_context.store.batch()?;
+		let tombstone: Option<u8> =
+			batch.get_ser(&channel_delete_key(server_pubkey, server_id, channel_id))?;
+		assert!(tombstone.is_none());
+		let prefix = message_prefix(server_pubkey, server_id, channel_id);
+		assert_eq!(batch.iter(&prefix, |k, _| Ok(k.to_vec()))?.count(), 0);
+		Ok(())
+	}
+
+	#[test]
+	fn test_resume_server_delete() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id, channel_id) = test_channel(&ds_context)?;
+		post_test_messages(&ds_context, server_pubkey, server_id, channel_id, 10)?;
+
+		// simulate being interrupted after the tombstone was committed
+		let mut tombstone_key = vec![SERVER_DELETE_PREFIX];
+		tombstone_key.append(&mut server_pubkey.to_vec());
+		tombstone_key.append(&mut server_id.to_vec());
+		let batch = ds_context.store.batch()?;
+		batch.put_ser(&tombstone_key, &0u8)?;
+		batch.commit()?;
+
+		assert_eq!(
+			ds_context.resume_server_deletes()?,
+			vec![(server_id, server_pubkey)]
+		);
+		assert!(ds_context
+			.get_channels(server_pubkey, server_id)?
+			.is_empty());
+		let batch = ds_context.store.batch()?;
+		let prefix = message_prefix(server_pubkey, server_id, channel_id);
+		assert_eq!(batch.iter(&prefix, |k, _| Ok(k.to_vec()))?.count(), 0);
+		let tombstone: Option<u8> = batch.get_ser(&tombstone_key)?;
+		assert!(tombstone.is_none());
+		Ok(())
+	}
+
+	#[test]
+	fn test_validate_challenge() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let user_pubkey = [30u8; 32];
+		let server_pubkey = [31u8; 32];
+		let validate = |challenge| {
+			ds_context.validate_challenge(
+				user_pubkey,
+				server_pubkey,
+				challenge,
+				TOKEN_EXPIRATION,
+				AUTH_FLAG_MEMBER,
+				"".to_string(),
+			)
+		};
+
+		// a challenge can only be answered once
+		let challenge = ds_context.create_auth_challenge(server_pubkey)?;
+		assert_eq!(challenge.server_pubkey, server_pubkey);
+		assert!(validate(challenge.challenge)?.is_some());
+		assert!(validate(challenge.challenge)?.is_none());
+
+		// an unknown challenge is rejected
+		let challenge = ds_context.create_auth_challenge(server_pubkey)?;
+		let mut wrong = challenge.challenge;
+		wrong[0] = wrong[0].wrapping_add(1);
+		assert!(validate(wrong)?.is_none());
+		assert!(validate(challenge.challenge)?.is_some());
+
+		// a challenge is bound to the server that issued it, and is used up by a wrong server
+		let challenge = ds_context.create_auth_challenge(server_pubkey)?;
+		assert!(ds_context
+			.consume_challenge(challenge.challenge, user_pubkey)?
+			.is_none());
+		assert!(validate(challenge.challenge)?.is_none());
+
+		// consuming returns the challenge as it was issued
+		let challenge = ds_context.create_auth_challenge(server_pubkey)?;
+		let consumed = ds_context
+			.consume_challenge(challenge.challenge, server_pubkey)?
+			.unwrap();
+		assert_eq!(consumed.timestamp, challenge.timestamp);
+		assert!(ds_context
+			.consume_challenge(challenge.challenge, server_pubkey)?
+			.is_none());
+
+		// an expired challenge is rejected
+		let challenge = Challenge {
+			challenge: [1u8; 32],
+			server_pubkey,
+			timestamp: 0,
+		};
+		let mut key = vec![CHALLENGE_PREFIX];
+		key.append(&mut challenge.challenge.to_vec());
+		let batch = ds_context.store.batch()?;
+		batch.put_ser(&key, &challenge)?;
+		batch.commit()?;
+		assert!(validate([1u8; 32])?.is_none());
+
+		// a truncated challenge does not read as a valid one
+		let mut buf = vec![];
+		serialize_default(&mut buf, &Challenge::new(server_pubkey)?)?;
+		assert_eq!(buf.len(), 32 + 32 + 16);
+		assert!(deserialize_default::<Challenge, _>(&mut &buf[..64][..]).is_err());
+		assert!(deserialize_default::<Challenge, _>(&mut &[1u8; 8][..]).is_err());
+
+		// expired and unreadable challenges are purged
+		let batch = ds_context.store.batch()?;
+		batch.put_ser(&key, &challenge)?;
+		let mut legacy_key = vec![CHALLENGE_PREFIX];
+		legacy_key.append(&mut user_pubkey.to_vec());
+		batch.put(&legacy_key, &[1u8; 8])?;
+		batch.commit()?;
+		let unanswered = ds_context.create_auth_challenge(server_pubkey)?;
+		ds_context.purge_tokens()?;
+		let batch = ds_context.store.batch()?;
+		let remaining = batch.iter(&[CHALLENGE_PREFIX], |k, _| Ok(k.to_vec()))?;
+		assert_eq!(remaining.count(), 1);
+		drop(batch);
+		assert!(validate(unanswered.challenge)?.is_some());
+
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+		assert!(challenge.expired(time_now));
+		assert!(!Challenge::new(server_pubkey)?.expired(time_now));
+		Ok(())
+	}
+
+	#[test]
+	fn test_revoke_session() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let user_pubkey = [31u8; 32];
+		let mut tokens = vec![];
+		for label in &["phone", "laptop"] {
+			let challenge = ds_context.create_auth_challenge(user_pubkey)?;
+			let token = ds_context
+				.validate_challenge(
+					user_pubkey,
+					user_pubkey,
+					challenge.challenge,
+					TOKEN_EXPIRATION,
+					AUTH_FLAG_MEMBER,
+					label.to_string(),
+				)?
+				.unwrap();
+			tokens.push(token.parse::<u128>()?);
+		}
+
+		let sessions = ds_context.list_sessions(user_pubkey)?;
+		assert_eq!(sessions.len(), 2);
+		assert!(ds_context.touch_session(user_pubkey, tokens[0])?);
+
+		// only the revoked session is removed, and only for its own user
+		let revoked = session_id(tokens[0]);
+		assert_eq!(ds_context.revoke_session([32u8; 32], revoked)?, None);
+		assert_eq!(
+			ds_context.revoke_session(user_pubkey, revoked)?,
+			Some(tokens[0])
+		);
+		assert_eq!(ds_context.revoke_session(user_pubkey, revoked)?, None);
+		assert!(!ds_context.touch_session(user_pubkey, tokens[0])?);
+		assert!(ds_context.touch_session(user_pubkey, tokens[1])?);
+
+		let sessions = ds_context.list_sessions(user_pubkey)?;
+		assert_eq!(sessions.len(), 1);
+		assert_eq!(sessions[0].session_id, session_id(tokens[1]));
+		assert_eq!(sessions[0].device_label, "laptop");
+		Ok(())
+	}
+
+	#[test]
+	fn test_revoke_owner_sessions() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let user_pubkey = [31u8; 32];
+		let mut tokens = vec![];
+		for roles in &[AUTH_FLAG_OWNER | AUTH_FLAG_MEMBER, AUTH_FLAG_MEMBER] {
+			let challenge = ds_context.create_auth_challenge(user_pubkey)?;
+			let token = ds_context
+				.validate_challenge(
+					user_pubkey,
+					user_pubkey,
+					challenge.challenge,
+					TOKEN_EXPIRATION,
+					*roles,
+					"".to_string(),
+				)?
+				.unwrap();
+			tokens.push(token.parse::<u128>()?);
+		}
+
+		// a session saved before sessions had roles, which never expired
+		let legacy_token = 1234u128;
+		{
+			let batch = ds_context.store.batch()?;
+			let auth_info = AuthInfo {
+				creation_time: 0,
+				last_access_time: 0,
+				expiration_millis: u128::MAX,
+				device_label: "".to_string(),
+				roles: 0,
+			};
+			let mut buf = vec![];
+			serialize_default(&mut buf, &auth_info)?;
+			let mut auth_key = vec![TOKEN_PREFIX];
+			auth_key.append(&mut user_pubkey.to_vec());
+			auth_key.append(&mut legacy_token.to_be_bytes().to_vec());
+			batch.put(&auth_key, &buf[..16 * 3])?;
+			batch.commit()?;
+		}
+
+		// only the sessions of the owner auth token are revoked
+		let mut revoked = ds_context.revoke_owner_sessions(user_pubkey)?;
+		revoked.sort();
+		let mut expected = vec![tokens[0], legacy_token];
+		expected.sort();
+		assert_eq!(revoked, expected);
+		assert!(!ds_context.touch_session(user_pubkey, tokens[0])?);
+		assert!(!ds_context.touch_session(user_pubkey, legacy_token)?);
+		assert!(ds_context.touch_session(user_pubkey, tokens[1])?);
+		assert_eq!(ds_context.revoke_owner_sessions(user_pubkey)?, vec![]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_auth_info_versions() -> Result<(), Error> {
+		let auth_info = AuthInfo {
+			creation_time: 100,
+			last_access_time: 200,
+			expiration_millis: 300,
+			device_label: "phone".to_string(),
+			roles: AUTH_FLAG_OWNER,
+		};
+		let mut buf = vec![];
+		serialize_default(&mut buf, &auth_info)?;
+		let read: AuthInfo = deserialize_default(&mut &buf[..])?;
+		assert_eq!(read.creation_time, 100);
+		assert_eq!(read.last_access_time, 200);
+		assert_eq!(read.expiration_millis, 300);
+		assert_eq!(read.device_label, "phone");
+		assert_eq!(read.roles, AUTH_FLAG_OWNER);
+
+		// tokens saved before sessions had labels
+		let version_offset = 16 * 3;
+		assert_eq!(buf[version_offset], 2);
+		let legacy = buf[..version_offset].to_vec();
+		let read: AuthInfo = deserialize_default(&mut &legacy[..])?;
+		assert_eq!(read.expiration_millis, 300);
+		assert_eq!(read.device_label, "");
+		assert_eq!(read.roles, 0);
+
+		// tokens saved before sessions had roles
+		let mut legacy = buf[..buf.len() - 16].to_vec();
+		legacy[version_offset] = 1;
+		let read: AuthInfo = deserialize_default(&mut &legacy[..])?;
+		assert_eq!(read.device_label, "phone");
+		assert_eq!(read.roles, 0);
+
+		let mut unknown = buf.clone();
+		unknown[version_offset] = 9;
+		assert!(deserialize_default::<AuthInfo, _>(&mut &unknown[..]).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_message_value_versions() -> Result<(), Error> {
+		let secret_key = ExpandedSecretKey::from(&SecretKey::from_bytes(&[7u8; 32])?);
+		let device_certificate =
+			DeviceCertificate::new(Pubkey::from_bytes([9u8; 32]), 100, &secret_key)?;
+		let message_value = MessageValueImpl {
+			payload: b"hello".to_vec(),
+			signature: [3u8; 64],
+			message_type: MessageType::Binary,
+			device_certificate: Some(device_certificate),
+		};
+		let mut buf = vec![];
+		serialize_default(&mut buf, &message_value)?;
+		let read: MessageValueImpl = deserialize_default(&mut &buf[..])?;
+		assert_eq!(read.payload, b"hello".to_vec());
+		assert_eq!(read.signature, [3u8; 64]);
+		assert!(matches!(read.message_type, MessageType::Binary));
+		let read_certificate = read.device_certificate.unwrap();
+		read_certificate.verify()?;
+		assert_eq!(read_certificate.timestamp, 100);
+
+		// messages saved before device certificates
+		let flag_offset = 4 + 5 + 64 + 1;
+		assert_eq!(buf[flag_offset], 1);
+		let legacy = buf[..flag_offset].to_vec();
+		let read: MessageValueImpl = deserialize_default(&mut &legacy[..])?;
+		assert_eq!(read.payload, b"hello".to_vec());
+		assert!(read.device_certificate.is_none());
+
+		let mut unknown = buf.clone();
+		unknown[flag_offset] = 9;
+		assert!(deserialize_default::<MessageValueImpl, _>(&mut &unknown[..]).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_device_revocation_timestamps() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let secret_key = ExpandedSecretKey::from(&SecretKey::from_bytes(&[7u8; 32])?);
+		let device_pubkey = Pubkey::from_bytes([9u8; 32]);
+		let certificate = DeviceCertificate::new(device_pubkey, 100, &secret_key)?;
+		assert!(ds_context.check_device_certificate(&certificate)?);
+
+		let revocation = DeviceRevocation::new(device_pubkey, 200, &secret_key)?;
+		ds_context.revoke_device(&revocation)?;
+		assert!(!ds_context.check_device_certificate(&certificate)?);
+		let user_pubkey = revocation.user_pubkey.to_bytes();
+		assert_eq!(ds_context.list_device_revocations(user_pubkey)?.len(), 1);
+
+		// the device can be linked again with a newer certificate
+		let relinked = DeviceCertificate::new(device_pubkey, 300, &secret_key)?;
+		assert!(ds_context.check_device_certificate(&relinked)?);
+
+		// an older revocation doesn't replace the newer one
+		let older = DeviceRevocation::new(device_pubkey, 50, &secret_key)?;
+		ds_context.revoke_device(&older)?;
+		assert!(!ds_context.check_device_certificate(&certificate)?);
+		let revocations = ds_context.list_device_revocations(user_pubkey)?;
+		assert_eq!(revocations.len(), 1);
+		assert_eq!(revocations[0].timestamp, 200);
+
+		// unsigned revocations are rejected
+		let mut forged = DeviceRevocation::new(device_pubkey, 400, &secret_key)?;
+		forged.timestamp = 500;
+		assert!(ds_context.revoke_device(&forged).is_err());
+		assert!(ds_context.check_device_certificate(&relinked)?);
+
+		// a certificate dated far in the future would outlive later revocations
+		let time_now = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)?
+			.as_millis();
+		let skewed = DeviceCertificate::new(device_pubkey, time_now + 1000, &secret_key)?;
+		assert!(ds_context.check_device_certificate(&skewed)?);
+		let future = time_now + CERTIFICATE_MAX_SKEW + 1000 * 60;
+		let future = DeviceCertificate::new(device_pubkey, future, &secret_key)?;
+		assert!(!ds_context.check_device_certificate(&future)?);
+		assert!(ds_context.is_device_revoked(user_pubkey, [10u8; 32], future.timestamp)?);
+		Ok(())
+	}
+
+	// add a member with the given name, joined at join_time
+	fn add_test_member(
+		ds_context: &DSContext,
+		server_pubkey: [u8; 32],
+		server_id: [u8; 8],
+		user_pubkey: [u8; 32],
+		user_name: &str,
+		join_time: u64,
+	) -> Result<(), Error> {
+		let batch = ds_context.store.batch()?;
+		ds_context.set_member(
+			Pubkey::from_bytes(user_pubkey),
+			ServerId::from_bytes(server_id),
+			Pubkey::from_bytes(server_pubkey),
+			AUTH_FLAG_MEMBER,
+			None,
+			Some(join_time),
+			None,
+			&batch,
+		)?;
+		ds_context.set_profile_impl(
+			Pubkey::from_bytes(user_pubkey),
+			Pubkey::from_bytes(server_pubkey),
+			ServerId::from_bytes(server_id),
+			ProfileData {
+				user_name: SerString::from(user_name),
+				user_bio: SerString::from(""),
+			},
+			&batch,
+		)?;
+		batch.commit()?;
+		Ok(())
+	}
+
+	fn member_names(members: &[Member]) -> Vec<String> {
+		members
+			.iter()
+			.map(|m| match &m.profile_data {
+				Some(profile_data) => profile_data.user_name.to_string(),
+				None => "".to_string(),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_get_members_pages() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		let owners = ds_context.get_members(server_pubkey, server_id, None, 0)?.0;
+		for i in 0..4u8 {
+			let name = format!("member{}", i);
+			add_test_member(
+				&ds_context,
+				server_pubkey,
+				server_id,
+				[100 + i; 32],
+				&name,
+				1000 + i as u64,
+			)?;
+		}
+
+		let (first, cursor) = ds_context.get_members(server_pubkey, server_id, None, 2)?;
+		assert_eq!(first.len(), 2);
+		let mut listed = first.clone();
+
+		// members joining while the list is paged through are listed at the end
+		for i in 4..6u8 {
+			let name = format!("member{}", i);
+			add_test_member(
+				&ds_context,
+				server_pubkey,
+				server_id,
+				[100 + i; 32],
+				&name,
+				1000 + i as u64,
+			)?;
+		}
+
+		let mut cursor = cursor;
+		while cursor.is_some() {
+			let (page, next) = ds_context.get_members(server_pubkey, server_id, cursor, 2)?;
+			assert!(page.len() <= 2);
+			listed.extend(page);
+			cursor = next;
+		}
+
+		let mut expected = member_names(&owners);
+		for i in 0..6 {
+			expected.push(format!("member{}", i));
+		}
+		assert_eq!(member_names(&listed), expected);
+
+		// the first page doesn't change
+		let (again, _) = ds_context.get_members(server_pubkey, server_id, None, 2)?;
+		assert_eq!(member_names(&again), member_names(&first));
+
+		// the limit is capped at MEMBER_BATCH_SIZE
+		let (all, cursor) = ds_context.get_members(server_pubkey, server_id, None, u64::MAX)?;
+		assert_eq!(all.len(), listed.len());
+		assert!(cursor.is_none());
+		Ok(())
+	}
+
+	#[test]
+	fn test_search_members() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		let names = ["Alice", "alfred", "ALBERT", "bob", "Carol"];
+		for (i, name) in names.iter().enumerate() {
+			add_test_member(
+				&ds_context,
+				server_pubkey,
+				server_id,
+				[100 + i as u8; 32],
+				name,
+				1000 + i as u64,
+			)?;
+		}
+
+		// prefixes match regardless of case
+		let mut found =
+			member_names(&ds_context.search_members(server_pubkey, server_id, "AL", 0)?);
+		found.sort();
+		assert_eq!(found, vec!["ALBERT", "Alice", "alfred"]);
+		let found = member_names(&ds_context.search_members(server_pubkey, server_id, "ali", 0)?);
+		assert_eq!(found, vec!["Alice"]);
+		assert!(ds_context
+			.search_members(server_pubkey, server_id, "lice", 0)?
+			.is_empty());
+		assert!(ds_context
+			.search_members(server_pubkey, server_id, "bobby", 0)?
+			.is_empty());
+
+		// at most limit members are returned
+		assert_eq!(
+			ds_context
+				.search_members(server_pubkey, server_id, "al", 2)?
+				.len(),
+			2
+		);
+		assert_eq!(
+			ds_context
+				.search_members(server_pubkey, server_id, "", 3)?
+				.len(),
+			3
+		);
+
+		// a renamed member is only found under the new name
+		let batch = ds_context.store.batch()?;
+		ds_context.set_profile_impl(
+			Pubkey::from_bytes([103u8; 32]),
+			Pubkey::from_bytes(server_pubkey),
+			ServerId::from_bytes(server_id),
+			ProfileData {
+				user_name: SerString::from("Robert"),
+				user_bio: SerString::from(""),
+			},
+			&batch,
+		)?;
+		batch.commit()?;
+		assert!(ds_context
+			.search_members(server_pubkey, server_id, "bob", 0)?
+			.is_empty());
+		let found = member_names(&ds_context.search_members(server_pubkey, server_id, "rob", 0)?);
+		assert_eq!(found, vec!["Robert"]);
+
+		// members of other servers are not found
+		assert!(ds_context
+			.search_members(server_pubkey, [0u8; 8], "al", 0)?
+			.is_empty());
+		Ok(())
+	}
+
+	#[test]
+	fn test_remove_member() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		add_test_member(
+			&ds_context,
+			server_pubkey,
+			server_id,
+			[100u8; 32],
+			"Alice",
+			1000,
+		)?;
+		let count = ds_context.member_count(server_pubkey, server_id)?;
+
+		assert!(ds_context.remove_member(server_pubkey, server_id, [100u8; 32])?);
+		assert!(!ds_context.remove_member(server_pubkey, server_id, [100u8; 32])?);
+		assert_eq!(
+			ds_context.member_count(server_pubkey, server_id)?,
+			count - 1
+		);
+		assert!(ds_context
+			.search_members(server_pubkey, server_id, "al", 0)?
+			.is_empty());
+		let (members, _) = ds_context.get_members(server_pubkey, server_id, None, 0)?;
+		assert!(members
+			.iter()
+			.all(|m| m.user_pubkey.to_bytes() != [100u8; 32]));
+
+		// the index has no entry left for the removed member
+		let batch = ds_context.store.batch()?;
+		let mut prefix = vec![MEMBER_NAME_PREFIX];
+		prefix.append(&mut server_pubkey.to_vec());
+		prefix.append(&mut server_id.to_vec());
+		let mut itt = batch.iter(&prefix, |k, _| Ok(k.to_vec()))?;
+		assert!(itt.all(|k| !k.ends_with(&[100u8; 32])));
+		drop(batch);
+
+		// the last owner can't be removed
+		let owners: Vec<&Member> = members
+			.iter()
+			.filter(|m| m.roles & AUTH_FLAG_OWNER != 0)
+			.collect();
+		assert_eq!(owners.len(), 1);
+		let owner = owners[0].user_pubkey.to_bytes();
+		assert!(!ds_context.remove_member(server_pubkey, server_id, owner)?);
+		Ok(())
+	}
+
+	#[test]
+	fn test_index_member_names() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		add_test_member(
+			&ds_context,
+			server_pubkey,
+			server_id,
+			[100u8; 32],
+			"Alice",
+			1000,
+		)?;
+
+		// profiles saved before the index existed have no entry
+		let profile_key = ProfileKey {
+			user_pubkey: Pubkey::from_bytes([100u8; 32]),
+			server_pubkey: Pubkey::from_bytes(server_pubkey),
+			server_id: ServerId::from_bytes(server_id),
+		};
+		let name_key = member_name_key(&profile_key, "Alice")?;
+		{
+			let batch = ds_context.store.batch()?;
+			batch.delete(&name_key)?;
+			batch.commit()?;
+		}
+		assert!(ds_context
+			.search_members(server_pubkey, server_id, "al", 0)?
+			.is_empty());
+
+		ds_context.index_member_names()?;
+		let found = member_names(&ds_context.search_members(server_pubkey, server_id, "al", 0)?);
+		assert_eq!(found, vec!["Alice"]);
+
+		// the migration only runs once
+		{
+			let batch = ds_context.store.batch()?;
+			batch.delete(&name_key)?;
+			batch.commit()?;
+		}
+		ds_context.index_member_names()?;
+		assert!(ds_context
+			.search_members(server_pubkey, server_id, "al", 0)?
+			.is_empty());
+		Ok(())
+	}
+
+	#[test]
+	fn test_last_owner() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		let owner = server_pubkey;
+		let co_owner = [100u8; 32];
+		add_test_member(
+			&ds_context,
+			server_pubkey,
+			server_id,
+			co_owner,
+			"co-owner",
+			1000,
+		)?;
+
+		// the only owner can't give up ownership
+		assert!(!ds_context.set_member_roles(server_pubkey, server_id, owner, AUTH_FLAG_MEMBER)?);
+		let member = ds_context
+			.get_server_member(server_pubkey, server_id, owner)?
+			.unwrap();
+		assert!(member.roles & AUTH_FLAG_OWNER != 0);
+
+		// once there is another owner it can
+		assert!(ds_context.set_member_roles(
+			server_pubkey,
+			server_id,
+			co_owner,
+			AUTH_FLAG_OWNER
+		)?);
+		assert!(ds_context.set_member_roles(
+			server_pubkey,
+			server_id,
+			owner,
+			AUTH_FLAG_MODERATOR
+		)?);
+		let member = ds_context
+			.get_server_member(server_pubkey, server_id, owner)?
+			.unwrap();
+		assert_eq!(member.roles, AUTH_FLAG_MODERATOR | AUTH_FLAG_MEMBER);
+
+		// and now the co-owner is the last owner
+		assert!(!ds_context.set_member_roles(server_pubkey, server_id, co_owner, 0)?);
+		assert!(!ds_context.remove_member(server_pubkey, server_id, co_owner)?);
+		assert!(!ds_context.set_member_roles(server_pubkey, server_id, [101u8; 32], 0)?);
+		Ok(())
+	}
+
+	#[test]
+	fn test_get_owned_servers() -> Result<(), Error> {
+		let (_dir, ds_context) = test_context()?;
+		let (server_pubkey, server_id) = test_server(&ds_context)?;
+		let (_, other_server_id) = test_server(&ds_context)?;
+		let co_owner = [100u8; 32];
+		add_test_member(
+			&ds_context,
+			server_pubkey,
+			server_id,
+			co_owner,
+			"co-owner",
+			1000,
+		)?;
+		add_test_member(
+			&ds_context,
+			server_pubkey,
+			other_server_id,
+			co_owner,
+			"co-owner",
+			1000,
+		)?;
+		assert_eq!(ds_context.get_servers()?.len(), 2);
+
+		// a member that owns none of the servers sees none of them
+		assert!(ds_context.get_owned_servers(co_owner)?.is_empty());
+
+		// a co-owner only sees the servers it owns
+		assert!(ds_context.set_member_roles(
+			server_pubkey,
+			server_id,
+			co_owner,
+			AUTH_FLAG_OWNER
+		)?);
+		let servers = ds_context.get_owned_servers(co_owner)?;
+		assert_eq!(servers.len(), 1);
+		assert_eq!(servers[0].server_id, server_id);
+		assert_eq!(ds_context.get_owned_servers(server_pubkey)?.len(), 2);
+		assert!(ds_context.get_owned_servers([101u8; 32])?.is_empty());
+		Ok(())
+	}
+}
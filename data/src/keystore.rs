@@ -0,0 +1,219 @@
+// Copyright 2022 37 Miners, LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+// Encryption of secret key material at rest. The key is derived from a passphrase with Argon2
+// and the secret is sealed with ChaCha20-Poly1305. An encrypted file is laid out as
+// magic | salt | nonce | ciphertext and is stored next to the plain file with an ".enc" suffix.
+//
+// An encrypted secret is only ever decrypted in memory, see unlock. The plain secret is never
+// written back to disk.
+
+use crate::backup;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use concorderror::{Error, ErrorKind};
+use std::path::Path;
+use zeroize::Zeroize;
+
+const MAGIC: &[u8; 4] = b"CKS1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+// path of the encrypted copy of a secret file.
+pub fn encrypted_path(path: &str) -> String {
+	format!("{}.enc", path)
+}
+
+// encrypt a secret with a key derived from the passphrase.
+pub fn encrypt(secret: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+	let salt: [u8; SALT_LEN] = rand::random();
+	let nonce: [u8; NONCE_LEN] = rand::random();
+
+	let mut key = derive_key(passphrase, &salt)?;
+	let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+	key.zeroize();
+
+	let mut ciphertext = cipher
+		.encrypt(Nonce::from_slice(&nonce), secret)
+		.map_err(|e| {
+			let error: Error = ErrorKind::KeystoreError(format!("encrypt: {}", e)).into();
+			error
+		})?;
+
+	let mut ret = MAGIC.to_vec();
+	ret.append(&mut salt.to_vec());
+	ret.append(&mut nonce.to_vec());
+	ret.append(&mut ciphertext);
+	Ok(ret)
+}
+
+// decrypt a secret encrypted by encrypt. Fails if the passphrase is wrong or the data was
+// modified.
+pub fn decrypt(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+	let header_len = MAGIC.len() + SALT_LEN + NONCE_LEN;
+	if encrypted.len() < header_len || encrypted[0..MAGIC.len()] != MAGIC[..] {
+		return Err(ErrorKind::KeystoreError("not an encrypted secret".to_string()).into());
+	}
+	let salt = &encrypted[MAGIC.len()..MAGIC.len() + SALT_LEN];
+	let nonce = &encrypted[MAGIC.len() + SALT_LEN..header_len];
+
+	let mut key = derive_key(passphrase, salt)?;
+	let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+	key.zeroize();
+
+	cipher
+		.decrypt(Nonce::from_slice(nonce), &encrypted[header_len..])
+		.map_err(|_| {
+			let error: Error =
+				ErrorKind::KeystoreError("wrong passphrase or corrupted secret".to_string()).into();
+			error
+		})
+}
+
+// encrypt the secret file at path and remove the plain file.
+pub fn encrypt_file(path: &str, passphrase: &str) -> Result<(), Error> {
+	let mut secret = std::fs::read(path)?;
+	let encrypted = encrypt(&secret, passphrase);
+	secret.zeroize();
+	std::fs::write(encrypted_path(path), encrypted?)?;
+	std::fs::remove_file(path)?;
+	Ok(())
+}
+
+// if an encrypted copy of the secret file exists, decrypt it in memory and return the expanded
+// ed25519 secret it holds. Nothing is written to disk. Returns None if there is nothing to
+// unlock.
+pub fn unlock(path: &str, passphrase: &str) -> Result<Option<[u8; 64]>, Error> {
+	let encrypted_path = encrypted_path(path);
+	if !Path::new(&encrypted_path).exists() {
+		return Ok(None);
+	}
+
+	let mut data = decrypt(&std::fs::read(&encrypted_path)?, passphrase)?;
+	let res = match backup::strip_header(&data) {
+		Ok(secret) => {
+			let mut ret = [0u8; 64];
+			ret.copy_from_slice(secret);
+			Ok(Some(ret))
+		}
+		Err(e) => Err(e),
+	};
+	data.zeroize();
+	res
+}
+
+// re-encrypt the encrypted copy of the secret file with a new passphrase.
+pub fn change_passphrase(path: &str, passphrase: &str, new_passphrase: &str) -> Result<(), Error> {
+	let encrypted_path = encrypted_path(path);
+	let mut secret = decrypt(&std::fs::read(&encrypted_path)?, passphrase)?;
+	let encrypted = encrypt(&secret, new_passphrase);
+	secret.zeroize();
+	std::fs::write(&encrypted_path, encrypted?)?;
+	Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; KEY_LEN], Error> {
+	let mut key = [0u8; KEY_LEN];
+	Argon2::default()
+		.hash_password_into(passphrase.as_bytes(), salt, &mut key)
+		.map_err(|e| {
+			let error: Error = ErrorKind::KeystoreError(format!("derive key: {}", e)).into();
+			error
+		})?;
+	Ok(key)
+}
+
+// write a file that only the current user can read.
+#[cfg(unix)]
+pub fn write_private(path: &str, data: &[u8]) -> Result<(), Error> {
+	use std::io::Write;
+	use std::os::unix::fs::OpenOptionsExt;
+
+	let mut file = std::fs::OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.mode(0o600)
+		.open(path)?;
+	file.write_all(data)?;
+	Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn write_private(path: &str, data: &[u8]) -> Result<(), Error> {
+	std::fs::write(path, data)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_encrypt_decrypt() -> Result<(), Error> {
+		let secret = [7u8; 64];
+		let encrypted = encrypt(&secret, "passphrase")?;
+		assert_eq!(&encrypted[0..MAGIC.len()], &MAGIC[..]);
+		assert_eq!(decrypt(&encrypted, "passphrase")?, secret.to_vec());
+
+		// a fresh salt and nonce are used every time
+		assert_ne!(encrypt(&secret, "passphrase")?, encrypted);
+
+		assert!(decrypt(&encrypted, "wrong passphrase").is_err());
+		assert!(decrypt(&encrypted[0..10], "passphrase").is_err());
+		assert!(decrypt(&secret, "passphrase").is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_decrypt_tampered() -> Result<(), Error> {
+		let encrypted = encrypt(&[7u8; 64], "passphrase")?;
+		let header_len = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+		// changing the salt, the nonce, the ciphertext or the tag is detected
+		for i in &[
+			MAGIC.len(),
+			MAGIC.len() + SALT_LEN,
+			header_len,
+			encrypted.len() - 1,
+		] {
+			let mut tampered = encrypted.clone();
+			tampered[*i] ^= 1;
+			assert!(decrypt(&tampered, "passphrase").is_err());
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn test_unlock() -> Result<(), Error> {
+		let dir = tempfile::tempdir()?;
+		let path = dir.path().join("secret").display().to_string();
+		assert!(unlock(&path, "passphrase")?.is_none());
+
+		std::fs::write(&path, [7u8; 64])?;
+		encrypt_file(&path, "passphrase")?;
+		assert!(!Path::new(&path).exists());
+		assert!(unlock(&path, "wrong passphrase").is_err());
+
+		change_passphrase(&path, "passphrase", "new passphrase")?;
+		assert!(unlock(&path, "passphrase").is_err());
+		assert_eq!(unlock(&path, "new passphrase")?, Some([7u8; 64]));
+
+		// the secret is only decrypted in memory
+		assert!(!Path::new(&path).exists());
+		assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+		Ok(())
+	}
+}
@@ -19,10 +19,13 @@ pub use secp256k1zkp as secp;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod backup;
 pub mod concord;
 pub mod hash;
 pub mod hex;
+pub mod keystore;
 pub mod lmdb;
+pub mod pow;
 pub mod ser;
 pub mod types;
 pub use crate::hex::*;
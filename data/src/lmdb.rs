@@ -325,6 +325,33 @@ impl Store {
 		Ok(PrefixIterator::new(tx, cursor, prefix, deserialize))
 	}
 
+	/// Produces an iterator from the provided key prefix, starting at the first
+	/// key that is greater than or equal to `start`.
+	pub fn iter_from<F, T>(
+		&self,
+		prefix: &[u8],
+		start: &[u8],
+		deserialize: F,
+	) -> Result<PrefixIterator<F, T>, Error>
+	where
+		F: Fn(&[u8], &[u8]) -> Result<T, Error>,
+	{
+		let lock = self.db.read();
+		let db = lock.as_ref().ok_or_else(|| {
+			let error: Error = ErrorKind::NotFoundErr("chain db is None".to_string()).into();
+			error
+		})?;
+		let tx = Arc::new(lmdb::ReadTransaction::new(self.env.clone())?);
+		let cursor = Arc::new(tx.cursor(db.clone())?);
+		Ok(PrefixIterator::new_from(
+			tx,
+			cursor,
+			prefix,
+			start,
+			deserialize,
+		))
+	}
+
 	/// Builds a new batch to be used with this store.
 	pub fn batch(&self) -> Result<Batch<'_>, Error> {
 		// check if the db needs resizing before returning the batch
@@ -419,6 +446,19 @@ impl<'a> Batch<'a> {
 		self.store.iter(prefix, deserialize)
 	}
 
+	/// Produces an iterator from the provided key prefix, starting at `start`.
+	pub fn iter_from<F, T>(
+		&self,
+		prefix: &[u8],
+		start: &[u8],
+		deserialize: F,
+	) -> Result<PrefixIterator<F, T>, Error>
+	where
+		F: Fn(&[u8], &[u8]) -> Result<T, Error>,
+	{
+		self.store.iter_from(prefix, start, deserialize)
+	}
+
 	/// Gets a `Readable` value from the db by provided key and default deserialization strategy.
 	pub fn get_ser<T: ser::Readable>(&self, key: &[u8]) -> Result<Option<T>, Error> {
 		self.get_with(key, |_, mut data| {
@@ -466,6 +506,7 @@ where
 	cursor: Arc<lmdb::Cursor<'static, 'static>>,
 	seek: bool,
 	prefix: Vec<u8>,
+	start: Vec<u8>,
 	deserialize: F,
 }
 
@@ -482,7 +523,7 @@ where
 			cursor.next(&access)
 		} else {
 			self.seek = true;
-			cursor.seek_range_k(&access, &self.prefix[..])
+			cursor.seek_range_k(&access, &self.start[..])
 		};
 		kv.ok()
 			.filter(|(k, _)| k.starts_with(self.prefix.as_slice()))
@@ -504,12 +545,24 @@ where
 		cursor: Arc<lmdb::Cursor<'static, 'static>>,
 		prefix: &[u8],
 		deserialize: F,
+	) -> PrefixIterator<F, T> {
+		Self::new_from(tx, cursor, prefix, prefix, deserialize)
+	}
+
+	/// Initialize a new prefix iterator which begins at the `start` key.
+	pub fn new_from(
+		tx: Arc<lmdb::ReadTransaction<'static>>,
+		cursor: Arc<lmdb::Cursor<'static, 'static>>,
+		prefix: &[u8],
+		start: &[u8],
+		deserialize: F,
 	) -> PrefixIterator<F, T> {
 		PrefixIterator {
 			tx,
 			cursor,
 			seek: false,
 			prefix: prefix.to_vec(),
+			start: start.to_vec(),
 			deserialize,
 		}
 	}
@@ -0,0 +1,149 @@
+// Copyright 2022 37 Miners, LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Proof of work for accepting invites. A solution is a nonce such that the Blake2b hash of the
+// challenge and the nonce has at least `difficulty` leading zero bits. The challenge includes
+// the invite and the user's pubkey so that a solution can't be reused by another key.
+
+use crate::hash::{DefaultHashable, Hash, Hashed};
+use crate::ser::{Writeable, Writer};
+use concorderror::Error;
+
+// the highest difficulty a server may require. Each step doubles the expected work.
+pub const MAX_POW_DIFFICULTY: u8 = 24;
+
+struct PowChallenge {
+	server_pubkey: [u8; 32],
+	invite_id: u128,
+	user_pubkey: [u8; 32],
+	nonce: u64,
+}
+
+impl Writeable for PowChallenge {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_fixed_bytes(self.server_pubkey)?;
+		writer.write_u128(self.invite_id)?;
+		writer.write_fixed_bytes(self.user_pubkey)?;
+		writer.write_u64(self.nonce)?;
+		Ok(())
+	}
+}
+
+impl DefaultHashable for PowChallenge {}
+
+fn leading_zero_bits(hash: &Hash) -> u32 {
+	let mut bits = 0;
+	for byte in hash.as_bytes() {
+		if *byte != 0 {
+			return bits + byte.leading_zeros();
+		}
+		bits += 8;
+	}
+	bits
+}
+
+// returns true if the nonce solves the challenge at the specified difficulty. A difficulty of
+// 0 accepts any nonce.
+pub fn verify(
+	server_pubkey: [u8; 32],
+	invite_id: u128,
+	user_pubkey: [u8; 32],
+	nonce: u64,
+	difficulty: u8,
+) -> bool {
+	if difficulty == 0 {
+		return true;
+	}
+
+	let challenge = PowChallenge {
+		server_pubkey,
+		invite_id,
+		user_pubkey,
+		nonce,
+	};
+	leading_zero_bits(&challenge.hash()) >= u32::from(difficulty)
+}
+
+// find the first nonce that solves the challenge at the specified difficulty.
+pub fn solve(
+	server_pubkey: [u8; 32],
+	invite_id: u128,
+	user_pubkey: [u8; 32],
+	difficulty: u8,
+) -> u64 {
+	let mut nonce = 0;
+	while !verify(server_pubkey, invite_id, user_pubkey, nonce, difficulty) {
+		nonce += 1;
+	}
+	nonce
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_solve_verify() {
+		let server_pubkey = [1u8; 32];
+		let user_pubkey = [2u8; 32];
+		let invite_id = 3;
+		for difficulty in 0..12 {
+			let nonce = solve(server_pubkey, invite_id, user_pubkey, difficulty);
+			assert!(verify(
+				server_pubkey,
+				invite_id,
+				user_pubkey,
+				nonce,
+				difficulty
+			));
+		}
+	}
+
+	#[test]
+	fn test_verify_rejects() {
+		let server_pubkey = [1u8; 32];
+		let user_pubkey = [2u8; 32];
+		let invite_id = 3;
+		let difficulty = 10;
+		let nonce = solve(server_pubkey, invite_id, user_pubkey, difficulty);
+
+		// solve returns the first solution so every smaller nonce is wrong
+		for wrong in 0..nonce {
+			assert!(!verify(
+				server_pubkey,
+				invite_id,
+				user_pubkey,
+				wrong,
+				difficulty
+			));
+		}
+
+		// a higher difficulty needs more leading zero bits than the solution has
+		let challenge = PowChallenge {
+			server_pubkey,
+			invite_id,
+			user_pubkey,
+			nonce,
+		};
+		let bits = leading_zero_bits(&challenge.hash());
+		assert!(bits >= u32::from(difficulty));
+		assert!(!verify(
+			server_pubkey,
+			invite_id,
+			user_pubkey,
+			nonce,
+			(bits + 1) as u8
+		));
+	}
+}
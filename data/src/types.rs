@@ -16,7 +16,7 @@ use crate::nioruntime_log;
 use crate::nioruntime_tor::ov3::OnionV3Address;
 use crate::ser::{chunk_read, chunk_write, Readable, Reader, Writeable, Writer};
 use concorderror::{Error, ErrorKind};
-use ed25519_dalek::PublicKey;
+use ed25519_dalek::{ExpandedSecretKey, PublicKey, Verifier};
 use nioruntime_log::*;
 use std::convert::TryInto;
 
@@ -322,6 +322,18 @@ pub struct Invite {
 	pub cur: u64,
 	pub max: u64,
 	pub id: u128,
+	// roles given to members that join through this invite
+	pub roles: u128,
+	// channel the member is given access to and lands in, 0 for none
+	pub landing_channel: u64,
+}
+
+impl Invite {
+	// an invite can be used until it expires or has been accepted max times. An expiry of 0
+	// means the invite never expires.
+	pub fn is_live(&self, time_now: u128) -> bool {
+		self.cur < self.max && (self.expiry == 0 || time_now < self.expiry)
+	}
 }
 
 impl Writeable for Invite {
@@ -337,6 +349,11 @@ impl Writeable for Invite {
 		writer.write_u64(self.max)?;
 		writer.write_u128(self.id)?;
 
+		// version of the fields that follow
+		writer.write_u8(1)?;
+		writer.write_u128(self.roles)?;
+		writer.write_u64(self.landing_channel)?;
+
 		Ok(())
 	}
 }
@@ -359,6 +376,16 @@ impl Readable for Invite {
 		let max = reader.read_u64()?;
 		let id = reader.read_u128()?;
 		info!("read invite id={}", id);
+
+		// invites saved before roles could be attached end here
+		let (roles, landing_channel) = match reader.read_u8() {
+			Ok(1) => (reader.read_u128()?, reader.read_u64()?),
+			Ok(_) => {
+				return Err(ErrorKind::CorruptedData("unknown Invite version".to_string()).into())
+			}
+			Err(_) => (0, 0),
+		};
+
 		Ok(Invite {
 			server_id,
 			inviter,
@@ -366,6 +393,8 @@ impl Readable for Invite {
 			cur,
 			max,
 			id,
+			roles,
+			landing_channel,
 		})
 	}
 }
@@ -406,6 +435,32 @@ impl From<Vec<u8>> for Image {
 	}
 }
 
+// position in a paged listing. Callers should treat the contents as opaque
+// and pass it back unchanged to retrieve the next page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageCursor {
+	pub data: Vec<u8>,
+}
+
+impl Writeable for PageCursor {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		let len = self.data.len();
+		writer.write_u64(len.try_into()?)?;
+		chunk_write(writer, &self.data)?;
+
+		Ok(())
+	}
+}
+
+impl Readable for PageCursor {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let len = reader.read_u64()?;
+		let data = chunk_read(reader, len.try_into()?)?;
+
+		Ok(Self { data })
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct ProfileData {
 	pub user_name: SerString,
@@ -445,3 +500,280 @@ impl Readable for ProfileData {
 		})
 	}
 }
+
+// tags that keep a certificate signature from being replayed as a revocation and vice versa.
+const DEVICE_CERTIFICATE_TAG: u8 = 0;
+const DEVICE_REVOCATION_TAG: u8 = 1;
+
+// build the message that the primary key of a user signs for a device key.
+fn device_message(
+	tag: u8,
+	user_pubkey: &Pubkey,
+	device_pubkey: &Pubkey,
+	timestamp: u128,
+) -> Vec<u8> {
+	let mut message = vec![tag];
+	message.append(&mut user_pubkey.to_bytes().to_vec());
+	message.append(&mut device_pubkey.to_bytes().to_vec());
+	message.append(&mut timestamp.to_be_bytes().to_vec());
+	message
+}
+
+// sign the device message with the primary key of the user.
+fn sign_device_message(
+	tag: u8,
+	device_pubkey: &Pubkey,
+	timestamp: u128,
+	secret_key: &ExpandedSecretKey,
+) -> Result<(Pubkey, Signature), Error> {
+	let user_pubkey: PublicKey = secret_key.into();
+	let user_pubkey = Pubkey::from_dalek(user_pubkey);
+	let message = device_message(tag, &user_pubkey, device_pubkey, timestamp);
+	let signature = secret_key.sign(&message, &user_pubkey.to_dalek()?).into();
+	Ok((user_pubkey, signature))
+}
+
+// a certificate in which the primary key of a user allows a device key to act as that user.
+#[derive(Debug, Clone)]
+pub struct DeviceCertificate {
+	pub user_pubkey: Pubkey,
+	pub device_pubkey: Pubkey,
+	pub timestamp: u128,
+	pub signature: Signature,
+}
+
+impl DeviceCertificate {
+	pub fn new(
+		device_pubkey: Pubkey,
+		timestamp: u128,
+		secret_key: &ExpandedSecretKey,
+	) -> Result<Self, Error> {
+		let (user_pubkey, signature) = sign_device_message(
+			DEVICE_CERTIFICATE_TAG,
+			&device_pubkey,
+			timestamp,
+			secret_key,
+		)?;
+		Ok(Self {
+			user_pubkey,
+			device_pubkey,
+			timestamp,
+			signature,
+		})
+	}
+
+	pub fn verify(&self) -> Result<(), Error> {
+		let message = device_message(
+			DEVICE_CERTIFICATE_TAG,
+			&self.user_pubkey,
+			&self.device_pubkey,
+			self.timestamp,
+		);
+		self.user_pubkey
+			.to_dalek()?
+			.verify(&message, &self.signature.to_dalek()?)?;
+		Ok(())
+	}
+}
+
+impl Writeable for DeviceCertificate {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.user_pubkey, writer)?;
+		Writeable::write(&self.device_pubkey, writer)?;
+		writer.write_u128(self.timestamp)?;
+		Writeable::write(&self.signature, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for DeviceCertificate {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let user_pubkey = Pubkey::read(reader)?;
+		let device_pubkey = Pubkey::read(reader)?;
+		let timestamp = reader.read_u128()?;
+		let signature = Signature::read(reader)?;
+
+		Ok(Self {
+			user_pubkey,
+			device_pubkey,
+			timestamp,
+			signature,
+		})
+	}
+}
+
+// a statement, signed by the primary key of a user, that a device key may no longer act as
+// that user.
+#[derive(Debug, Clone)]
+pub struct DeviceRevocation {
+	pub user_pubkey: Pubkey,
+	pub device_pubkey: Pubkey,
+	pub timestamp: u128,
+	pub signature: Signature,
+}
+
+impl DeviceRevocation {
+	pub fn new(
+		device_pubkey: Pubkey,
+		timestamp: u128,
+		secret_key: &ExpandedSecretKey,
+	) -> Result<Self, Error> {
+		let (user_pubkey, signature) =
+			sign_device_message(DEVICE_REVOCATION_TAG, &device_pubkey, timestamp, secret_key)?;
+		Ok(Self {
+			user_pubkey,
+			device_pubkey,
+			timestamp,
+			signature,
+		})
+	}
+
+	pub fn verify(&self) -> Result<(), Error> {
+		let message = device_message(
+			DEVICE_REVOCATION_TAG,
+			&self.user_pubkey,
+			&self.device_pubkey,
+			self.timestamp,
+		);
+		self.user_pubkey
+			.to_dalek()?
+			.verify(&message, &self.signature.to_dalek()?)?;
+		Ok(())
+	}
+}
+
+impl Writeable for DeviceRevocation {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		Writeable::write(&self.user_pubkey, writer)?;
+		Writeable::write(&self.device_pubkey, writer)?;
+		writer.write_u128(self.timestamp)?;
+		Writeable::write(&self.signature, writer)?;
+		Ok(())
+	}
+}
+
+impl Readable for DeviceRevocation {
+	fn read<R: Reader>(reader: &mut R) -> Result<Self, Error> {
+		let user_pubkey = Pubkey::read(reader)?;
+		let device_pubkey = Pubkey::read(reader)?;
+		let timestamp = reader.read_u128()?;
+		let signature = Signature::read(reader)?;
+
+		Ok(Self {
+			user_pubkey,
+			device_pubkey,
+			timestamp,
+			signature,
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::ser::{deserialize_default, serialize_default};
+	use ed25519_dalek::SecretKey;
+
+	#[test]
+	fn test_invite_versions() -> Result<(), Error> {
+		let invite = Invite {
+			inviter: [1u8; 32],
+			server_id: [2u8; 8],
+			expiry: 1000,
+			cur: 3,
+			max: 4,
+			id: 5,
+			roles: 1 << 5,
+			landing_channel: 6,
+		};
+		let mut buf = vec![];
+		serialize_default(&mut buf, &invite)?;
+		let read: Invite = deserialize_default(&mut &buf[..])?;
+		assert_eq!(read.inviter, [1u8; 32]);
+		assert_eq!(read.server_id, [2u8; 8]);
+		assert_eq!(read.expiry, 1000);
+		assert_eq!(read.cur, 3);
+		assert_eq!(read.max, 4);
+		assert_eq!(read.id, 5);
+		assert_eq!(read.roles, 1 << 5);
+		assert_eq!(read.landing_channel, 6);
+
+		// invites saved before roles could be attached
+		let version_offset = 8 + 32 + 16 + 8 + 8 + 16;
+		assert_eq!(buf[version_offset], 1);
+		let legacy = buf[..version_offset].to_vec();
+		let read: Invite = deserialize_default(&mut &legacy[..])?;
+		assert_eq!(read.id, 5);
+		assert_eq!(read.max, 4);
+		assert_eq!(read.roles, 0);
+		assert_eq!(read.landing_channel, 0);
+
+		let mut unknown = buf.clone();
+		unknown[version_offset] = 9;
+		assert!(deserialize_default::<Invite, _>(&mut &unknown[..]).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_device_certificate_sign_verify() -> Result<(), Error> {
+		let secret_key = ExpandedSecretKey::from(&SecretKey::from_bytes(&[7u8; 32])?);
+		let device_pubkey = Pubkey::from_bytes([9u8; 32]);
+		let certificate = DeviceCertificate::new(device_pubkey, 100, &secret_key)?;
+		certificate.verify()?;
+		let user_pubkey: PublicKey = (&secret_key).into();
+		assert_eq!(certificate.user_pubkey, Pubkey::from_dalek(user_pubkey));
+
+		let mut buf = vec![];
+		serialize_default(&mut buf, &certificate)?;
+		let read: DeviceCertificate = deserialize_default(&mut &buf[..])?;
+		read.verify()?;
+		assert_eq!(read.device_pubkey, device_pubkey);
+		assert_eq!(read.timestamp, 100);
+
+		// any change to the signed fields breaks the signature
+		let mut tampered = certificate.clone();
+		tampered.timestamp = 101;
+		assert!(tampered.verify().is_err());
+		let mut tampered = certificate.clone();
+		tampered.device_pubkey = Pubkey::from_bytes([10u8; 32]);
+		assert!(tampered.verify().is_err());
+
+		let revocation = DeviceRevocation::new(device_pubkey, 200, &secret_key)?;
+		revocation.verify()?;
+		let mut buf = vec![];
+		serialize_default(&mut buf, &revocation)?;
+		let read: DeviceRevocation = deserialize_default(&mut &buf[..])?;
+		read.verify()?;
+		let mut tampered = revocation.clone();
+		tampered.timestamp = 201;
+		assert!(tampered.verify().is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_device_signature_tags() -> Result<(), Error> {
+		let secret_key = ExpandedSecretKey::from(&SecretKey::from_bytes(&[7u8; 32])?);
+		let device_pubkey = Pubkey::from_bytes([9u8; 32]);
+
+		// a certificate can't be replayed as a revocation
+		let certificate = DeviceCertificate::new(device_pubkey, 100, &secret_key)?;
+		let replayed = DeviceRevocation {
+			user_pubkey: certificate.user_pubkey,
+			device_pubkey: certificate.device_pubkey,
+			timestamp: certificate.timestamp,
+			signature: certificate.signature.clone(),
+		};
+		assert!(replayed.verify().is_err());
+
+		// and a revocation can't be replayed as a certificate
+		let revocation = DeviceRevocation::new(device_pubkey, 100, &secret_key)?;
+		let replayed = DeviceCertificate {
+			user_pubkey: revocation.user_pubkey,
+			device_pubkey: revocation.device_pubkey,
+			timestamp: revocation.timestamp,
+			signature: revocation.signature.clone(),
+		};
+		assert!(replayed.verify().is_err());
+		Ok(())
+	}
+}
@@ -153,6 +153,12 @@ pub enum ErrorKind {
 	/// Url parse error
 	#[fail(display = "Parse Error: {}", _0)]
 	ParseError(String),
+	/// Encrypted secret storage error
+	#[fail(display = "Keystore Error: {}", _0)]
+	KeystoreError(String),
+	/// A request was not answered in time
+	#[fail(display = "Timeout Error: {}", _0)]
+	TimeoutError(String),
 }
 
 impl Display for Error {
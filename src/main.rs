@@ -14,14 +14,19 @@
 
 use clap::load_yaml;
 use clap::App;
+use clap::ArgMatches;
 use concordconfig::ConcordConfig;
-use concorderror::Error;
+use concorddata::{backup, keystore};
+use concorderror::{Error, ErrorKind};
 use concordlib::*;
 use librustlet::*;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 nioruntime_log::debug!(); // set log level to debug
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+// secret key of the onion service, relative to the root_dir
+const SECRET_FILE: &str = "tor/hidden_service/hs_ed25519_secret_key";
 
 // include build information
 pub mod built_info {
@@ -68,6 +73,9 @@ fn real_main() -> Result<(), Error> {
 	}
 	.to_string();
 
+	let hash_auth_token = args.is_present("hash_auth_token");
+	let print_auth_token = !args.is_present("no_print_auth_token");
+
 	let root_dir = args.is_present("root_dir");
 	let root_dir = match root_dir {
 		true => args.value_of("root_dir").unwrap().to_string(),
@@ -84,11 +92,63 @@ fn real_main() -> Result<(), Error> {
 	.to_string();
 	let root_dir = root_dir.replace("~", &home_dir);
 
+	// the secret key of the onion service, which may be kept encrypted with a passphrase
+	let secret_file = match args.value_of("secret_file") {
+		Some(secret_file) => secret_file.replace("~", &home_dir),
+		None => format!("{}/{}", root_dir, SECRET_FILE),
+	};
+
+	match args.subcommand() {
+		("export", Some(sub_args)) => {
+			let passphrase = read_passphrase(&args, "passphrase", "CONCORD_PASSPHRASE")?;
+			let backup_file = sub_args.value_of("file").unwrap();
+			backup::export_backup(&secret_file, &passphrase, backup_file)?;
+			println!("Exported the identity to {}", backup_file);
+			std::process::exit(0);
+		}
+		("import", Some(sub_args)) => {
+			let passphrase = read_passphrase(&args, "passphrase", "CONCORD_PASSPHRASE")?;
+			let backup_file = sub_args.value_of("file").unwrap();
+			let onion = backup::import_backup(backup_file, &passphrase, &secret_file)?;
+			println!("Imported the identity of {}", onion);
+			std::process::exit(0);
+		}
+		_ => {}
+	}
+
+	if args.is_present("encrypt_secret") {
+		let passphrase = read_passphrase(&args, "passphrase", "CONCORD_PASSPHRASE")?;
+		keystore::encrypt_file(&secret_file, &passphrase)?;
+		println!("Encrypted {}", secret_file);
+		std::process::exit(0);
+	}
+
+	if args.is_present("change_passphrase") {
+		let passphrase = read_passphrase(&args, "passphrase", "CONCORD_PASSPHRASE")?;
+		let new_passphrase = read_passphrase(&args, "new_passphrase", "CONCORD_NEW_PASSPHRASE")?;
+		keystore::change_passphrase(&secret_file, &passphrase, &new_passphrase)?;
+		println!("Changed the passphrase of {}", secret_file);
+		std::process::exit(0);
+	}
+
+	// an encrypted secret is decrypted in memory and handed to concord, it is never written
+	// to disk
+	let secret = match Path::new(&keystore::encrypted_path(&secret_file)).exists() {
+		true => {
+			let passphrase = read_passphrase(&args, "passphrase", "CONCORD_PASSPHRASE")?;
+			keystore::unlock(&secret_file, &passphrase)?
+		}
+		false => None,
+	};
+
 	let config = ConcordConfig {
 		tor_port,
 		port,
 		root_dir,
 		host,
+		hash_auth_token,
+		print_auth_token,
+		secret,
 		..Default::default()
 	};
 
@@ -110,3 +170,29 @@ fn real_main() -> Result<(), Error> {
 	concord_init(config)?; // init concord
 	Ok(())
 }
+
+// read a passphrase from the command line, the environment or, if neither is set, stdin.
+fn read_passphrase(args: &ArgMatches, arg: &str, var: &str) -> Result<String, Error> {
+	match args.value_of(arg) {
+		Some(passphrase) => return Ok(passphrase.to_string()),
+		None => {}
+	}
+	match std::env::var(var) {
+		Ok(passphrase) => return Ok(passphrase),
+		Err(_) => {}
+	}
+
+	print!("{}: ", arg);
+	std::io::stdout().flush()?;
+	let mut line = String::new();
+	std::io::stdin().read_line(&mut line)?;
+	let passphrase = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+	if passphrase.is_empty() {
+		return Err(ErrorKind::ArgumentMissingError(format!(
+			"{} must be given with --{}, {} or on stdin",
+			arg, arg, var
+		))
+		.into());
+	}
+	Ok(passphrase)
+}